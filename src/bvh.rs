@@ -1,8 +1,43 @@
 use std::cmp::Ordering;
-use rand::Rng;
+use std::time::Instant;
+use rand::{Rng, RngCore};
+use crate::sampler::Sampler;
 use crate::vec3::Vec3;
 use crate::ray::Ray;
-use crate::hitable::{HitRecord, Hitable};
+use crate::hitable::{self, HitRecord, Hitable};
+use crate::stats;
+
+/// Work done finding the surfaces hit by rays: bounding volume hierarchy nodes visited, and
+/// intersection tests against primitives (spheres and rectangles, including the faces of
+/// cuboids).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Traversal {
+    pub nodes: u64,
+    pub primitives: u64,
+}
+
+impl Traversal {
+    /// Runs `f`, returning its result along with the work done by rays traced on this thread
+    /// meanwhile.
+    pub fn measure<R, F: FnOnce() -> R>(f: F) -> (R, Traversal) {
+        let start = stats::traversal();
+        let result = f();
+        let end = stats::traversal();
+        let traversal = Traversal {
+            nodes: end.nodes.wrapping_sub(start.nodes),
+            primitives: end.primitives.wrapping_sub(start.primitives),
+        };
+        (result, traversal)
+    }
+
+    pub(crate) fn visit_node() {
+        stats::count(|stats| stats.traversal.nodes += 1);
+    }
+
+    pub(crate) fn test_primitive() {
+        stats::count(|stats| stats.traversal.primitives += 1);
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct AABB {
@@ -91,15 +126,23 @@ pub struct BvhNode {
     left: Option<Box<dyn Hitable + Send + Sync>>,
     right: Option<Box<dyn Hitable + Send + Sync>>,
     bounding_box: AABB,
+    // whether children are hitables the tree was built from, rather than other nodes
+    leaves: bool,
 }
 
 impl BvhNode {
+    pub fn from_vec(hitables: Vec<Box<dyn Hitable + Send + Sync>>, time0: f32, time1: f32, rng: &mut dyn RngCore) -> Self {
+        let start = Instant::now();
+        let node = Self::build(hitables, time0, time1, rng);
+        stats::count(|stats| stats.bvh_build_time += start.elapsed());
+        node
+    }
+
     // TODO: lots of optimisation here
     // 1. randomly choose an axis
     // 2. sort primitives
     // 3. put half in each subtree
-    pub fn from_vec(mut hitables: Vec<Box<dyn Hitable + Send + Sync>>, time0: f32, time1: f32) -> Self {
-        let mut rng = rand::thread_rng();
+    fn build(mut hitables: Vec<Box<dyn Hitable + Send + Sync>>, time0: f32, time1: f32, rng: &mut dyn RngCore) -> Self {
         let axis: usize = rng.gen_range(0, 3);
 
         hitables.sort_by(|a, b| {
@@ -109,7 +152,7 @@ impl BvhNode {
         });
 
         let size = hitables.len();
-        let (left, right): (Option<Box<dyn Hitable + Send + Sync>>, Option<Box<dyn Hitable + Send + Sync>>) = match size {
+        let (left, right) = match size {
             1 => {
                 let left_node = Some(hitables.remove(0));
                 let right_node = None;
@@ -123,8 +166,8 @@ impl BvhNode {
             size => {
                 let mid = size / 2;
                 let right = hitables.split_off(mid);
-                let left_node: Option<Box<dyn Hitable + Send + Sync>> = Some(Box::new(BvhNode::from_vec(hitables, time0, time1)));
-                let right_node: Option<Box<dyn Hitable + Send + Sync>> = Some(Box::new(BvhNode::from_vec(right, time0, time1)));
+                let left_node: Option<Box<dyn Hitable + Send + Sync>> = Some(Box::new(BvhNode::build(hitables, time0, time1, rng)));
+                let right_node: Option<Box<dyn Hitable + Send + Sync>> = Some(Box::new(BvhNode::build(right, time0, time1, rng)));
                 (left_node, right_node)
             },
         };
@@ -141,7 +184,16 @@ impl BvhNode {
 
         let bounding_box = AABB::surrounding_box(&box_left, &box_right);
 
-        Self { left, right, bounding_box }
+        Self { left, right, bounding_box, leaves: size <= 2 }
+    }
+
+    fn hit_child<'a>(&self, child: &'a (dyn Hitable + Send + Sync), r: &Ray, t_min: f32, t_max: f32,
+                     sampler: &mut dyn Sampler) -> Option<HitRecord<'a>> {
+        let mut hit = child.hit(r, t_min, t_max, sampler)?;
+        if self.leaves {
+            hit.object.get_or_insert_with(|| hitable::object_key(child));
+        }
+        Some(hit)
     }
 }
 
@@ -150,14 +202,16 @@ fn box_compare(a: &AABB, b: &AABB, axis: usize) -> Ordering {
 }
 
 impl Hitable for BvhNode {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, sampler: &mut dyn Sampler) -> Option<HitRecord<'_>> {
+        Traversal::visit_node();
         if !self.bounding_box.hit(r, t_min, t_max) {
             return None;
         }
 
         match (&self.left, &self.right) {
             (Some(left), Some(right)) => {
-                 match (left.hit(r, t_min, t_max), right.hit(r, t_min, t_max)) {
+                 match (self.hit_child(&**left, r, t_min, t_max, sampler),
+                        self.hit_child(&**right, r, t_min, t_max, sampler)) {
                     (Some(left_hit_rec), Some(right_hit_rec)) => {
                         if left_hit_rec.t < right_hit_rec.t {
                             Some(left_hit_rec)
@@ -170,8 +224,8 @@ impl Hitable for BvhNode {
                     (None, None) => None,
                 }
             },
-            (Some(left), None) => left.hit(r, t_min, t_max),
-            (None, Some(right)) => right.hit(r, t_min, t_max),
+            (Some(left), None) => self.hit_child(&**left, r, t_min, t_max, sampler),
+            (None, Some(right)) => self.hit_child(&**right, r, t_min, t_max, sampler),
             (None, None) => None,
         }
     }
@@ -1,4 +1,4 @@
-use rand::prelude::*; 
+use crate::sampler::Sampler;
 
 use crate::vec3::Vec3;
 use crate::ray::Ray;
@@ -25,6 +25,7 @@ impl Camera {
     /// * `vup` - up vector, controls rotation of camera
     /// * `vertical_fov` - vertical field of view in degrees
     /// * `aspect_ratio` - typically width / height of image size (nx / ny)
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         look_from: Vec3,
         look_at: Vec3,
@@ -52,13 +53,95 @@ impl Camera {
         Camera { origin, lower_left_corner, horizontal, vertical, u, v, lens_radius, time0, time1 }
     }
 
-    pub fn get_ray(&self, s: f32, t: f32) -> Ray {
-        let mut rng = thread_rng();
-        let ray_disc = self.lens_radius * utils::random_in_unit_disc();
-        let offset = &self.u * ray_disc[0] + &self.v * ray_disc[1];
-        let time = self.time0 + rng.gen::<f32>() * (self.time1 - self.time0);
-        Ray::new_at_time(&self.origin + &offset,
-                         &self.lower_left_corner + s * &self.horizontal + t * &self.vertical - &self.origin - &offset,
+    pub fn get_ray(&self, s: f32, t: f32, sampler: &mut dyn Sampler) -> Ray {
+        let lens_point = self.sample_lens(sampler);
+        let time = self.time(sampler.get_1d());
+        Ray::new_at_time(lens_point.clone(),
+                         &self.lower_left_corner + s * &self.horizontal + t * &self.vertical - &lens_point,
                          time)
     }
+
+    /// Time within the shutter interval for a sample `u` in [0, 1).
+    pub fn time(&self, u: f32) -> f32 {
+        self.time0 + u * (self.time1 - self.time0)
+    }
+
+    /// Connects a point in the scene to the camera, for tracing light from emitters to the
+    /// camera. Samples a point on the lens, returning it along with the film position (s, t) (as
+    /// passed to `get_ray`) that `point` is seen at, and the camera's importance for light
+    /// arriving from `point` divided by the pdf of sampling that lens point (as seen from
+    /// `point`). Returns `None` if `point` is out of view.
+    pub fn sample_importance(&self, point: &Vec3, sampler: &mut dyn Sampler) -> Option<(Vec3, (f32, f32), f32)> {
+        let lens_point = self.sample_lens(sampler);
+        let direction = point - &lens_point;
+        let (film_pos, cosine) = self.film_position(&lens_point, &direction)?;
+        let weight = 1.0 / (self.image_area() * cosine.powi(3) * direction.squared_length());
+        Some((lens_point, film_pos, weight))
+    }
+
+    /// Solid angle pdf of `get_ray` generating `ray` (for film positions chosen uniformly), given
+    /// the point on the lens it starts from.
+    pub fn pdf_direction(&self, ray: &Ray) -> f32 {
+        match self.film_position(ray.origin(), ray.direction()) {
+            Some((_, cosine)) => 1.0 / (self.image_area() * cosine.powi(3)),
+            None => 0.0,
+        }
+    }
+
+    fn sample_lens(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        let ray_disc = self.lens_radius * utils::concentric_disc(sampler.get_2d());
+        &self.origin + &(&self.u * ray_disc[0] + &self.v * ray_disc[1])
+    }
+
+    // film position seen along direction from a point on the lens, along with the cosine between
+    // direction and the viewing direction
+    fn film_position(&self, lens_point: &Vec3, direction: &Vec3) -> Option<((f32, f32), f32)> {
+        let forward = self.v.cross(&self.u);
+        let cosine = direction.dot(&forward) / direction.length();
+        if cosine <= 0.0 {
+            return None;
+        }
+
+        // every direction from the lens to a point on the plane of focus is focused onto it
+        let focus_dist = (&self.lower_left_corner - &self.origin).dot(&forward);
+        let focus_point = lens_point + &((focus_dist / (cosine * direction.length())) * direction);
+        let offset = focus_point - &self.lower_left_corner;
+        let s = offset.dot(&self.horizontal) / self.horizontal.squared_length();
+        let t = offset.dot(&self.vertical) / self.vertical.squared_length();
+        if !(0.0..=1.0).contains(&s) || !(0.0..=1.0).contains(&t) {
+            return None;
+        }
+        Some(((s, t), cosine))
+    }
+
+    // area of the film, scaled to lie at unit distance from the lens
+    fn image_area(&self) -> f32 {
+        let forward = self.v.cross(&self.u);
+        let focus_dist = (&self.lower_left_corner - &self.origin).dot(&forward);
+        self.horizontal.length() * self.vertical.length() / (focus_dist * focus_dist)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::SamplerType;
+
+    #[test]
+    fn test_sample_importance_finds_film_position() {
+        let camera = Camera::new(Vec3::new(1.0, 2.0, 3.0), Vec3::zeros(), Vec3::new(0.0, 1.0, 0.0), 40.0, 1.5, 0.2, 3.0,
+                                 0.0, 1.0);
+        let mut sampler = SamplerType::Independent.new_sampler(0, 1);
+        sampler.start_sample(0, 0);
+        for &(s, t) in &[(0.5, 0.5), (0.1, 0.8), (0.95, 0.05)] {
+            // points on the plane of focus are seen at the same film position from anywhere on the lens
+            let r = camera.get_ray(s, t, &mut *sampler);
+            let point = r.point_at_parameter(1.0);
+            let (_, film_pos, weight) = camera.sample_importance(&point, &mut *sampler).unwrap();
+            assert!((film_pos.0 - s).abs() < 1e-4 && (film_pos.1 - t).abs() < 1e-4, "{:?} isn't {:?}", film_pos, (s, t));
+            assert!(weight > 0.0);
+            assert!(camera.pdf_direction(&r) > 0.0);
+        }
+        assert!(camera.sample_importance(&Vec3::new(2.0, 4.0, 6.0), &mut *sampler).is_none());
+    }
 }
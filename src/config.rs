@@ -1,13 +1,55 @@
 use std::path::Path;
+use std::time::Duration;
 
 use clap::{Arg, App};
 
+use crate::integrator::Integrator;
+use crate::sampler::SamplerType;
+use crate::render::TileOrder;
+use crate::filter::FilterType;
+use crate::output::{OutputFormat, PixelType};
+use crate::tonemap::{ToneMapper, ToneMapping};
+use crate::render::Aov;
+
 pub struct Config {
     width: u32,
     height: u32,
     samples: u32,
+    seed: u64,
+    sampler: SamplerType,
+    filter: FilterType,
+    filter_radius: Option<f32>,
+    noise_threshold: Option<f32>,
+    max_samples: u32,
+    median_of_means: Option<u32>,
+    heatmap: Option<String>,
+    pass_samples: Option<u32>,
+    checkpoint: Option<String>,
+    checkpoint_interval: u64,
+    resume: Option<String>,
+    preview: Option<String>,
+    preview_interval: u64,
+    tile_size: u32,
+    tile_order: TileOrder,
+    max_depth: usize,
+    min_depth: usize,
+    indirect_clamp: Option<f32>,
     output: String,
+    format: OutputFormat,
+    half: bool,
+    tone_mapper: ToneMapper,
+    exposure: f32,
+    white_point: Option<f32>,
+    aovs: Vec<Aov>,
+    denoise: bool,
     inline: bool,
+    integrator: Integrator,
+    spectral: bool,
+    caustic_photons: Option<usize>,
+    caustic_radius: Option<f32>,
+    path_guiding: bool,
+    ao_radius: Option<f32>,
+    stats_json: Option<String>,
 }
 
 impl<'a> Config {
@@ -23,14 +65,161 @@ impl<'a> Config {
         self.samples
     }
 
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn sampler(&self) -> SamplerType {
+        self.sampler
+    }
+
+    pub fn filter(&self) -> FilterType {
+        self.filter
+    }
+
+    /// Radius of the reconstruction filter, which defaults to one suited to the filter type.
+    pub fn filter_radius(&self) -> f32 {
+        self.filter_radius.unwrap_or_else(|| self.filter.default_radius())
+    }
+
+    /// Relative noise threshold for adaptive sampling, which is disabled if this is `None`.
+    pub fn noise_threshold(&self) -> Option<f32> {
+        self.noise_threshold
+    }
+
+    pub fn max_samples(&self) -> u32 {
+        self.max_samples
+    }
+
+    /// Number of batches for median of means pixel estimation, which is disabled (in favour of
+    /// the mean) if this is `None`.
+    pub fn median_of_means(&self) -> Option<u32> {
+        self.median_of_means
+    }
+
+    /// Output path of sample count heatmap, if one should be written.
+    pub fn heatmap(&'a self) -> Option<&'a Path> {
+        self.heatmap.as_ref().map(Path::new)
+    }
+
+    pub fn pass_samples(&self) -> Option<u32> {
+        self.pass_samples
+    }
+
+    /// Path to periodically save checkpoints to, which defaults to the checkpoint being resumed
+    /// from (if any).
+    pub fn checkpoint(&'a self) -> Option<&'a Path> {
+        self.checkpoint.as_ref().or(self.resume.as_ref()).map(Path::new)
+    }
+
+    pub fn checkpoint_interval(&self) -> Duration {
+        Duration::from_secs(self.checkpoint_interval)
+    }
+
+    /// Checkpoint to continue rendering from, if any.
+    pub fn resume(&'a self) -> Option<&'a Path> {
+        self.resume.as_ref().map(Path::new)
+    }
+
+    /// Path to write the image to while it's rendering, if any.
+    pub fn preview(&'a self) -> Option<&'a Path> {
+        self.preview.as_ref().map(Path::new)
+    }
+
+    pub fn preview_interval(&self) -> Duration {
+        Duration::from_secs(self.preview_interval)
+    }
+
+    pub fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+
+    pub fn tile_order(&self) -> TileOrder {
+        self.tile_order
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    pub fn min_depth(&self) -> usize {
+        self.min_depth
+    }
+
+    /// Maximum brightness of indirect light, which is unclamped if this is `None`.
+    pub fn indirect_clamp(&self) -> Option<f32> {
+        self.indirect_clamp
+    }
+
     pub fn output(&'a self) -> &'a Path {
         Path::new(&self.output)
     }
 
+    /// Format of the output image, given explicitly or implied by the output path's extension.
+    pub fn format(&self) -> OutputFormat {
+        self.format
+    }
+
+    /// Type of channels in EXR output.
+    pub fn exr_pixel_type(&self) -> PixelType {
+        if self.half {
+            PixelType::Half
+        } else {
+            PixelType::Float
+        }
+    }
+
+    /// Conversion of radiance to 8-bit output.
+    pub fn tone_mapping(&self) -> ToneMapping {
+        let tone_mapping = ToneMapping::new(self.tone_mapper).with_exposure(self.exposure);
+        match self.white_point {
+            Some(white_point) => tone_mapping.with_white_point(white_point),
+            None => tone_mapping,
+        }
+    }
+
+    /// AOVs to output alongside the rendered image.
+    pub fn aovs(&self) -> &[Aov] {
+        &self.aovs
+    }
+
+    pub fn denoise(&self) -> bool {
+        self.denoise
+    }
+
     pub fn inline(&self) -> bool {
         self.inline
     }
 
+    pub fn integrator(&self) -> Integrator {
+        self.integrator
+    }
+
+    pub fn spectral(&self) -> bool {
+        self.spectral
+    }
+
+    pub fn caustic_photons(&self) -> Option<usize> {
+        self.caustic_photons
+    }
+
+    pub fn caustic_radius(&self) -> Option<f32> {
+        self.caustic_radius
+    }
+
+    pub fn path_guiding(&self) -> bool {
+        self.path_guiding
+    }
+
+    pub fn ao_radius(&self) -> Option<f32> {
+        self.ao_radius
+    }
+
+    /// Output path of render statistics as JSON, if they should be written.
+    pub fn stats_json(&'a self) -> Option<&'a Path> {
+        self.stats_json.as_ref().map(Path::new)
+    }
+
     pub fn from_cli_args() -> Self {
         let matches = App::new("raytracer")
             .arg(Arg::with_name("width")
@@ -48,22 +237,238 @@ impl<'a> Config {
                .value_name("N")
                .help("Set number of samples per pixel")
                .takes_value(true))
+            .arg(Arg::with_name("seed")
+               .long("seed")
+               .value_name("SEED")
+               .help("Set seed for random number generation, renders with the same seed are identical")
+               .takes_value(true))
+            .arg(Arg::with_name("sampler")
+               .long("sampler")
+               .value_name("SAMPLER")
+               .help("Set sampler used to generate pixel, lens and scattering samples")
+               .possible_values(SamplerType::NAMES)
+               .takes_value(true))
+            .arg(Arg::with_name("filter")
+               .long("filter")
+               .value_name("FILTER")
+               .help("Set filter used to reconstruct pixels from nearby samples")
+               .possible_values(FilterType::NAMES)
+               .takes_value(true))
+            .arg(Arg::with_name("filter-radius")
+               .long("filter-radius")
+               .value_name("R")
+               .help("Set radius of reconstruction filter in pixels")
+               .takes_value(true))
+            .arg(Arg::with_name("noise-threshold")
+               .long("noise-threshold")
+               .value_name("T")
+               .help("Enable adaptive sampling, sampling pixels until their relative noise is below T")
+               .takes_value(true))
+            .arg(Arg::with_name("max-samples")
+               .long("max-samples")
+               .value_name("N")
+               .help("Set maximum number of samples per pixel when adaptive sampling")
+               .takes_value(true))
+            .arg(Arg::with_name("median-of-means")
+               .long("median-of-means")
+               .value_name("BATCHES")
+               .help("Estimate pixels by the median of the means of BATCHES batches of samples (at least 1), to suppress fireflies")
+               .takes_value(true))
+            .arg(Arg::with_name("heatmap")
+               .long("heatmap")
+               .value_name("HEATMAP")
+               .help("Write image of the number of samples taken per pixel to HEATMAP")
+               .takes_value(true))
+            .arg(Arg::with_name("pass-samples")
+               .long("pass-samples")
+               .value_name("N")
+               .help("Set number of samples per pixel added in each progressive rendering pass, between which checkpoints are saved (default: all samples in one pass, or with --guide, 1 doubling each pass)")
+               .takes_value(true))
+            .arg(Arg::with_name("checkpoint")
+               .long("checkpoint")
+               .value_name("CHECKPOINT")
+               .help("Periodically save accumulated samples to CHECKPOINT, between passes (see --pass-samples) and when finished")
+               .takes_value(true))
+            .arg(Arg::with_name("checkpoint-interval")
+               .long("checkpoint-interval")
+               .value_name("SECS")
+               .help("Set minimum number of seconds between checkpoints")
+               .takes_value(true))
+            .arg(Arg::with_name("resume")
+               .long("resume")
+               .value_name("CHECKPOINT")
+               .help("Continue adding samples to the render saved in CHECKPOINT")
+               .takes_value(true))
+            .arg(Arg::with_name("preview")
+               .long("preview")
+               .value_name("PREVIEW")
+               .help("Write the image in progress to PREVIEW as tiles finish (in the output format)")
+               .takes_value(true))
+            .arg(Arg::with_name("preview-interval")
+               .long("preview-interval")
+               .value_name("SECS")
+               .help("Set minimum number of seconds between preview writes")
+               .takes_value(true))
+            .arg(Arg::with_name("tile-size")
+               .long("tile-size")
+               .value_name("N")
+               .help("Set width and height in pixels of the tiles rendered in parallel")
+               .takes_value(true))
+            .arg(Arg::with_name("tile-order")
+               .long("tile-order")
+               .value_name("ORDER")
+               .help("Set order in which tiles are rendered")
+               .possible_values(TileOrder::NAMES)
+               .takes_value(true))
+            .arg(Arg::with_name("max-depth")
+               .long("max-depth")
+               .value_name("N")
+               .help("Set maximum number of bounces per path")
+               .takes_value(true))
+            .arg(Arg::with_name("min-depth")
+               .long("min-depth")
+               .value_name("N")
+               .help("Set number of bounces before paths may be terminated by Russian roulette")
+               .takes_value(true))
+            .arg(Arg::with_name("clamp-indirect")
+               .long("clamp-indirect")
+               .value_name("MAX")
+               .help("Clamp indirect light from each sample to MAX, to suppress fireflies")
+               .takes_value(true))
             .arg(Arg::with_name("output")
                .long("output")
                .value_name("OUTPUT")
                .help("Set output path of generated image")
                .takes_value(true))
+            .arg(Arg::with_name("format")
+               .long("format")
+               .value_name("FORMAT")
+               .help("Set format of generated image, by default implied by the output extension (or png)")
+               .possible_values(OutputFormat::NAMES)
+               .takes_value(true))
+            .arg(Arg::with_name("half")
+               .long("half")
+               .help("Write EXR channels as 16-bit rather than 32-bit floats"))
+            .arg(Arg::with_name("tonemap")
+               .long("tonemap")
+               .value_name("TONEMAP")
+               .help("Set tone mapping operator used for 8-bit output")
+               .possible_values(ToneMapper::NAMES)
+               .takes_value(true))
+            .arg(Arg::with_name("exposure")
+               .long("exposure")
+               .value_name("STOPS")
+               .help("Set exposure adjustment for 8-bit output, in stops")
+               .allow_hyphen_values(true)
+               .takes_value(true))
+            .arg(Arg::with_name("white-point")
+               .long("white-point")
+               .value_name("L")
+               .help("Set luminance mapped to white by the extended-reinhard and hable tone mappers")
+               .takes_value(true))
+            .arg(Arg::with_name("aov")
+               .long("aov")
+               .value_name("AOV")
+               .help("Output an AOV, as layers of EXR output or otherwise as a separate image alongside it")
+               .possible_values(Aov::NAMES)
+               .multiple(true)
+               .number_of_values(1)
+               .use_delimiter(true)
+               .takes_value(true))
+            .arg(Arg::with_name("denoise")
+               .long("denoise")
+               .help("Denoise the rendered image, guided by albedo and normals of the first surfaces hit"))
             .arg(Arg::with_name("inline")
                .long("inline")
                .help("Output image inline (for use with iTerm2)"))
+            .arg(Arg::with_name("integrator")
+               .long("integrator")
+               .value_name("INTEGRATOR")
+               .help("Set integrator used to compute radiance, or a debug view of the scene's geometry (normals, uv, distance, ao, bvh-nodes, primitive-tests)")
+               .possible_values(Integrator::NAMES)
+               .takes_value(true))
+            .arg(Arg::with_name("spectral")
+               .long("spectral")
+               .help("Trace light at sampled wavelengths rather than as RGB, so that glass disperses light"))
+            .arg(Arg::with_name("caustic-photons")
+               .long("caustic-photons")
+               .value_name("N")
+               .help("Emit N photons from lights before rendering, and estimate caustics from those reaching diffuse surfaces through glass or metal")
+               .takes_value(true))
+            .arg(Arg::with_name("caustic-radius")
+               .long("caustic-radius")
+               .value_name("R")
+               .help("Set maximum distance caustic photons are gathered from (default: 1% of the scene's size)")
+               .takes_value(true))
+            .arg(Arg::with_name("guide")
+               .long("guide")
+               .help("Learn which directions light arrives from while rendering, and guide paths towards them"))
+            .arg(Arg::with_name("ao-radius")
+               .long("ao-radius")
+               .value_name("R")
+               .help("Set distance within which surfaces occlude each other for the ao integrator (default: 10% of the scene's size)")
+               .takes_value(true))
+            .arg(Arg::with_name("stats-json")
+               .long("stats-json")
+               .value_name("PATH")
+               .help("Write render statistics to PATH as JSON")
+               .takes_value(true))
         .get_matches();
 
         let width = matches.value_of("width").unwrap_or("200").parse().unwrap();
         let height = matches.value_of("height").unwrap_or("100").parse().unwrap();
         let samples = matches.value_of("samples").unwrap_or("10").parse().unwrap();
-        let output = matches.value_of("output").unwrap_or("./raytracer.png").to_owned();
+        let seed = matches.value_of("seed").unwrap_or("0").parse().unwrap();
+        let sampler = matches.value_of("sampler").unwrap_or("sobol").parse().unwrap();
+        let filter = matches.value_of("filter").unwrap_or("box").parse().unwrap();
+        let filter_radius = matches.value_of("filter-radius").map(|r| r.parse().unwrap());
+        let noise_threshold = matches.value_of("noise-threshold").map(|t| t.parse().unwrap());
+        let max_samples = matches.value_of("max-samples").unwrap_or("1024").parse().unwrap();
+        let median_of_means = matches.value_of("median-of-means").map(|b| b.parse().unwrap());
+        let heatmap = matches.value_of("heatmap").map(|h| h.to_owned());
+        let pass_samples = matches.value_of("pass-samples").map(|n| n.parse().unwrap());
+        let checkpoint = matches.value_of("checkpoint").map(|c| c.to_owned());
+        let checkpoint_interval = matches.value_of("checkpoint-interval").unwrap_or("300").parse().unwrap();
+        let resume = matches.value_of("resume").map(|r| r.to_owned());
+        let preview = matches.value_of("preview").map(|p| p.to_owned());
+        let preview_interval = matches.value_of("preview-interval").unwrap_or("5").parse().unwrap();
+        let tile_size = matches.value_of("tile-size").unwrap_or("16").parse().unwrap();
+        let tile_order = matches.value_of("tile-order").unwrap_or("spiral").parse().unwrap();
+        let max_depth = matches.value_of("max-depth").unwrap_or("50").parse().unwrap();
+        let min_depth = matches.value_of("min-depth").unwrap_or("3").parse().unwrap();
+        let indirect_clamp = matches.value_of("clamp-indirect").map(|c| c.parse().unwrap());
+        let format: Option<OutputFormat> = matches.value_of("format").map(|f| f.parse().unwrap());
+        let output = match matches.value_of("output") {
+            Some(output) => output.to_owned(),
+            None => format!("./raytracer.{}", format.unwrap_or_default().extension()),
+        };
+        let format = format.or_else(|| OutputFormat::from_path(&output)).unwrap_or_default();
+        let half = matches.occurrences_of("half") > 0;
+        let tone_mapper = matches.value_of("tonemap").unwrap_or("clamp").parse().unwrap();
+        let exposure = matches.value_of("exposure").unwrap_or("0").parse().unwrap();
+        let white_point = matches.value_of("white-point").map(|w| w.parse().unwrap());
+        let mut aovs = Vec::new();
+        for aov in matches.values_of("aov").into_iter().flatten() {
+            let aov = aov.parse().unwrap();
+            if !aovs.contains(&aov) {
+                aovs.push(aov);
+            }
+        }
+        let denoise = matches.occurrences_of("denoise") > 0;
         let inline = matches.occurrences_of("inline") > 0;
+        let integrator = matches.value_of("integrator").unwrap_or("path").parse().unwrap();
+        let spectral = matches.occurrences_of("spectral") > 0;
+        let caustic_photons = matches.value_of("caustic-photons").map(|n| n.parse().unwrap());
+        let caustic_radius = matches.value_of("caustic-radius").map(|r| r.parse().unwrap());
+        let path_guiding = matches.occurrences_of("guide") > 0;
+        let ao_radius = matches.value_of("ao-radius").map(|r| r.parse().unwrap());
+        let stats_json = matches.value_of("stats-json").map(|s| s.to_owned());
 
-        Self { width, height, samples, output, inline }
+        Self { width, height, samples, seed, sampler, filter, filter_radius, noise_threshold, max_samples,
+               median_of_means, heatmap, pass_samples, checkpoint, checkpoint_interval, resume, preview,
+               preview_interval, tile_size, tile_order, max_depth, min_depth,
+               indirect_clamp, output, format, half,
+               tone_mapper, exposure, white_point, aovs, denoise, inline, integrator, spectral,
+               caustic_photons, caustic_radius, path_guiding, ao_radius, stats_json }
     }
 }
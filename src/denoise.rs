@@ -0,0 +1,228 @@
+//! Removing Monte Carlo noise from rendered images, using an edge-avoiding à-trous wavelet filter
+//! (Dammertz et al. 2010, with SVGF's edge stopping functions). Each pass blurs with a 5x5 kernel
+//! whose taps are spread further apart, with neighbours weighted by how similar their normals,
+//! depths, albedos and colours are, so that noise is smoothed out but geometry and texture edges
+//! are kept.
+
+use rayon::prelude::*;
+
+use crate::vec3::Vec3;
+use crate::render::{Aov, AovImage, Framebuffer};
+
+// B3 spline, the 1D kernel of each pass
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// Denoiser for rendered images, guided by auxiliary buffers of the first surfaces seen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Denoiser {
+    iterations: u32,
+    sigma_colour: f32,
+    sigma_albedo: f32,
+    sigma_depth: f32,
+    normal_exponent: f32,
+}
+
+impl Denoiser {
+    /// AOVs needed to guide the denoiser.
+    pub const GUIDES: &'static [Aov] = &[Aov::Albedo, Aov::Normal, Aov::Depth];
+
+    pub fn new() -> Self {
+        Self { iterations: 5, sigma_colour: 2.0, sigma_albedo: 0.1, sigma_depth: 1.0, normal_exponent: 64.0 }
+    }
+
+    /// Set number of filter passes, with each doubling the filter's width (5 by default, for a
+    /// filter 125 pixels wide).
+    pub fn with_iterations(mut self, iterations: u32) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    /// Set how much colours may differ, in standard deviations of the noise, before neighbours are
+    /// ignored. Larger values give smoother images but blur lighting details.
+    pub fn with_sigma_colour(mut self, sigma_colour: f32) -> Self {
+        self.sigma_colour = sigma_colour;
+        self
+    }
+
+    /// Denoises a rendered image, given the `GUIDES` AOVs rendered for it.
+    pub fn denoise(&self, fb: &Framebuffer, aovs: &AovImage) -> Framebuffer {
+        assert!(aovs.width() == fb.width() && aovs.height() == fb.height(), "AOVs are a different size to the image");
+        let guide = |aov| aovs.get(aov).unwrap_or_else(|| panic!("denoising needs the {} AOV", aov));
+
+        let pixels = self.filter(fb.width(), fb.height(), fb.pixels(), guide(Aov::Albedo), guide(Aov::Normal),
+                                 guide(Aov::Depth));
+        let mut denoised = fb.clone();
+        for (idx, col) in pixels.into_iter().enumerate() {
+            denoised.set(idx as u32 % fb.width(), idx as u32 / fb.width(), col);
+        }
+        denoised
+    }
+
+    fn filter(&self, width: u32, height: u32, colour: &[Vec3], albedo: &[Vec3], normal: &[Vec3],
+              depth: &[Vec3]) -> Vec<Vec3> {
+        let (w, h) = (width as usize, height as usize);
+        let depth: Vec<f32> = depth.iter().map(|d| d[0]).collect();
+        let depth_gradient = gradient(w, h, &depth);
+
+        // filtering the light reaching surfaces rather than the light leaving them keeps texture
+        // detail, which is restored afterwards
+        let mut irradiance: Vec<Vec3> = colour.iter().zip(albedo).map(|(c, a)| demodulate(c, a, |c, a| c / a)).collect();
+        let mut variance = spatial_variance(w, h, &irradiance);
+
+        for i in 0..self.iterations {
+            let step = 1 << i;
+            let (next_irradiance, next_variance): (Vec<Vec3>, Vec<f32>) = (0..w * h).into_par_iter()
+                .map(|p| {
+                    let (x, y) = ((p % w) as i64, (p / w) as i64);
+                    let lum_p = irradiance[p].luminance();
+                    let lum_scale = self.sigma_colour * variance[p].max(0.0).sqrt() + 1e-4;
+
+                    let mut sum = Vec3::zeros();
+                    let mut var_sum = 0.0;
+                    let mut weight_sum = 0.0;
+                    for (ky, &hy) in KERNEL.iter().enumerate() {
+                        for (kx, &hx) in KERNEL.iter().enumerate() {
+                            let dx = (kx as i64 - 2) * step;
+                            let dy = (ky as i64 - 2) * step;
+                            let (qx, qy) = (x + dx, y + dy);
+                            if qx < 0 || qy < 0 || qx >= w as i64 || qy >= h as i64 {
+                                continue;
+                            }
+                            let q = qy as usize * w + qx as usize;
+
+                            let w_depth = if depth[p].is_finite() && depth[q].is_finite() {
+                                let expected = depth_gradient[p] * ((dx * dx + dy * dy) as f32).sqrt();
+                                (-(depth[p] - depth[q]).abs() / (self.sigma_depth * expected + 1e-4)).exp()
+                            } else if depth[p].is_finite() == depth[q].is_finite() {
+                                1.0
+                            } else {
+                                0.0
+                            };
+                            // the background has no normals, so only depth tells it apart
+                            let w_normal = if normal[p].squared_length() > 0.0 && normal[q].squared_length() > 0.0 {
+                                normal[p].dot(&normal[q]).max(0.0).powf(self.normal_exponent)
+                            } else {
+                                1.0
+                            };
+                            let w_albedo = (-(&albedo[p] - &albedo[q]).squared_length()
+                                / (self.sigma_albedo * self.sigma_albedo)).exp();
+                            let w_colour = (-(lum_p - irradiance[q].luminance()).abs() / lum_scale).exp();
+
+                            let weight = hx * hy * w_depth * w_normal * w_albedo * w_colour;
+                            sum += &(weight * &irradiance[q]);
+                            var_sum += weight * weight * variance[q];
+                            weight_sum += weight;
+                        }
+                    }
+                    // the centre tap always has a weight of at least 9/64
+                    (sum / weight_sum, var_sum / (weight_sum * weight_sum))
+                })
+                .unzip();
+            irradiance = next_irradiance;
+            variance = next_variance;
+        }
+
+        irradiance.iter().zip(albedo).map(|(c, a)| demodulate(c, a, |c, a| c * a)).collect()
+    }
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// applies `f` to each channel of a colour and albedo, leaving channels with little albedo (such as
+// emitters, or the background) unchanged
+fn demodulate<F: Fn(f32, f32) -> f32>(col: &Vec3, albedo: &Vec3, f: F) -> Vec3 {
+    let mut out = col.clone();
+    for k in 0..3 {
+        if albedo[k] > 0.01 {
+            out[k] = f(col[k], albedo[k]);
+        }
+    }
+    out
+}
+
+// screen space depth gradient, for judging whether depths differ by more than the surface's slope
+// explains. Along each axis the smaller of the differences to either side is used, so that the
+// gradient isn't thrown off at the edges of objects.
+fn gradient(w: usize, h: usize, depth: &[f32]) -> Vec<f32> {
+    let diff = |p: usize, q: Option<usize>| q.map(|q| (depth[q] - depth[p]).abs()).filter(|d| d.is_finite());
+    (0..w * h).map(|p| {
+        let (x, y) = (p % w, p / w);
+        let mut gradient2 = 0.0;
+        for &(prev, next) in &[(x.checked_sub(1).map(|_| p - 1), Some(p + 1).filter(|_| x + 1 < w)),
+                               (y.checked_sub(1).map(|_| p - w), Some(p + w).filter(|_| y + 1 < h))] {
+            let d = match (diff(p, prev), diff(p, next)) {
+                (Some(a), Some(b)) => a.min(b),
+                (Some(d), None) | (None, Some(d)) => d,
+                (None, None) => 0.0,
+            };
+            gradient2 += d * d;
+        }
+        gradient2.sqrt()
+    }).collect()
+}
+
+// variance of luminance over each pixel's 3x3 neighbourhood, as an estimate of its noise
+fn spatial_variance(w: usize, h: usize, colour: &[Vec3]) -> Vec<f32> {
+    (0..w * h).map(|p| {
+        let (x, y) = ((p % w) as i64, (p / w) as i64);
+        let (mut sum, mut sum2, mut n) = (0.0, 0.0, 0.0);
+        for qy in (y - 1).max(0)..(y + 2).min(h as i64) {
+            for qx in (x - 1).max(0)..(x + 2).min(w as i64) {
+                let lum = colour[qy as usize * w + qx as usize].luminance();
+                sum += lum;
+                sum2 += lum * lum;
+                n += 1.0;
+            }
+        }
+        let mean = sum / n;
+        (sum2 / n - mean * mean).max(0.0)
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_pcg::Pcg64Mcg;
+
+    // noisy image of two flat surfaces with different albedos, split down the middle
+    fn noisy_image(w: usize, h: usize) -> (Vec<Vec3>, Vec<Vec3>, Vec<Vec3>, Vec<Vec3>) {
+        let mut rng = Pcg64Mcg::seed_from_u64(1);
+        let albedo: Vec<Vec3> = (0..w * h)
+            .map(|p| if p % w < w / 2 { Vec3::new(0.8, 0.2, 0.2) } else { Vec3::new(0.2, 0.2, 0.8) })
+            .collect();
+        let colour = albedo.iter().map(|a| rng.gen_range(0.0, 2.0) * a).collect();
+        let normal = vec![Vec3::new(0.0, 0.0, 1.0); w * h];
+        let depth = vec![Vec3::new(5.0, 0.0, 0.0); w * h];
+        (colour, albedo, normal, depth)
+    }
+
+    fn mean_and_variance(values: &[f32]) -> (f32, f32) {
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        let variance = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / values.len() as f32;
+        (mean, variance)
+    }
+
+    #[test]
+    fn test_denoise_removes_noise_but_keeps_edges() {
+        let (w, h) = (32, 32);
+        let (colour, albedo, normal, depth) = noisy_image(w, h);
+        let denoised = Denoiser::new().filter(w as u32, h as u32, &colour, &albedo, &normal, &depth);
+
+        let left = |pixels: &[Vec3], k| -> Vec<f32> {
+            pixels.iter().enumerate().filter(|(p, _)| p % w < w / 2).map(|(_, c)| c[k]).collect()
+        };
+        let (noisy_mean, noisy_var) = mean_and_variance(&left(&colour, 0));
+        let (mean, var) = mean_and_variance(&left(&denoised, 0));
+        assert!((mean - noisy_mean).abs() < 0.05 * noisy_mean, "mean changed from {} to {}", noisy_mean, mean);
+        assert!(var < 0.1 * noisy_var, "variance only reduced from {} to {}", noisy_var, var);
+
+        // the blue half shouldn't bleed into the red
+        let (blue, _) = mean_and_variance(&left(&denoised, 2));
+        assert!((blue - 0.2).abs() < 0.05, "blue is {}", blue);
+    }
+}
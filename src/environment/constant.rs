@@ -0,0 +1,20 @@
+use crate::vec3::Vec3;
+use crate::environment::Environment;
+
+/// Same radiance arriving from every direction.
+#[derive(Clone)]
+pub struct Constant {
+    colour: Vec3,
+}
+
+impl Constant {
+    pub fn from_rgb(r: f32, g: f32, b: f32) -> Self {
+        Self { colour: Vec3::new(r, g, b) }
+    }
+}
+
+impl Environment for Constant {
+    fn value(&self, _direction: &Vec3) -> Vec3 {
+        self.colour.clone()
+    }
+}
@@ -0,0 +1,135 @@
+/// Piecewise constant distribution over [0, 1), proportional to a list of function values.
+#[derive(Clone)]
+pub struct Distribution1D {
+    func: Vec<f32>,
+    cdf: Vec<f32>,
+    func_int: f32,
+}
+
+impl Distribution1D {
+    pub fn new(func: Vec<f32>) -> Self {
+        let n = func.len();
+        let mut cdf = vec![0.0; n + 1];
+        for i in 1..=n {
+            cdf[i] = cdf[i - 1] + func[i - 1].abs() / n as f32;
+        }
+
+        let func_int = cdf[n];
+        for (i, c) in cdf.iter_mut().enumerate() {
+            // fall back to uniform if function is zero everywhere
+            *c = if func_int > 0.0 {
+                *c / func_int
+            } else {
+                i as f32 / n as f32
+            };
+        }
+
+        Self { func, cdf, func_int }
+    }
+
+    /// Integral of the function over [0, 1).
+    pub fn integral(&self) -> f32 {
+        self.func_int
+    }
+
+    /// Maps `u` uniform in [0, 1) to a sample from the distribution, returning the sample, its
+    /// pdf and the index of the segment it lies in.
+    pub fn sample(&self, u: f32) -> (f32, f32, usize) {
+        let n = self.func.len();
+        let offset = (self.cdf.partition_point(|&c| c <= u).max(1) - 1).min(n - 1);
+
+        let mut du = u - self.cdf[offset];
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        if width > 0.0 {
+            du /= width;
+        }
+
+        let x = ((offset as f32 + du) / n as f32).min(1.0 - f32::EPSILON);
+        (x, self.segment_pdf(offset), offset)
+    }
+
+    pub fn pdf(&self, x: f32) -> f32 {
+        let n = self.func.len();
+        let offset = ((x * n as f32) as usize).min(n - 1);
+        self.segment_pdf(offset)
+    }
+
+    fn segment_pdf(&self, offset: usize) -> f32 {
+        if self.func_int > 0.0 {
+            self.func[offset].abs() / self.func_int
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Piecewise constant distribution over [0, 1)^2, given a row-major grid of function values.
+/// Sampled by choosing a row from the marginal distribution, then a column within that row.
+#[derive(Clone)]
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(func: &[f32], nu: usize, nv: usize) -> Self {
+        let conditional: Vec<Distribution1D> = func.chunks(nu)
+            .take(nv)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(conditional.iter().map(|d| d.integral()).collect());
+        Self { conditional, marginal }
+    }
+
+    /// Returns sampled (u, v) along with its pdf.
+    pub fn sample(&self, u0: f32, u1: f32) -> (f32, f32, f32) {
+        let (v, pdf_v, row) = self.marginal.sample(u1);
+        let (u, pdf_u, _) = self.conditional[row].sample(u0);
+        (u, v, pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, u: f32, v: f32) -> f32 {
+        let nv = self.conditional.len();
+        let row = ((v * nv as f32) as usize).min(nv - 1);
+        self.marginal.pdf(v) * self.conditional[row].pdf(u)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distribution1d_sample() {
+        let d = Distribution1D::new(vec![1.0, 3.0]);
+        assert_eq!(d.integral(), 2.0);
+
+        let (x, pdf, offset) = d.sample(0.0);
+        assert_eq!((x, pdf, offset), (0.0, 0.5, 0));
+
+        // first quarter of the cdf maps to first half of the domain
+        let (x, pdf, offset) = d.sample(0.125);
+        assert_eq!((x, pdf, offset), (0.25, 0.5, 0));
+
+        let (x, pdf, offset) = d.sample(0.625);
+        assert_eq!((x, pdf, offset), (0.75, 1.5, 1));
+        assert_eq!(d.pdf(0.75), 1.5);
+    }
+
+    #[test]
+    fn test_distribution1d_zero() {
+        let d = Distribution1D::new(vec![0.0, 0.0, 0.0, 0.0]);
+        let (x, pdf, offset) = d.sample(0.5);
+        assert_eq!((x, pdf, offset), (0.5, 1.0, 2));
+    }
+
+    #[test]
+    fn test_distribution2d_pdf() {
+        let d = Distribution2D::new(&[1.0, 1.0, 0.0, 2.0], 2, 2);
+        let (u, v, pdf) = d.sample(0.5, 0.9);
+        assert!(u >= 0.5 && v >= 0.5);
+        assert_eq!(pdf, d.pdf(u, v));
+        assert_eq!(d.pdf(0.25, 0.75), 0.0);
+        assert_eq!(d.pdf(0.75, 0.75), 2.0);
+    }
+}
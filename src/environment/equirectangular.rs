@@ -0,0 +1,107 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::f32::consts::PI;
+use crate::sampler::Sampler;
+use image::hdr::HDRDecoder;
+use crate::vec3::Vec3;
+use crate::environment::Environment;
+use crate::environment::distribution::Distribution2D;
+
+/// Environment map from a Radiance HDR image in equirectangular (latitude/longitude) format,
+/// importance sampled in proportion to the luminance of its pixels.
+///
+/// The top row of the image is straight up (+y), and with no rotation the centre of the image
+/// faces along +x.
+#[derive(Clone)]
+pub struct Equirectangular {
+    pixels: Vec<Vec3>,
+    nx: u32,
+    ny: u32,
+    rotation: f32,
+    intensity: f32,
+    distribution: Distribution2D,
+}
+
+impl Equirectangular {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        let reader = BufReader::new(File::open(path).unwrap());
+        let decoder = HDRDecoder::new(reader).unwrap();
+        let metadata = decoder.metadata();
+        let pixels = decoder.read_image_hdr().unwrap()
+            .into_iter()
+            .map(|rgb| Vec3::new(rgb[0], rgb[1], rgb[2]))
+            .collect();
+        Self::from_pixels(pixels, metadata.width, metadata.height)
+    }
+
+    /// Environment map from a row-major list of linear radiance values.
+    pub fn from_pixels(pixels: Vec<Vec3>, nx: u32, ny: u32) -> Self {
+        // weight by sin(theta) to account for rows near the poles covering a smaller solid angle
+        let mut func = Vec::with_capacity(pixels.len());
+        for j in 0..ny {
+            let sin_theta = (PI * (j as f32 + 0.5) / ny as f32).sin();
+            for i in 0..nx {
+                func.push(pixels[(j * nx + i) as usize].luminance() * sin_theta);
+            }
+        }
+        let distribution = Distribution2D::new(&func, nx as usize, ny as usize);
+        Self { pixels, nx, ny, rotation: 0.0, intensity: 1.0, distribution }
+    }
+
+    /// Rotate environment about the vertical (y) axis by `angle` degrees.
+    pub fn with_rotation(mut self, angle: f32) -> Self {
+        self.rotation = angle.to_radians();
+        self
+    }
+
+    /// Scale radiance of the whole environment.
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    fn direction_to_uv(&self, direction: &Vec3) -> (f32, f32) {
+        let d = direction.to_unit_vector();
+        let phi = d[2].atan2(d[0]) - self.rotation;
+        let u = (phi / (2.0 * PI) + 0.5).rem_euclid(1.0);
+        let v = d[1].clamp(-1.0, 1.0).acos() / PI;
+        (u, v)
+    }
+
+    fn uv_to_direction(&self, u: f32, v: f32) -> Vec3 {
+        let phi = (u - 0.5) * 2.0 * PI + self.rotation;
+        let theta = v * PI;
+        Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin())
+    }
+}
+
+impl Environment for Equirectangular {
+    fn value(&self, direction: &Vec3) -> Vec3 {
+        let (u, v) = self.direction_to_uv(direction);
+        let i = ((u * self.nx as f32) as u32).min(self.nx - 1);
+        let j = ((v * self.ny as f32) as u32).min(self.ny - 1);
+        self.intensity * &self.pixels[(j * self.nx + i) as usize]
+    }
+
+    fn sample_direction(&self, sampler: &mut dyn Sampler) -> Option<(Vec3, f32)> {
+        let u = sampler.get_2d();
+        let (u, v, pdf) = self.distribution.sample(u.0, u.1);
+        let sin_theta = (v * PI).sin();
+        if pdf <= 0.0 || sin_theta <= 0.0 {
+            return None;
+        }
+
+        // convert from pdf over the image to pdf over solid angle
+        Some((self.uv_to_direction(u, v), pdf / (2.0 * PI * PI * sin_theta)))
+    }
+
+    fn pdf_value(&self, direction: &Vec3) -> f32 {
+        let (u, v) = self.direction_to_uv(direction);
+        let sin_theta = (v * PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+}
@@ -0,0 +1,27 @@
+use crate::vec3::Vec3;
+use crate::environment::Environment;
+
+/// Linear blend between two colours, from looking straight down to looking straight up.
+#[derive(Clone)]
+pub struct Gradient {
+    bottom: Vec3,
+    top: Vec3,
+}
+
+impl Gradient {
+    pub fn new(bottom: Vec3, top: Vec3) -> Self {
+        Self { bottom, top }
+    }
+
+    /// The classic white to light blue sky.
+    pub fn sky() -> Self {
+        Self::new(Vec3::ones(), Vec3::new(0.5, 0.7, 1.0))
+    }
+}
+
+impl Environment for Gradient {
+    fn value(&self, direction: &Vec3) -> Vec3 {
+        let t = 0.5 * (direction.to_unit_vector()[1] + 1.0);
+        (1.0 - t) * &self.bottom + t * &self.top
+    }
+}
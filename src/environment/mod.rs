@@ -0,0 +1,39 @@
+//! Environments provide the light arriving from infinitely far away, along rays which don't hit
+//! anything in the scene.
+
+use crate::sampler::Sampler;
+use crate::vec3::Vec3;
+use crate::utils;
+
+mod distribution;
+
+mod constant;
+pub use constant::Constant;
+
+mod gradient;
+pub use gradient::Gradient;
+
+mod equirectangular;
+pub use equirectangular::Equirectangular;
+
+mod sky;
+pub use sky::Sky;
+
+mod sun;
+pub use sun::{Sun, SunSky};
+
+pub trait Environment: Send + Sync {
+    /// Radiance arriving from the environment, travelling in the reverse of `direction`.
+    fn value(&self, direction: &Vec3) -> Vec3;
+
+    /// Samples a direction towards the environment, returning it along with its solid angle pdf.
+    /// Defaults to choosing uniformly from all directions.
+    fn sample_direction(&self, sampler: &mut dyn Sampler) -> Option<(Vec3, f32)> {
+        Some((utils::uniform_sphere(sampler.get_2d()), 1.0 / (4.0 * std::f32::consts::PI)))
+    }
+
+    /// Solid angle pdf of `sample_direction` generating `direction`.
+    fn pdf_value(&self, _direction: &Vec3) -> f32 {
+        1.0 / (4.0 * std::f32::consts::PI)
+    }
+}
@@ -0,0 +1,186 @@
+use std::f32::consts::PI;
+use crate::sampler::Sampler;
+use crate::vec3::Vec3;
+use crate::environment::Environment;
+
+/// Default scaling from luminance in kcd/m^2 to the units used by the rest of the renderer, which
+/// puts a clear midday sky at around 0.5.
+pub const DEFAULT_INTENSITY: f32 = 0.05;
+
+/// Analytic daylight sky, using the Preetham et al. (1999) model. The sun itself is not included,
+/// see `Sun` and `SunSky`. There is no light from below the horizon.
+#[derive(Clone)]
+pub struct Sky {
+    sun_direction: Vec3,
+    theta_sun: f32,
+    perez_lum: [f32; 5],
+    perez_x: [f32; 5],
+    perez_y: [f32; 5],
+    zenith: (f32, f32, f32),
+    intensity: f32,
+}
+
+impl Sky {
+    /// # Arguments
+    ///
+    /// * `elevation` - angle of the sun above the horizon in degrees
+    /// * `azimuth` - angle of the sun around the vertical in degrees, 0 being +x, 90 being +z
+    /// * `turbidity` - haziness of the atmosphere, from 2 (very clear) to around 10 (hazy)
+    pub fn new(elevation: f32, azimuth: f32, turbidity: f32) -> Self {
+        let t = turbidity;
+        let sun_direction = sun_direction(elevation, azimuth);
+        let theta_sun = PI / 2.0 - elevation.to_radians();
+
+        let perez_lum = [
+            0.1787 * t - 1.4630,
+            -0.3554 * t + 0.4275,
+            -0.0227 * t + 5.3251,
+            0.1206 * t - 2.5771,
+            -0.0670 * t + 0.3703,
+        ];
+        let perez_x = [
+            -0.0193 * t - 0.2592,
+            -0.0665 * t + 0.0008,
+            -0.0004 * t + 0.2125,
+            -0.0641 * t - 0.8989,
+            -0.0033 * t + 0.0452,
+        ];
+        let perez_y = [
+            -0.0167 * t - 0.2608,
+            -0.0950 * t + 0.0092,
+            -0.0079 * t + 0.2102,
+            -0.0441 * t - 1.6537,
+            -0.0109 * t + 0.0529,
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        let zenith_lum = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+        let zenith_x = zenith_chromaticity(t, theta_sun, &[
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_y = zenith_chromaticity(t, theta_sun, &[
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+
+        Self {
+            sun_direction,
+            theta_sun,
+            perez_lum,
+            perez_x,
+            perez_y,
+            zenith: (zenith_lum, zenith_x, zenith_y),
+            intensity: DEFAULT_INTENSITY,
+        }
+    }
+
+    /// Set scaling from luminance in kcd/m^2 to output radiance.
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    // relative value of a Perez distribution function, compared to its value at the zenith
+    fn perez_ratio(&self, coeffs: &[f32; 5], cos_theta: f32, gamma: f32) -> f32 {
+        perez(coeffs, cos_theta, gamma) / perez(coeffs, 1.0, self.theta_sun)
+    }
+}
+
+impl Environment for Sky {
+    fn value(&self, direction: &Vec3) -> Vec3 {
+        let d = direction.to_unit_vector();
+        if d[1] <= 0.0 {
+            return Vec3::zeros();
+        }
+
+        // avoid blowing up at the horizon
+        let cos_theta = d[1].max(0.01);
+        let gamma = d.dot(&self.sun_direction).clamp(-1.0, 1.0).acos();
+
+        let (zenith_lum, zenith_x, zenith_y) = self.zenith;
+        let lum = zenith_lum * self.perez_ratio(&self.perez_lum, cos_theta, gamma);
+        let x = zenith_x * self.perez_ratio(&self.perez_x, cos_theta, gamma);
+        let y = zenith_y * self.perez_ratio(&self.perez_y, cos_theta, gamma);
+        self.intensity * xyy_to_rgb(x, y, lum)
+    }
+
+    // uniformly sample the upper hemisphere
+    fn sample_direction(&self, sampler: &mut dyn Sampler) -> Option<(Vec3, f32)> {
+        let u = sampler.get_2d();
+        let z = u.0;
+        let phi = 2.0 * PI * u.1;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        Some((Vec3::new(r * phi.cos(), z, r * phi.sin()), 1.0 / (2.0 * PI)))
+    }
+
+    fn pdf_value(&self, direction: &Vec3) -> f32 {
+        if direction[1] > 0.0 {
+            1.0 / (2.0 * PI)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Unit vector pointing towards a sun at the given elevation and azimuth (in degrees).
+pub fn sun_direction(elevation: f32, azimuth: f32) -> Vec3 {
+    let elevation = elevation.to_radians();
+    let azimuth = azimuth.to_radians();
+    Vec3::new(elevation.cos() * azimuth.cos(), elevation.sin(), elevation.cos() * azimuth.sin())
+}
+
+fn perez(coeffs: &[f32; 5], cos_theta: f32, gamma: f32) -> f32 {
+    let [a, b, c, d, e] = *coeffs;
+    (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+}
+
+fn zenith_chromaticity(turbidity: f32, theta_sun: f32, m: &[[f32; 4]; 3]) -> f32 {
+    let t = [turbidity * turbidity, turbidity, 1.0];
+    let th = [theta_sun.powi(3), theta_sun.powi(2), theta_sun, 1.0];
+    let mut acc = 0.0;
+    for (i, row) in m.iter().enumerate() {
+        for (j, coeff) in row.iter().enumerate() {
+            acc += t[i] * coeff * th[j];
+        }
+    }
+    acc
+}
+
+/// Converts CIE xyY colour to linear sRGB.
+pub fn xyy_to_rgb(x: f32, y: f32, lum: f32) -> Vec3 {
+    if y <= 0.0 {
+        return Vec3::zeros();
+    }
+
+    let cx = x * lum / y;
+    let cz = (1.0 - x - y) * lum / y;
+    let r = 3.2406 * cx - 1.5372 * lum - 0.4986 * cz;
+    let g = -0.9689 * cx + 1.8758 * lum + 0.0415 * cz;
+    let b = 0.0557 * cx - 0.2040 * lum + 1.0570 * cz;
+    Vec3::new(r.max(0.0), g.max(0.0), b.max(0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sky_brightest_near_sun() {
+        let sky = Sky::new(30.0, 0.0, 3.0);
+        let near_sun = sky.value(&sun_direction(35.0, 0.0));
+        let away_from_sun = sky.value(&sun_direction(35.0, 180.0));
+        assert!(near_sun[1] > away_from_sun[1]);
+        assert_eq!(sky.value(&Vec3::new(0.0, -1.0, 0.0)), Vec3::zeros());
+    }
+
+    #[test]
+    fn test_sky_clear_is_blue() {
+        let sky = Sky::new(45.0, 0.0, 2.5);
+        let zenith = sky.value(&Vec3::new(0.0, 1.0, 0.0));
+        assert!(zenith[2] > zenith[0]);
+        assert!(zenith[1] > 0.0);
+    }
+}
@@ -0,0 +1,135 @@
+use std::f32::consts::PI;
+use crate::sampler::Sampler;
+use crate::vec3::Vec3;
+use crate::utils::{self, Onb};
+use crate::environment::Environment;
+use crate::environment::sky::{self, Sky};
+
+/// Angular radius of the sun as seen from earth, in radians.
+const ANGULAR_RADIUS: f32 = 0.004_65;
+
+/// Luminance of the sun outside the atmosphere in kcd/m^2.
+const LUMINANCE: f32 = 2.0e6;
+
+/// Disc of the sun, coloured by scattering in the atmosphere (Rayleigh and aerosol extinction
+/// along the path to the sun, as used by the Preetham model). Emits nothing once the sun has set.
+#[derive(Clone)]
+pub struct Sun {
+    direction: Vec3,
+    radiance: Vec3,
+    cos_theta_max: f32,
+    solid_angle: f32,
+}
+
+impl Sun {
+    /// Arguments are the same as for `Sky::new`.
+    pub fn new(elevation: f32, azimuth: f32, turbidity: f32) -> Self {
+        let direction = sky::sun_direction(elevation, azimuth);
+        let radiance = if elevation > 0.0 {
+            sky::DEFAULT_INTENSITY * LUMINANCE * transmittance(90.0 - elevation, turbidity)
+        } else {
+            Vec3::zeros()
+        };
+
+        // 1 - cos(r) = 2 sin^2(r / 2), avoiding rounding cos(r) to 1 for such a small disc
+        let one_minus_cos = 2.0 * (ANGULAR_RADIUS / 2.0).sin().powi(2);
+        Self { direction, radiance, cos_theta_max: 1.0 - one_minus_cos, solid_angle: 2.0 * PI * one_minus_cos }
+    }
+
+    /// Scale radiance relative to the default intensity (matching `Sky`'s default).
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.radiance *= intensity / sky::DEFAULT_INTENSITY;
+        self
+    }
+
+    fn has_set(&self) -> bool {
+        self.radiance == Vec3::zeros()
+    }
+}
+
+impl Environment for Sun {
+    fn value(&self, direction: &Vec3) -> Vec3 {
+        if direction.to_unit_vector().dot(&self.direction) >= self.cos_theta_max {
+            self.radiance.clone()
+        } else {
+            Vec3::zeros()
+        }
+    }
+
+    fn sample_direction(&self, sampler: &mut dyn Sampler) -> Option<(Vec3, f32)> {
+        if self.has_set() {
+            return None;
+        }
+        let uvw = Onb::from_w(&self.direction);
+        let direction = uvw.local(&utils::uniform_cone(sampler.get_2d(), self.cos_theta_max));
+        Some((direction, 1.0 / self.solid_angle))
+    }
+
+    fn pdf_value(&self, direction: &Vec3) -> f32 {
+        if !self.has_set() && direction.to_unit_vector().dot(&self.direction) >= self.cos_theta_max {
+            1.0 / self.solid_angle
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Preetham sky along with the sun disc, sampling each half of the time.
+#[derive(Clone)]
+pub struct SunSky {
+    sun: Sun,
+    sky: Sky,
+}
+
+impl SunSky {
+    /// Arguments are the same as for `Sky::new`.
+    pub fn new(elevation: f32, azimuth: f32, turbidity: f32) -> Self {
+        Self { sun: Sun::new(elevation, azimuth, turbidity), sky: Sky::new(elevation, azimuth, turbidity) }
+    }
+
+    /// Set scaling from luminance in kcd/m^2 to output radiance, for both sun and sky.
+    pub fn with_intensity(self, intensity: f32) -> Self {
+        Self { sun: self.sun.with_intensity(intensity), sky: self.sky.with_intensity(intensity) }
+    }
+}
+
+impl Environment for SunSky {
+    fn value(&self, direction: &Vec3) -> Vec3 {
+        self.sun.value(direction) + self.sky.value(direction)
+    }
+
+    fn sample_direction(&self, sampler: &mut dyn Sampler) -> Option<(Vec3, f32)> {
+        let (direction, _) = if self.sun.has_set() || sampler.get_1d() < 0.5 {
+            self.sky.sample_direction(sampler)?
+        } else {
+            self.sun.sample_direction(sampler)?
+        };
+        let pdf = self.pdf_value(&direction);
+        Some((direction, pdf))
+    }
+
+    fn pdf_value(&self, direction: &Vec3) -> f32 {
+        if self.sun.has_set() {
+            self.sky.pdf_value(direction)
+        } else {
+            0.5 * (self.sun.pdf_value(direction) + self.sky.pdf_value(direction))
+        }
+    }
+}
+
+// fraction of sunlight reaching the ground at red, green and blue wavelengths, for sun at zenith
+// angle theta (degrees)
+fn transmittance(theta: f32, turbidity: f32) -> Vec3 {
+    // relative optical mass of the atmosphere along the path to the sun
+    let mass = 1.0 / (theta.to_radians().cos() + 0.15 * (93.885 - theta).powf(-1.253));
+    let beta = 0.046_08 * turbidity - 0.045_86;
+    let alpha = 1.3;
+
+    let mut t = Vec3::zeros();
+    for (i, wavelength) in [0.65f32, 0.55, 0.45].iter().enumerate() {
+        let rayleigh = (-0.008_735 * wavelength.powf(-4.08) * mass).exp();
+        let aerosol = (-beta * wavelength.powf(-alpha) * mass).exp();
+        t[i] = rayleigh * aerosol;
+    }
+    t
+}
@@ -0,0 +1,30 @@
+use super::Filter;
+
+/// Weights all samples within the radius equally. With a radius of half a pixel, each pixel is
+/// the average of the samples taken within it.
+#[derive(Debug)]
+pub struct BoxFilter {
+    radius: f32,
+}
+
+impl BoxFilter {
+    pub fn new(radius: f32) -> Self {
+        Self { radius }
+    }
+}
+
+impl Filter for BoxFilter {
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    // half open, so that samples on the boundary between pixels count towards exactly one
+    fn evaluate(&self, x: f32, y: f32) -> f32 {
+        let r = self.radius;
+        if -r <= x && x < r && -r <= y && y < r {
+            1.0
+        } else {
+            0.0
+        }
+    }
+}
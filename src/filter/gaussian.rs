@@ -0,0 +1,35 @@
+use super::Filter;
+
+/// Gaussian filter, offset so that it falls smoothly to zero at the radius rather than being cut
+/// off abruptly.
+#[derive(Debug)]
+pub struct Gaussian {
+    radius: f32,
+    sigma: f32,
+    edge: f32,
+}
+
+impl Gaussian {
+    pub fn new(radius: f32, sigma: f32) -> Self {
+        let edge = gaussian(radius, sigma);
+        Self { radius, sigma, edge }
+    }
+
+    fn gaussian(&self, x: f32) -> f32 {
+        (gaussian(x, self.sigma) - self.edge).max(0.0)
+    }
+}
+
+impl Filter for Gaussian {
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f32, y: f32) -> f32 {
+        self.gaussian(x) * self.gaussian(y)
+    }
+}
+
+fn gaussian(x: f32, sigma: f32) -> f32 {
+    (-x * x / (2.0 * sigma * sigma)).exp()
+}
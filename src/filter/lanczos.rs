@@ -0,0 +1,40 @@
+use std::f32::consts::PI;
+
+use super::Filter;
+
+/// Lanczos filter, a sinc (the ideal low-pass filter) windowed by a sinc stretched to reach its
+/// first zero at the radius. Has one lobe per pixel of radius.
+#[derive(Debug)]
+pub struct Lanczos {
+    radius: f32,
+}
+
+impl Lanczos {
+    pub fn new(radius: f32) -> Self {
+        Self { radius }
+    }
+
+    fn lanczos(&self, x: f32) -> f32 {
+        if x.abs() >= self.radius {
+            return 0.0;
+        }
+        sinc(x) * sinc(x / self.radius)
+    }
+}
+
+impl Filter for Lanczos {
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f32, y: f32) -> f32 {
+        self.lanczos(x) * self.lanczos(y)
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-5 {
+        return 1.0;
+    }
+    (PI * x).sin() / (PI * x)
+}
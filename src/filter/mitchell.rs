@@ -0,0 +1,41 @@
+use super::Filter;
+
+/// Mitchell-Netravali family of cubic filters, parameterised by B and C. Mitchell and Netravali
+/// recommend B = C = 1/3 as a good trade off between blurring and ringing.
+#[derive(Debug)]
+pub struct Mitchell {
+    radius: f32,
+    b: f32,
+    c: f32,
+}
+
+impl Mitchell {
+    pub fn new(radius: f32, b: f32, c: f32) -> Self {
+        Self { radius, b, c }
+    }
+
+    // the cubic is defined over [-2, 2], so is stretched to fit the radius
+    fn mitchell(&self, x: f32) -> f32 {
+        let (b, c) = (self.b, self.c);
+        let x = (2.0 * x / self.radius).abs();
+        let weight = if x >= 2.0 {
+            0.0
+        } else if x > 1.0 {
+            (-b - 6.0 * c) * x.powi(3) + (6.0 * b + 30.0 * c) * x.powi(2) + (-12.0 * b - 48.0 * c) * x
+                + (8.0 * b + 24.0 * c)
+        } else {
+            (12.0 - 9.0 * b - 6.0 * c) * x.powi(3) + (-18.0 + 12.0 * b + 6.0 * c) * x.powi(2) + (6.0 - 2.0 * b)
+        };
+        weight / 6.0
+    }
+}
+
+impl Filter for Mitchell {
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f32, y: f32) -> f32 {
+        self.mitchell(x) * self.mitchell(y)
+    }
+}
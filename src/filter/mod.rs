@@ -0,0 +1,130 @@
+//! Reconstruction filters, which weight how much each sample contributes to the pixels around
+//! it. Wider filters than a box of one pixel blur slightly but reduce aliasing, and filters with
+//! negative lobes (Mitchell, Lanczos) sharpen edges again.
+
+use std::fmt;
+use std::str::FromStr;
+
+mod box_filter;
+pub use box_filter::BoxFilter;
+
+mod tent;
+pub use tent::Tent;
+
+mod gaussian;
+pub use gaussian::Gaussian;
+
+mod mitchell;
+pub use mitchell::Mitchell;
+
+mod lanczos;
+pub use lanczos::Lanczos;
+
+/// Filters are `Debug` so that the one samples were filtered with can be recorded (see
+/// `SampleSettings`).
+pub trait Filter: Send + Sync + fmt::Debug {
+    /// Distance in pixels (along each axis) from a pixel's centre beyond which samples have no
+    /// effect on it.
+    fn radius(&self) -> f32;
+
+    /// Weight of a sample at offset (x, y) pixels from a pixel's centre. Weights needn't be
+    /// normalised, as pixels are divided by the sum of the weights of their samples.
+    fn evaluate(&self, x: f32, y: f32) -> f32;
+}
+
+/// Available `Filter` implementations.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum FilterType {
+    /// Equal weight within the radius, by default the pixel's own area.
+    #[default]
+    Box,
+    /// Weight falling off linearly to zero at the radius.
+    Tent,
+    /// Gaussian with a standard deviation of a third of the radius.
+    Gaussian,
+    /// Mitchell-Netravali cubic, with B = C = 1/3.
+    Mitchell,
+    /// Sinc windowed by a sinc stretched to the radius.
+    Lanczos,
+}
+
+impl FilterType {
+    pub const NAMES: &'static [&'static str] = &["box", "tent", "gaussian", "mitchell", "lanczos"];
+
+    /// Radius used when none is given.
+    pub fn default_radius(self) -> f32 {
+        match self {
+            FilterType::Box => 0.5,
+            FilterType::Tent => 1.0,
+            FilterType::Gaussian => 1.5,
+            FilterType::Mitchell => 2.0,
+            FilterType::Lanczos => 2.0,
+        }
+    }
+
+    pub fn new_filter(self, radius: f32) -> Box<dyn Filter> {
+        match self {
+            FilterType::Box => Box::new(BoxFilter::new(radius)),
+            FilterType::Tent => Box::new(Tent::new(radius)),
+            FilterType::Gaussian => Box::new(Gaussian::new(radius, radius / 3.0)),
+            FilterType::Mitchell => Box::new(Mitchell::new(radius, 1.0 / 3.0, 1.0 / 3.0)),
+            FilterType::Lanczos => Box::new(Lanczos::new(radius)),
+        }
+    }
+}
+
+impl FromStr for FilterType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "box" => Ok(FilterType::Box),
+            "tent" => Ok(FilterType::Tent),
+            "gaussian" => Ok(FilterType::Gaussian),
+            "mitchell" => Ok(FilterType::Mitchell),
+            "lanczos" => Ok(FilterType::Lanczos),
+            _ => Err(format!("unknown filter: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for FilterType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            FilterType::Box => "box",
+            FilterType::Tent => "tent",
+            FilterType::Gaussian => "gaussian",
+            FilterType::Mitchell => "mitchell",
+            FilterType::Lanczos => "lanczos",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_names() {
+        for name in FilterType::NAMES {
+            let filter: FilterType = name.parse().unwrap();
+            assert_eq!(&filter.to_string(), name);
+        }
+        assert!("sinc".parse::<FilterType>().is_err());
+    }
+
+    #[test]
+    fn test_filters_vanish_at_radius() {
+        for name in FilterType::NAMES {
+            let filter_type: FilterType = name.parse().unwrap();
+            let filter = filter_type.new_filter(filter_type.default_radius());
+            let r = filter.radius();
+            assert!(filter.evaluate(0.0, 0.0) > 0.0, "{} is zero at its centre", name);
+            assert_eq!(filter.evaluate(0.1, 0.2), filter.evaluate(-0.1, -0.2), "{} isn't symmetric", name);
+            for &(x, y) in &[(r, 0.0), (0.0, r), (-r - 0.01, 0.3), (r + 0.5, r + 0.5)] {
+                assert!(filter.evaluate(x, y).abs() < 1e-6, "{} is non-zero at ({}, {})", name, x, y);
+            }
+        }
+    }
+}
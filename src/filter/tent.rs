@@ -0,0 +1,27 @@
+use super::Filter;
+
+/// Triangle filter, with weight falling off linearly from the centre to zero at the radius.
+#[derive(Debug)]
+pub struct Tent {
+    radius: f32,
+}
+
+impl Tent {
+    pub fn new(radius: f32) -> Self {
+        Self { radius }
+    }
+
+    fn tent(&self, x: f32) -> f32 {
+        (self.radius - x.abs()).max(0.0)
+    }
+}
+
+impl Filter for Tent {
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f32, y: f32) -> f32 {
+        self.tent(x) * self.tent(y)
+    }
+}
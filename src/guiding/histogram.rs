@@ -0,0 +1,139 @@
+use crate::vec3::Vec3;
+
+// directions are binned by (cos θ, φ), which divides the sphere into bins of equal solid angle
+const THETA_BINS: usize = 8;
+const PHI_BINS: usize = 16;
+const BINS: usize = THETA_BINS * PHI_BINS;
+
+// cells only guide paths once they've learned from this many, so that a few lucky paths don't
+// send every later path the same way
+const MIN_SAMPLES: u32 = 1024;
+
+/// Distribution of the light arriving at a region of the scene over directions, learned from
+/// estimates of the radiance arriving from the directions paths were scattered in.
+#[derive(Clone, Debug)]
+pub struct DirectionalHistogram {
+    weights: Vec<f32>,
+    samples: u32,
+    pdf: Vec<f32>, // probability of each bin, or empty if not trained yet
+    cdf: Vec<f32>,
+}
+
+impl DirectionalHistogram {
+    pub fn new() -> Self {
+        Self { weights: vec![0.0; BINS], samples: 0, pdf: Vec::new(), cdf: Vec::new() }
+    }
+
+    /// Records radiance `weight` arriving from `direction`, which should be divided by the pdf of
+    /// the direction being sampled.
+    pub fn add(&mut self, direction: &Vec3, weight: f32) {
+        self.weights[bin(direction)] += weight;
+        self.samples += 1;
+    }
+
+    /// Adds everything recorded by another histogram.
+    pub fn merge(&mut self, other: &DirectionalHistogram) {
+        for (w, o) in self.weights.iter_mut().zip(&other.weights) {
+            *w += o;
+        }
+        self.samples += other.samples;
+    }
+
+    /// Rebuilds the distribution from what's been recorded.
+    pub fn update(&mut self) {
+        let total: f32 = self.weights.iter().sum();
+        if self.samples < MIN_SAMPLES || !(total > 0.0 && total.is_finite()) {
+            self.pdf.clear();
+            self.cdf.clear();
+            return;
+        }
+
+        self.pdf = self.weights.iter().map(|w| w / total).collect();
+        self.cdf = self.pdf.iter()
+            .scan(0.0, |sum, p| {
+                *sum += p;
+                Some(*sum)
+            })
+            .collect();
+    }
+
+    /// Whether enough has been learned to sample directions from.
+    pub fn is_trained(&self) -> bool {
+        !self.pdf.is_empty()
+    }
+
+    /// Samples a (unit) direction in proportion to the light arriving from it.
+    pub fn sample(&self, (u, v): (f32, f32)) -> Vec3 {
+        let idx = self.cdf.partition_point(|&c| c <= u).min(BINS - 1);
+        let start = if idx > 0 { self.cdf[idx - 1] } else { 0.0 };
+        let offset = if self.pdf[idx] > 0.0 { ((u - start) / self.pdf[idx]).clamp(0.0, 1.0) } else { 0.5 };
+
+        let z = -1.0 + 2.0 * ((idx / PHI_BINS) as f32 + offset) / THETA_BINS as f32;
+        let phi = 2.0 * std::f32::consts::PI * ((idx % PHI_BINS) as f32 + v) / PHI_BINS as f32;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        Vec3::new(r * phi.cos(), r * phi.sin(), z)
+    }
+
+    /// Solid angle pdf of `sample` returning `direction`.
+    pub fn pdf(&self, direction: &Vec3) -> f32 {
+        if !self.is_trained() {
+            return 0.0;
+        }
+        self.pdf[bin(direction)] * BINS as f32 / (4.0 * std::f32::consts::PI)
+    }
+}
+
+impl Default for DirectionalHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn bin(direction: &Vec3) -> usize {
+    let d = direction.to_unit_vector();
+    let u = (d[2].clamp(-1.0, 1.0) + 1.0) / 2.0;
+    let v = d[1].atan2(d[0]) / (2.0 * std::f32::consts::PI);
+    let v = if v < 0.0 { v + 1.0 } else { v };
+    let theta_bin = ((u * THETA_BINS as f32) as usize).min(THETA_BINS - 1);
+    let phi_bin = ((v * PHI_BINS as f32) as usize).min(PHI_BINS - 1);
+    theta_bin * PHI_BINS + phi_bin
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils;
+
+    #[test]
+    fn test_sample_matches_pdf() {
+        let mut histogram = DirectionalHistogram::new();
+        let up = Vec3::new(0.1, 0.2, 1.0);
+        let add = |histogram: &mut DirectionalHistogram, samples: std::ops::Range<u32>| {
+            for i in samples {
+                histogram.add(&up, 1.0);
+                histogram.add(&utils::uniform_sphere((i as f32 / MIN_SAMPLES as f32, 0.3)), 0.5);
+            }
+            histogram.update();
+        };
+        add(&mut histogram, 0..MIN_SAMPLES / 4);
+        assert!(!histogram.is_trained());
+        add(&mut histogram, MIN_SAMPLES / 4..MIN_SAMPLES);
+        assert!(histogram.is_trained());
+
+        // directions sampled land in the bin they were sampled from
+        let mut expected = 0.0;
+        for i in 0..1024 {
+            for j in 0..4 {
+                let (u, v) = ((i as f32 + 0.5) / 1024.0, (j as f32 + 0.5) / 4.0);
+                let direction = histogram.sample((u, v));
+                assert!((direction.length() - 1.0).abs() < 1e-4);
+                if bin(&direction) == bin(&up) {
+                    expected += 1.0 / (1024.0 * 4.0);
+                }
+            }
+        }
+        let solid_angle = 4.0 * std::f32::consts::PI / BINS as f32;
+        assert!((histogram.pdf(&up) * solid_angle - expected).abs() < 0.01);
+        assert!(histogram.pdf(&up) > histogram.pdf(&-up.clone()));
+    }
+}
@@ -0,0 +1,141 @@
+//! Path guiding (after Müller et al. 2017): learning where light arrives at each part of the scene
+//! from, using the paths already traced, and scattering later paths towards it. Materials sample
+//! directions according to their BSDF alone, so light arriving mostly from a few directions (e.g.
+//! from a brightly lit patch of wall) is rarely found by diffuse bounces. The scene is divided into
+//! a grid of cells, each learning a histogram of the radiance arriving from each direction, and
+//! paths are scattered by sampling either the histogram or the material, with the pdf of both
+//! combined so the result stays unbiased.
+
+use std::collections::HashMap;
+
+use crate::sampler::Sampler;
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::hitable::{HitRecord, Hitable};
+use crate::material::ScatterRecord;
+use crate::scenes::Scene;
+
+mod histogram;
+pub use histogram::DirectionalHistogram;
+
+// probability of sampling a direction from the learned distribution rather than the material,
+// where there is one
+const GUIDE_PROBABILITY: f32 = 0.5;
+
+/// Directional distributions of light over a grid of cells covering the scene.
+pub struct PathGuide {
+    grid: Grid,
+    cells: Vec<Option<DirectionalHistogram>>,
+}
+
+impl PathGuide {
+    /// Untrained guide, dividing the scene's bounding box into `resolution`³ cells. Returns `None`
+    /// if the scene isn't bounded.
+    pub fn new<T: Hitable + Send + Sync>(scene: &Scene<T>, resolution: usize) -> Option<Self> {
+        let bbox = scene.hitables.bounding_box(0.0, 1.0)?;
+        let resolution = resolution.max(1);
+        let cell_size = (bbox.max() - bbox.min()) / resolution as f32;
+        let grid = Grid { min: bbox.min().clone(), cell_size, resolution };
+        let cells = vec![None; resolution * resolution * resolution];
+        Some(Self { grid, cells })
+    }
+
+    /// New recorder for radiance found while rendering, to be learned from with `train`.
+    pub fn recorder(&self) -> GuideRecorder {
+        GuideRecorder { grid: self.grid.clone(), cells: HashMap::new() }
+    }
+
+    /// Learns from the radiance recorded by `recorders`, and updates the distributions paths are
+    /// guided by.
+    pub fn train(&mut self, recorders: &[GuideRecorder]) {
+        for recorder in recorders {
+            for (&idx, histogram) in &recorder.cells {
+                self.cells[idx].get_or_insert_with(DirectionalHistogram::new).merge(histogram);
+            }
+        }
+        for histogram in self.cells.iter_mut().flatten() {
+            histogram.update();
+        }
+    }
+
+    /// Scatters a path at `hit` by sampling either the learned distribution or the material
+    /// (which gave `srec`), if the guide has learned about the surface there. Specular scattering
+    /// and scattering within media is left as it is.
+    pub fn scatter(&self, r: &Ray, hit: &HitRecord, srec: ScatterRecord, sampler: &mut dyn Sampler) -> ScatterRecord {
+        let histogram = match self.histogram(hit) {
+            Some(histogram) if !srec.is_specular() => histogram,
+            _ => return srec,
+        };
+
+        let ray = if sampler.get_1d() < GUIDE_PROBABILITY {
+            Ray::new_at_time(hit.point.clone(), histogram.sample(sampler.get_2d()), r.time())
+        } else {
+            srec.ray
+        };
+        let pdf = GUIDE_PROBABILITY * histogram.pdf(ray.direction())
+            + (1.0 - GUIDE_PROBABILITY) * hit.material.scattering_pdf(r, hit, &ray);
+        let attenuation = if pdf > 0.0 {
+            (1.0 / pdf) * &hit.material.bsdf(r, hit, &ray)
+        } else {
+            Vec3::zeros()
+        };
+        ScatterRecord { attenuation, ray, pdf: Some(pdf), wavelength_dependent: srec.wavelength_dependent }
+    }
+
+    /// Solid angle pdf of `scatter` sampling `scattered` at `hit`.
+    pub fn scattering_pdf(&self, r: &Ray, hit: &HitRecord, scattered: &Ray) -> f32 {
+        let material_pdf = hit.material.scattering_pdf(r, hit, scattered);
+        match self.histogram(hit) {
+            Some(histogram) => GUIDE_PROBABILITY * histogram.pdf(scattered.direction()) + (1.0 - GUIDE_PROBABILITY) * material_pdf,
+            None => material_pdf,
+        }
+    }
+
+    // distribution paths are guided by at a surface, if one has been learned
+    fn histogram(&self, hit: &HitRecord) -> Option<&DirectionalHistogram> {
+        if hit.normal.squared_length() == 0.0 {
+            return None;
+        }
+        self.cells[self.grid.cell(&hit.point)].as_ref().filter(|histogram| histogram.is_trained())
+    }
+}
+
+/// Radiance found while rendering part of an image, for a `PathGuide` to learn from.
+pub struct GuideRecorder {
+    grid: Grid,
+    cells: HashMap<usize, DirectionalHistogram>,
+}
+
+impl GuideRecorder {
+    /// Records `radiance` (luminance) arriving at `point` from `direction`, which was sampled with
+    /// solid angle pdf `pdf`.
+    pub fn record(&mut self, point: &Vec3, direction: &Vec3, radiance: f32, pdf: f32) {
+        let weight = radiance / pdf;
+        if !(weight >= 0.0 && weight.is_finite()) {
+            return;
+        }
+        self.cells.entry(self.grid.cell(point))
+            .or_default()
+            .add(direction, weight);
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Grid {
+    min: Vec3,
+    cell_size: Vec3,
+    resolution: usize,
+}
+
+impl Grid {
+    // index of the cell containing `point`, or the nearest one to it
+    fn cell(&self, point: &Vec3) -> usize {
+        let mut idx = 0;
+        for a in (0..3).rev() {
+            let x = if self.cell_size[a] > 0.0 { (point[a] - self.min[a]) / self.cell_size[a] } else { 0.0 };
+            let i = (x.max(0.0) as usize).min(self.resolution - 1);
+            idx = idx * self.resolution + i;
+        }
+        idx
+    }
+}
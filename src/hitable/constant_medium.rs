@@ -1,4 +1,4 @@
-use rand::prelude::*;
+use crate::sampler::Sampler;
 use crate::vec3::Vec3;
 use crate::hitable::Hitable;
 use crate::ray::Ray;
@@ -22,10 +22,10 @@ impl<H: Hitable + Send + Sync, T: Texture + Clone> ConstantMedium<H, T> {
 
 // careful boundary logic needed for ray origins inside the volume - common in clouds where bouncing occurs often
 impl<H: Hitable + Send + Sync, T: Texture + Clone> Hitable for ConstantMedium<H, T> {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, sampler: &mut dyn Sampler) -> Option<HitRecord<'_>> {
 
-        if let Some(hit_rec1) = self.boundary.hit(r, std::f32::MIN, std::f32::MAX) {
-            if let Some(hit_rec2) = self.boundary.hit(r, hit_rec1.t + 0.0001, std::f32::MAX) {
+        if let Some(hit_rec1) = self.boundary.hit(r, f32::MIN, f32::MAX, sampler) {
+            if let Some(hit_rec2) = self.boundary.hit(r, hit_rec1.t + 0.0001, f32::MAX, sampler) {
                 let mut hr1 = hit_rec1.clone();
                 let mut hr2 = hit_rec2.clone();
 
@@ -45,15 +45,14 @@ impl<H: Hitable + Send + Sync, T: Texture + Clone> Hitable for ConstantMedium<H,
                     hr1.t = 0.0;
                 }
 
-                let mut rng = rand::thread_rng();
 
                 let distance_inside_boundary = (hr2.t - hr1.t) * r.direction().length();
-                let hit_distance = -(1.0 / self.density) * rng.gen::<f32>().ln();
+                let hit_distance = -(1.0 / self.density) * sampler.get_1d().ln();
 
                 if hit_distance < distance_inside_boundary {
                     let t = hr1.t + hit_distance / r.direction().length();
                     let p = r.point_at_parameter(t);
-                    let n = Vec3::new(1.0, 0.0, 0.0); // arbitrary, does nothing
+                    let n = Vec3::zeros(); // scattering within a volume has no surface normal
                     return Some(HitRecord::new(t, p, n, &self.phase_function))
                 }
             }
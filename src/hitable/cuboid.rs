@@ -1,3 +1,4 @@
+use crate::sampler::Sampler;
 use crate::vec3::Vec3;
 use crate::ray::Ray;
 use crate::material::Material;
@@ -38,8 +39,11 @@ impl Cuboid {
 }
 
 impl Hitable for Cuboid {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        self.hitables.hit(r, t_min, t_max)
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, sampler: &mut dyn Sampler) -> Option<HitRecord<'_>> {
+        // faces are part of the cuboid, rather than separate objects
+        let mut hit = self.hitables.hit(r, t_min, t_max, sampler)?;
+        hit.object = None;
+        Some(hit)
     }
 
     fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
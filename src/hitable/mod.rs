@@ -1,3 +1,4 @@
+use crate::sampler::Sampler;
 use crate::vec3::Vec3;
 use crate::ray::Ray;
 use crate::material::Material;
@@ -26,6 +27,9 @@ pub struct HitRecord<'a> {
     pub material: &'a dyn Material,
     pub u: f32,
     pub v: f32,
+    /// Identifies the object hit, i.e. the item of the innermost hitable list (or BVH) containing
+    /// the surface hit. Only meaningful while the scene exists, as it's based on its address.
+    pub object: Option<usize>,
 }
 
 impl<'a> HitRecord<'a> {
@@ -34,24 +38,54 @@ impl<'a> HitRecord<'a> {
     }
 
     pub fn new_with_uv(t: f32, point: Vec3, normal: Vec3, material: &'a dyn Material, u: f32, v: f32) -> Self {
-        Self { t, point, normal, material, u, v }
+        Self { t, point, normal, material, u, v, object: None }
     }
 }
 
+// key identifying an object hit, for `HitRecord::object`
+pub(crate) fn object_key(hitable: &dyn Hitable) -> usize {
+    hitable as *const dyn Hitable as *const () as usize
+}
+
 pub trait Hitable {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, sampler: &mut dyn Sampler) -> Option<HitRecord<'_>>;
 
     fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
         None
     }
+
+    /// Solid angle pdf of `sample_direction` generating `direction` (not necessarily a unit
+    /// vector) from `origin`. Defaults to 0 for hitables that can't be sampled as emitters.
+    fn pdf_value(&self, _origin: &Vec3, _direction: &Vec3, _sampler: &mut dyn Sampler) -> f32 {
+        0.0
+    }
+
+    /// Samples a random point on this hitable as seen from `origin`, returning the direction to it
+    /// along with the solid angle pdf of choosing that direction.
+    fn sample_direction(&self, _origin: &Vec3, _sampler: &mut dyn Sampler) -> Option<(Vec3, f32)> {
+        None
+    }
+
+    /// Samples a point uniformly by area on this hitable, e.g. for starting paths from emitters,
+    /// returning a hit record there along with the area pdf of choosing it.
+    fn sample_surface(&self, _sampler: &mut dyn Sampler) -> Option<(HitRecord<'_>, f32)> {
+        None
+    }
+
+    /// Area pdf of `sample_surface` choosing the point found along `direction` from `origin`.
+    /// Defaults to 0 for hitables that can't be sampled as emitters.
+    fn surface_pdf(&self, _origin: &Vec3, _direction: &Vec3, _sampler: &mut dyn Sampler) -> f32 {
+        0.0
+    }
 }
 
 impl Hitable for Vec<Box<dyn Hitable + Send + Sync>> {
-     fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+     fn hit(&self, r: &Ray, t_min: f32, t_max: f32, sampler: &mut dyn Sampler) -> Option<HitRecord<'_>> {
         let mut closest_hit = None;
         let mut closest_t = t_max;
         for hitable in self {
-            if let Some(hit) = hitable.hit(r, t_min, closest_t) {
+            if let Some(mut hit) = hitable.hit(r, t_min, closest_t, sampler) {
+                hit.object.get_or_insert_with(|| object_key(&**hitable));
                 closest_t = hit.t;
                 closest_hit = Some(hit);
             }
@@ -64,12 +98,7 @@ impl Hitable for Vec<Box<dyn Hitable + Send + Sync>> {
             return None;
         }
 
-        let first_box = match self[0].bounding_box(t0, t1) {
-            Some(bounding_box) => bounding_box,
-            None => return None,
-        };
-
-        let mut surrounding_box = first_box.clone();
+        let mut surrounding_box = self[0].bounding_box(t0, t1)?;
 
         for hitable in self[1..].iter() {
             match hitable.bounding_box(t0, t1) {
@@ -82,14 +111,31 @@ impl Hitable for Vec<Box<dyn Hitable + Send + Sync>> {
 
         Some(surrounding_box)
     }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3, sampler: &mut dyn Sampler) -> f32 {
+        self[..].pdf_value(origin, direction, sampler)
+    }
+
+    fn sample_direction(&self, origin: &Vec3, sampler: &mut dyn Sampler) -> Option<(Vec3, f32)> {
+        self[..].sample_direction(origin, sampler)
+    }
+
+    fn sample_surface(&self, sampler: &mut dyn Sampler) -> Option<(HitRecord<'_>, f32)> {
+        self[..].sample_surface(sampler)
+    }
+
+    fn surface_pdf(&self, origin: &Vec3, direction: &Vec3, sampler: &mut dyn Sampler) -> f32 {
+        self[..].surface_pdf(origin, direction, sampler)
+    }
 }
 
 impl Hitable for [Box<dyn Hitable + Send + Sync>] {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, sampler: &mut dyn Sampler) -> Option<HitRecord<'_>> {
         let mut closest_hit = None;
         let mut closest_t = t_max;
         for hitable in self {
-            if let Some(hit) = hitable.hit(r, t_min, closest_t) {
+            if let Some(mut hit) = hitable.hit(r, t_min, closest_t, sampler) {
+                hit.object.get_or_insert_with(|| object_key(&**hitable));
                 closest_t = hit.t;
                 closest_hit = Some(hit);
             }
@@ -102,12 +148,7 @@ impl Hitable for [Box<dyn Hitable + Send + Sync>] {
             return None;
         }
 
-        let first_box = match self[0].bounding_box(t0, t1) {
-            Some(bounding_box) => bounding_box,
-            None => return None,
-        };
-
-        let mut surrounding_box = first_box.clone();
+        let mut surrounding_box = self[0].bounding_box(t0, t1)?;
 
         for hitable in self[1..].iter() {
             match hitable.bounding_box(t0, t1) {
@@ -120,6 +161,55 @@ impl Hitable for [Box<dyn Hitable + Send + Sync>] {
 
         Some(surrounding_box)
     }
+
+    // each hitable is equally likely to be sampled, so pdf is the mean of all pdfs
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3, sampler: &mut dyn Sampler) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+
+        let total: f32 = self.iter().map(|hitable| hitable.pdf_value(origin, direction, sampler)).sum();
+        total / self.len() as f32
+    }
+
+    fn sample_direction(&self, origin: &Vec3, sampler: &mut dyn Sampler) -> Option<(Vec3, f32)> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let idx = ((sampler.get_1d() * self.len() as f32) as usize).min(self.len() - 1);
+        let (direction, _) = self[idx].sample_direction(origin, sampler)?;
+        let pdf = self.pdf_value(origin, &direction, sampler);
+        Some((direction, pdf))
+    }
+
+    fn sample_surface(&self, sampler: &mut dyn Sampler) -> Option<(HitRecord<'_>, f32)> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let idx = ((sampler.get_1d() * self.len() as f32) as usize).min(self.len() - 1);
+        let (mut hit_rec, pdf) = self[idx].sample_surface(sampler)?;
+        hit_rec.object.get_or_insert_with(|| object_key(&*self[idx]));
+        Some((hit_rec, pdf / self.len() as f32))
+    }
+
+    // only the first hitable along direction could have been sampled there
+    fn surface_pdf(&self, origin: &Vec3, direction: &Vec3, sampler: &mut dyn Sampler) -> f32 {
+        let r = Ray::new(origin.clone(), direction.clone());
+        let mut closest = None;
+        let mut closest_t = f32::MAX;
+        for hitable in self {
+            if let Some(hit) = hitable.hit(&r, 0.001, closest_t, sampler) {
+                closest_t = hit.t;
+                closest = Some(hitable);
+            }
+        }
+        match closest {
+            Some(hitable) => hitable.surface_pdf(origin, direction, sampler) / self.len() as f32,
+            None => 0.0,
+        }
+    }
 }
 
 pub struct FlipNormals<T> {
@@ -133,8 +223,8 @@ impl<T: Hitable + Send + Sync> FlipNormals<T> {
 }
 
 impl<T: Hitable + Send + Sync> Hitable for FlipNormals<T> {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        match self.hitable.hit(r, t_min, t_max) {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, sampler: &mut dyn Sampler) -> Option<HitRecord<'_>> {
+        match self.hitable.hit(r, t_min, t_max, sampler) {
             Some(mut hit_rec) => {
                 hit_rec.normal = -hit_rec.normal;
                 Some(hit_rec)
@@ -146,6 +236,24 @@ impl<T: Hitable + Send + Sync> Hitable for FlipNormals<T> {
     fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
         self.hitable.bounding_box(t0, t1)
     }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3, sampler: &mut dyn Sampler) -> f32 {
+        self.hitable.pdf_value(origin, direction, sampler)
+    }
+
+    fn sample_direction(&self, origin: &Vec3, sampler: &mut dyn Sampler) -> Option<(Vec3, f32)> {
+        self.hitable.sample_direction(origin, sampler)
+    }
+
+    fn sample_surface(&self, sampler: &mut dyn Sampler) -> Option<(HitRecord<'_>, f32)> {
+        let (mut hit_rec, pdf) = self.hitable.sample_surface(sampler)?;
+        hit_rec.normal = -hit_rec.normal;
+        Some((hit_rec, pdf))
+    }
+
+    fn surface_pdf(&self, origin: &Vec3, direction: &Vec3, sampler: &mut dyn Sampler) -> f32 {
+        self.hitable.surface_pdf(origin, direction, sampler)
+    }
 }
 
 
@@ -161,9 +269,9 @@ impl<T: Hitable + Send + Sync> Translate<T> {
 }
 
 impl<T: Hitable + Send + Sync> Hitable for Translate<T> {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, sampler: &mut dyn Sampler) -> Option<HitRecord<'_>> {
         let moved_ray = Ray::new_at_time(r.origin() - &self.offset, r.direction().clone(), r.time());
-        match self.hitable.hit(&moved_ray, t_min, t_max) {
+        match self.hitable.hit(&moved_ray, t_min, t_max, sampler) {
             Some(mut hit_rec) => {
                 hit_rec.point += &self.offset;
                 Some(hit_rec)
@@ -173,10 +281,26 @@ impl<T: Hitable + Send + Sync> Hitable for Translate<T> {
     }
 
     fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
-        match self.hitable.bounding_box(t0, t1) {
-            Some(bbox) => Some(AABB::new(bbox.min() + &self.offset, bbox.max() + &self.offset)),
-            None => None,
-        }
+        self.hitable.bounding_box(t0, t1)
+            .map(|bbox| AABB::new(bbox.min() + &self.offset, bbox.max() + &self.offset))
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3, sampler: &mut dyn Sampler) -> f32 {
+        self.hitable.pdf_value(&(origin - &self.offset), direction, sampler)
+    }
+
+    fn sample_direction(&self, origin: &Vec3, sampler: &mut dyn Sampler) -> Option<(Vec3, f32)> {
+        self.hitable.sample_direction(&(origin - &self.offset), sampler)
+    }
+
+    fn sample_surface(&self, sampler: &mut dyn Sampler) -> Option<(HitRecord<'_>, f32)> {
+        let (mut hit_rec, pdf) = self.hitable.sample_surface(sampler)?;
+        hit_rec.point += &self.offset;
+        Some((hit_rec, pdf))
+    }
+
+    fn surface_pdf(&self, origin: &Vec3, direction: &Vec3, sampler: &mut dyn Sampler) -> f32 {
+        self.hitable.surface_pdf(&(origin - &self.offset), direction, sampler)
     }
 }
 
@@ -213,37 +337,42 @@ impl<T: Hitable + Send + Sync> Rotate<T> {
         let cos_theta = radians.cos();
         Self { hitable, axis, sin_theta, cos_theta }
     }
-}
-
-impl<T: Hitable + Send + Sync> Hitable for Rotate<T> {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let cos_t = self.cos_theta;
-        let sin_t = self.sin_theta;
 
-        let (a_idx, b_idx) = match self.axis {
+    // indices of the two components the rotation changes
+    fn rotated_axes(&self) -> (usize, usize) {
+        match self.axis {
             Axis::X => (1, 2),
             Axis::Y => (0, 2),
             Axis::Z => (0, 1),
-        };
-
-        let rotated_ray = {
-            let mut origin = r.origin().clone();
-            let mut direction = r.direction().clone();
-            origin[a_idx] = cos_t * r.origin()[a_idx] - sin_t * r.origin()[b_idx];
-            origin[b_idx] = sin_t * r.origin()[a_idx] + cos_t * r.origin()[b_idx];
-            direction[a_idx] = cos_t * r.direction()[a_idx] - sin_t * r.direction()[b_idx];
-            direction[b_idx] = sin_t * r.direction()[a_idx] + cos_t * r.direction()[b_idx];
-            Ray::new_at_time(origin, direction, r.time())
-        };
-
-        match self.hitable.hit(&rotated_ray, t_min, t_max) {
+        }
+    }
+
+    // point or direction rotated from the scene into the space of the wrapped hitable
+    fn to_object(&self, v: &Vec3) -> Vec3 {
+        let (a_idx, b_idx) = self.rotated_axes();
+        let mut rotated = v.clone();
+        rotated[a_idx] = self.cos_theta * v[a_idx] - self.sin_theta * v[b_idx];
+        rotated[b_idx] = self.sin_theta * v[a_idx] + self.cos_theta * v[b_idx];
+        rotated
+    }
+
+    // point or direction rotated from the space of the wrapped hitable into the scene
+    fn to_world(&self, v: &Vec3) -> Vec3 {
+        let (a_idx, b_idx) = self.rotated_axes();
+        let mut rotated = v.clone();
+        rotated[a_idx] = self.cos_theta * v[a_idx] + self.sin_theta * v[b_idx];
+        rotated[b_idx] = -self.sin_theta * v[a_idx] + self.cos_theta * v[b_idx];
+        rotated
+    }
+}
+
+impl<T: Hitable + Send + Sync> Hitable for Rotate<T> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, sampler: &mut dyn Sampler) -> Option<HitRecord<'_>> {
+        let rotated_ray = Ray::new_at_time(self.to_object(r.origin()), self.to_object(r.direction()), r.time());
+        match self.hitable.hit(&rotated_ray, t_min, t_max, sampler) {
             Some(mut hit_rec) => {
-                let p = &hit_rec.point.clone();
-                let n = &hit_rec.normal.clone();
-                hit_rec.point[a_idx] = cos_t * p[a_idx] + sin_t * p[b_idx];
-                hit_rec.point[b_idx] = -sin_t * p[a_idx] + cos_t * p[b_idx];
-                hit_rec.normal[a_idx] = cos_t * n[a_idx] + sin_t * n[b_idx];
-                hit_rec.normal[b_idx] = -sin_t * n[a_idx] + cos_t * n[b_idx];
+                hit_rec.point = self.to_world(&hit_rec.point);
+                hit_rec.normal = self.to_world(&hit_rec.normal);
                 Some(hit_rec)
             },
             None => None,
@@ -251,13 +380,10 @@ impl<T: Hitable + Send + Sync> Hitable for Rotate<T> {
     }
 
     fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
-        let bbox = match self.hitable.bounding_box(t0, t1) {
-            Some(bbox) => bbox,
-            None => return None,
-        };
+        let bbox = self.hitable.bounding_box(t0, t1)?;
 
-        let mut min = Vec3::new(std::f32::MAX, std::f32::MAX, std::f32::MAX);
-        let mut max = Vec3::new(std::f32::MIN, std::f32::MIN, std::f32::MIN);
+        let mut min = Vec3::new(f32::MAX, f32::MAX, f32::MAX);
+        let mut max = Vec3::new(f32::MIN, f32::MIN, f32::MIN);
 
         for i in 0..2 {
             let i = i as f32;
@@ -304,4 +430,57 @@ impl<T: Hitable + Send + Sync> Hitable for Rotate<T> {
 
         Some(AABB::new(min, max))
     }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3, sampler: &mut dyn Sampler) -> f32 {
+        self.hitable.pdf_value(&self.to_object(origin), &self.to_object(direction), sampler)
+    }
+
+    fn sample_direction(&self, origin: &Vec3, sampler: &mut dyn Sampler) -> Option<(Vec3, f32)> {
+        let (direction, pdf) = self.hitable.sample_direction(&self.to_object(origin), sampler)?;
+        Some((self.to_world(&direction), pdf))
+    }
+
+    fn sample_surface(&self, sampler: &mut dyn Sampler) -> Option<(HitRecord<'_>, f32)> {
+        let (mut hit_rec, pdf) = self.hitable.sample_surface(sampler)?;
+        hit_rec.point = self.to_world(&hit_rec.point);
+        hit_rec.normal = self.to_world(&hit_rec.normal);
+        Some((hit_rec, pdf))
+    }
+
+    fn surface_pdf(&self, origin: &Vec3, direction: &Vec3, sampler: &mut dyn Sampler) -> f32 {
+        self.hitable.surface_pdf(&self.to_object(origin), &self.to_object(direction), sampler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::DiffuseLight;
+    use crate::sampler::Independent;
+    use crate::texture;
+
+    #[test]
+    fn test_rotated_light_sampling() {
+        let light = || DiffuseLight::new(texture::Constant::from_rgb(1.0, 1.0, 1.0));
+        // an xy rectangle at z = 1, rotated a quarter turn about y onto the plane x = 1
+        let rotated = Rotate::new_y(Rectangle::new_xy((-1.0, 1.0), (0.0, 2.0), 1.0, light()), 90.0);
+        let expected = Rectangle::new_yz((0.0, 2.0), (-1.0, 1.0), 1.0, light());
+        let origin = Vec3::new(-2.0, 0.5, 0.3);
+        let mut sampler = Independent::new(0);
+
+        for _ in 0..16 {
+            let (direction, pdf) = rotated.sample_direction(&origin, &mut sampler).unwrap();
+            let hit = expected.hit(&Ray::new(origin.clone(), direction.clone()), 0.001, f32::MAX, &mut sampler);
+            assert!(hit.is_some(), "sampled direction {:?} misses the light", direction);
+            let expected_pdf = expected.pdf_value(&origin, &direction, &mut sampler);
+            assert!((pdf - expected_pdf).abs() < 1e-3 * expected_pdf, "pdf {} vs {}", pdf, expected_pdf);
+            assert!((rotated.pdf_value(&origin, &direction, &mut sampler) - expected_pdf).abs() < 1e-3 * expected_pdf);
+            let surface_pdf = expected.surface_pdf(&origin, &direction, &mut sampler);
+            assert!((rotated.surface_pdf(&origin, &direction, &mut sampler) - surface_pdf).abs() < 1e-3 * surface_pdf);
+
+            let (hit, _) = rotated.sample_surface(&mut sampler).unwrap();
+            assert!((hit.point[0] - 1.0).abs() < 1e-4, "sampled point {:?} is off the light", hit.point);
+            assert!((hit.normal[0].abs() - 1.0).abs() < 1e-4, "sampled normal {:?}", hit.normal);
+        }
+    }
 }
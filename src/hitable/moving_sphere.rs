@@ -1,8 +1,9 @@
+use crate::sampler::Sampler;
 use crate::vec3::Vec3;
 use crate::ray::Ray;
 use crate::material::Material;
 use crate::hitable::{HitRecord, Hitable};
-use crate::bvh::AABB;
+use crate::bvh::{Traversal, AABB};
 
 // could also be implemented in Sphere, with constant center between times
 
@@ -32,11 +33,12 @@ impl<M: Material + Clone> MovingSphere<M> {
 }
 
 impl<M: Material + Clone> Hitable for MovingSphere<M> {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, _sampler: &mut dyn Sampler) -> Option<HitRecord<'_>> {
+        Traversal::test_primitive();
         let time = r.time();
         let oc = r.origin() - self.center(time); // vector from ray source to sphere center
-        let a = r.direction().dot(&r.direction());
-        let b = oc.dot(&r.direction());
+        let a = r.direction().dot(r.direction());
+        let b = oc.dot(r.direction());
         let c = oc.dot(&oc) - self.radius.powi(2);
         let discriminant = b * b - a * c;
         if discriminant > 0.0 {
@@ -1,8 +1,9 @@
+use crate::sampler::Sampler;
 use crate::vec3::Vec3;
 use crate::ray::Ray;
 use crate::material::Material;
 use crate::hitable::{HitRecord, Hitable};
-use crate::bvh::AABB;
+use crate::bvh::{Traversal, AABB};
 
 #[derive(Clone)]
 enum Plane {
@@ -46,10 +47,33 @@ impl<M: Material> Rectangle<M> {
         };
         Self { a_bound, b_bound, a_idx, b_idx, k_idx, plane, plane_normal, k, material }
     }
+
+    fn area(&self) -> f32 {
+        (self.a_bound.1 - self.a_bound.0) * (self.b_bound.1 - self.b_bound.0)
+    }
+
+    // point at fractions (u, v) of the way across the rectangle
+    fn point_at(&self, (u, v): (f32, f32)) -> Vec3 {
+        let mut point = Vec3::zeros();
+        point[self.a_idx] = self.a_bound.0 + u * (self.a_bound.1 - self.a_bound.0);
+        point[self.b_idx] = self.b_bound.0 + v * (self.b_bound.1 - self.b_bound.0);
+        point[self.k_idx] = self.k;
+        point
+    }
+
+    // convert area pdf (1 / area) to a solid angle pdf as seen along direction
+    fn solid_angle_pdf(&self, direction: &Vec3, distance_squared: f32) -> f32 {
+        let cosine = (direction.dot(&self.plane_normal) / direction.length()).abs();
+        if cosine < 1e-6 {
+            return 0.0;
+        }
+        distance_squared / (cosine * self.area())
+    }
 }
 
 impl<M: Material> Hitable for Rectangle<M> {
-    fn hit(&self, r: &Ray, t0: f32, t1: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t0: f32, t1: f32, _sampler: &mut dyn Sampler) -> Option<HitRecord<'_>> {
+        Traversal::test_primitive();
         let origin = r.origin();
         let direction = r.direction();
 
@@ -93,4 +117,37 @@ impl<M: Material> Hitable for Rectangle<M> {
         };
         Some(AABB::new(min, max))
     }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3, sampler: &mut dyn Sampler) -> f32 {
+        match self.hit(&Ray::new(origin.clone(), direction.clone()), 0.001, f32::MAX, sampler) {
+            Some(hit_rec) => {
+                let distance_squared = hit_rec.t.powi(2) * direction.squared_length();
+                self.solid_angle_pdf(direction, distance_squared)
+            },
+            None => 0.0,
+        }
+    }
+
+    fn sample_direction(&self, origin: &Vec3, sampler: &mut dyn Sampler) -> Option<(Vec3, f32)> {
+        let direction = self.point_at(sampler.get_2d()) - origin;
+        let pdf = self.solid_angle_pdf(&direction, direction.squared_length());
+        if pdf > 0.0 {
+            Some((direction, pdf))
+        } else {
+            None
+        }
+    }
+
+    fn sample_surface(&self, sampler: &mut dyn Sampler) -> Option<(HitRecord<'_>, f32)> {
+        let (u, v) = sampler.get_2d();
+        let hit_rec = HitRecord::new_with_uv(0.0, self.point_at((u, v)), self.plane_normal.clone(), &self.material, u, v);
+        Some((hit_rec, 1.0 / self.area()))
+    }
+
+    fn surface_pdf(&self, origin: &Vec3, direction: &Vec3, sampler: &mut dyn Sampler) -> f32 {
+        match self.hit(&Ray::new(origin.clone(), direction.clone()), 0.001, f32::MAX, sampler) {
+            Some(_) => 1.0 / self.area(),
+            None => 0.0,
+        }
+    }
 }
@@ -1,8 +1,10 @@
+use crate::sampler::Sampler;
 use crate::vec3::Vec3;
+use crate::utils::{self, Onb};
 use crate::ray::Ray;
 use crate::material::Material;
 use crate::hitable::{HitRecord, Hitable};
-use crate::bvh::AABB;
+use crate::bvh::{Traversal, AABB};
 
 #[derive(Clone)]
 pub struct Sphere<M: Material + Clone> {
@@ -20,6 +22,21 @@ impl<M: Material + Clone> Sphere<M> {
         (p - &self.center) / self.radius
     }
 
+    // solid angle of the cone of directions from origin which hit the sphere, or None if origin is
+    // inside it
+    fn cone_solid_angle(&self, origin: &Vec3) -> Option<f32> {
+        let distance_squared = (&self.center - origin).squared_length();
+        let sin_squared = self.radius.powi(2) / distance_squared;
+        if sin_squared >= 1.0 {
+            return None;
+        }
+
+        // 1 - cos(theta_max), rearranged to avoid cancellation for small/distant spheres
+        let cos_theta_max = (1.0 - sin_squared).sqrt();
+        let one_minus_cos = sin_squared / (1.0 + cos_theta_max);
+        Some(2.0 * std::f32::consts::PI * one_minus_cos)
+    }
+
     fn get_uv(&self, point: &Vec3) -> (f32, f32) {
         let point = (point - &self.center) / self.radius;
         let phi = point[2].atan2(point[0]);
@@ -32,10 +49,11 @@ impl<M: Material + Clone> Sphere<M> {
 }
 
 impl<M: Material + Clone> Hitable for Sphere<M> {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32, _sampler: &mut dyn Sampler) -> Option<HitRecord<'_>> {
+        Traversal::test_primitive();
         let oc = r.origin() - &self.center; // vector from ray source to sphere center
-        let a = r.direction().dot(&r.direction());
-        let b = oc.dot(&r.direction());
+        let a = r.direction().dot(r.direction());
+        let b = oc.dot(r.direction());
         let c = oc.dot(&oc) - self.radius.powi(2);
         let discriminant = b * b - a * c;
         if discriminant > 0.0 {
@@ -63,4 +81,43 @@ impl<M: Material + Clone> Hitable for Sphere<M> {
         let r = Vec3::new(self.radius, self.radius, self.radius);
         Some(AABB::new(&self.center - &r, &self.center + &r))
     }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3, sampler: &mut dyn Sampler) -> f32 {
+        let r = Ray::new(origin.clone(), direction.clone());
+        if self.hit(&r, 0.001, f32::MAX, sampler).is_none() {
+            return 0.0;
+        }
+
+        match self.cone_solid_angle(origin) {
+            Some(solid_angle) => 1.0 / solid_angle,
+            None => 0.0,
+        }
+    }
+
+    // sample direction uniformly from the cone of directions subtended by the sphere
+    fn sample_direction(&self, origin: &Vec3, sampler: &mut dyn Sampler) -> Option<(Vec3, f32)> {
+        let solid_angle = self.cone_solid_angle(origin)?;
+        let direction = &self.center - origin;
+        let distance_squared = direction.squared_length();
+        let cos_theta_max = (1.0 - self.radius.powi(2) / distance_squared).sqrt();
+        let uvw = Onb::from_w(&direction);
+        let direction = uvw.local(&utils::uniform_cone(sampler.get_2d(), cos_theta_max));
+        Some((direction, 1.0 / solid_angle))
+    }
+
+    fn sample_surface(&self, sampler: &mut dyn Sampler) -> Option<(HitRecord<'_>, f32)> {
+        let normal = utils::uniform_sphere(sampler.get_2d());
+        let point = &self.center + self.radius * &normal;
+        let (u, v) = self.get_uv(&point);
+        let area = 4.0 * std::f32::consts::PI * self.radius.powi(2);
+        Some((HitRecord::new_with_uv(0.0, point, normal, &self.material, u, v), 1.0 / area))
+    }
+
+    fn surface_pdf(&self, origin: &Vec3, direction: &Vec3, sampler: &mut dyn Sampler) -> f32 {
+        let r = Ray::new(origin.clone(), direction.clone());
+        match self.hit(&r, 0.001, f32::MAX, sampler) {
+            Some(_) => 1.0 / (4.0 * std::f32::consts::PI * self.radius.powi(2)),
+            None => 0.0,
+        }
+    }
 }
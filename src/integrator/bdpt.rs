@@ -0,0 +1,432 @@
+use crate::sampler::Sampler;
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::hitable::{HitRecord, Hitable};
+use crate::material::ScatterRecord;
+use crate::scenes::Scene;
+use crate::camera::Camera;
+use crate::stats;
+use crate::integrator::{Bounce, PathLength, PathObserver};
+
+/// Bidirectional path tracer (Veach 1997). A subpath is traced from the camera and another from
+/// a point sampled on one of the scene's lights, and every prefix of one is connected to every
+/// prefix of the other. Each path found this way could have been found by the other connection
+/// strategies of the same length, so they're combined with multiple importance sampling (power
+/// heuristic). Connections made directly to the camera land on other pixels, so are passed to
+/// `observer` as splats.
+///
+/// Light from the environment is only found by camera subpaths leaving the scene.
+pub fn colour<T: Hitable + Send + Sync, O: PathObserver>(r: &Ray, scene: &Scene<T>, length: &PathLength, observer: &mut O, sampler: &mut dyn Sampler) -> Vec3 {
+    let mut radiance = Vec3::zeros();
+
+    let mut camera_path = vec![Vertex::camera(r.origin().clone())];
+    let pdf_dir = scene.camera.pdf_direction(r);
+    let escaped = random_walk(scene, r.clone(), Vec3::ones(), pdf_dir, length.max_depth + 2, length, &mut camera_path, sampler);
+    let light_path = light_subpath(scene, r, length, sampler);
+
+    // paths leaving the scene can't be found any other way
+    if let Some((escaped, beta)) = escaped {
+        let depth = camera_path.len() - 1;
+        let background = escaped.colour(scene.background(escaped.direction()));
+        let contribution = length.clamp(beta * background, depth);
+        observer.escaped(depth, &escaped, &contribution);
+        radiance += contribution;
+    }
+
+    for t in 1..=camera_path.len() {
+        let mut vertex_contribution = Vec3::zeros();
+        for s in 0..=light_path.len() {
+            // directly visible lights are only found by camera rays
+            if (s == 1 && t == 1) || s + t < 2 || s + t - 2 > length.max_depth {
+                continue;
+            }
+
+            if t == 1 {
+                if let Some((film_pos, contribution)) = connect_to_camera(scene, &light_path, s, r, sampler) {
+                    observer.splat(film_pos, &length.clamp(contribution, s - 1));
+                }
+            } else {
+                let contribution = connect(scene, &light_path, &camera_path, s, t, r, sampler);
+                vertex_contribution += length.clamp(contribution, s + t - 2);
+            }
+        }
+
+        if t > 1 {
+            let vertex = &camera_path[t - 1];
+            if let (Some(ref hit), Some(ref ray)) = (&vertex.hit, &vertex.incoming) {
+                observer.bounce(&Bounce { depth: t - 2, ray, hit, throughput: &vertex.beta, contribution: &vertex_contribution,
+                                          scatter: vertex.scatter.as_ref() });
+            }
+        }
+        radiance += vertex_contribution;
+    }
+
+    radiance
+}
+
+// vertex of a camera or light subpath
+struct Vertex<'a> {
+    point: Vec3,
+    // geometric normal, zero for the camera and for scattering within volumes
+    normal: Vec3,
+    // surface hit, `None` for the camera
+    hit: Option<HitRecord<'a>>,
+    // ray which arrived at the vertex (along the subpath), `None` for the ends of subpaths
+    incoming: Option<Ray>,
+    // how the subpath continued from the vertex, kept for observers
+    scatter: Option<ScatterRecord>,
+    // emitted importance or radiance carried to here, divided by the pdf of sampling the subpath
+    beta: Vec3,
+    // area pdfs of sampling this vertex from its predecessor, and from its successor if the
+    // subpath had been traced in the other direction
+    pdf_fwd: f32,
+    pdf_rev: f32,
+    // whether scattering at the vertex is specular, so that it can't be connected to
+    delta: bool,
+    // whether the vertex starts a light subpath
+    is_light: bool,
+    // whether the subpath only carries its hero wavelength from here on
+    hero_only: bool,
+}
+
+impl<'a> Vertex<'a> {
+    fn camera(point: Vec3) -> Self {
+        Self { point, normal: Vec3::zeros(), hit: None, incoming: None, scatter: None, beta: Vec3::ones(),
+               pdf_fwd: 0.0, pdf_rev: 0.0, delta: false, is_light: false, hero_only: false }
+    }
+
+    fn light(hit: HitRecord<'a>, beta: Vec3, pdf_fwd: f32) -> Self {
+        Self { point: hit.point.clone(), normal: hit.normal.clone(), hit: Some(hit), incoming: None, scatter: None, beta,
+               pdf_fwd, pdf_rev: 0.0, delta: false, is_light: true, hero_only: false }
+    }
+
+    fn surface(hit: HitRecord<'a>, incoming: Ray, beta: Vec3, hero_only: bool) -> Self {
+        Self { point: hit.point.clone(), normal: hit.normal.clone(), hit: Some(hit), incoming: Some(incoming), scatter: None,
+               beta, pdf_fwd: 0.0, pdf_rev: 0.0, delta: false, is_light: false, hero_only }
+    }
+
+    fn on_surface(&self) -> bool {
+        self.normal.squared_length() > 0.0
+    }
+
+    // converts a solid angle pdf of sampling the direction to `next` from here into an area pdf
+    // at `next`
+    fn convert_density(&self, pdf: f32, next: &Vertex) -> f32 {
+        let w = &next.point - &self.point;
+        let distance_squared = w.squared_length();
+        if distance_squared == 0.0 {
+            return 0.0;
+        }
+        let mut pdf = pdf / distance_squared;
+        if next.on_surface() {
+            pdf *= next.normal.dot(&w).abs() / distance_squared.sqrt();
+        }
+        pdf
+    }
+
+    // BSDF times cosine for light arriving from `from` and leaving towards `to` (or vice versa,
+    // for light subpaths), in the form of radiance carried by `r`
+    fn bsdf(&self, from: &Vec3, to: &Vec3, r: &Ray) -> Vec3 {
+        match self.hit {
+            Some(ref hit) if !self.is_light => {
+                let incoming = Ray::new_at_time(from.clone(), &self.point - from, r.time());
+                let scattered = Ray::new_at_time(self.point.clone(), to - &self.point, r.time());
+                r.colour(hit.material.bsdf(&incoming, hit, &scattered))
+            },
+            _ => Vec3::zeros(),
+        }
+    }
+
+    // area pdf at `next` of sampling it from this vertex, having arrived from `prev`
+    fn pdf(&self, camera: &Camera, prev: Option<&Vertex>, next: &Vertex) -> f32 {
+        if self.is_light {
+            return self.pdf_light(next);
+        }
+        let direction = &next.point - &self.point;
+        let pdf = match (&self.hit, prev) {
+            (None, _) => camera.pdf_direction(&Ray::new(self.point.clone(), direction.clone())),
+            (Some(hit), Some(prev)) => {
+                let incoming = Ray::new(prev.point.clone(), &self.point - &prev.point);
+                hit.material.scattering_pdf(&incoming, hit, &Ray::new(self.point.clone(), direction.clone()))
+            },
+            (Some(_), None) => 0.0,
+        };
+        self.convert_density(pdf, next)
+    }
+
+    // area pdf at `next` of emitting light towards it from this vertex, were it on a light
+    fn pdf_light(&self, next: &Vertex) -> f32 {
+        let w = &next.point - &self.point;
+        let cosine = self.normal.dot(&w).abs() / w.length();
+        self.convert_density(cosine / (2.0 * std::f32::consts::PI), next)
+    }
+
+    // area pdf of starting a light subpath at this vertex, reached from `prev`
+    fn pdf_light_origin<T: Hitable + Send + Sync>(&self, scene: &Scene<T>, prev: &Vertex, sampler: &mut dyn Sampler) -> f32 {
+        scene.lights.surface_pdf(&prev.point, &(&self.point - &prev.point), sampler)
+    }
+
+    // radiance emitted from the vertex, in the form of radiance carried by `r`
+    fn emitted(&self, r: &Ray) -> Vec3 {
+        match self.hit {
+            Some(ref hit) => r.colour(hit.material.emitted(hit.u, hit.v, &hit.point)),
+            None => Vec3::zeros(),
+        }
+    }
+}
+
+// traces a subpath from a point sampled on one of the scene's lights
+fn light_subpath<'a, T: Hitable + Send + Sync>(scene: &'a Scene<T>, r: &Ray, length: &PathLength, sampler: &mut dyn Sampler) -> Vec<Vertex<'a>> {
+    let light = match scene.sample_light_ray(r.time(), r.wavelengths().cloned(), sampler) {
+        Some(light) => light,
+        None => return Vec::new(),
+    };
+    let mut path = vec![Vertex::light(light.hit, light.emitted, light.pdf_pos)];
+    random_walk(scene, light.ray, light.beta, light.pdf_dir, length.max_depth + 1, length, &mut path, sampler);
+    path
+}
+
+// extends a subpath along `ray`, which was sampled with solid angle pdf `pdf_fwd` and carries
+// `beta`, until it's absorbed or has `max_vertices` vertices. Returns the last ray if the subpath
+// left the scene, along with the beta it carried.
+#[allow(clippy::too_many_arguments)]
+fn random_walk<'a, T: Hitable + Send + Sync>(scene: &'a Scene<T>, mut ray: Ray, mut beta: Vec3, mut pdf_fwd: f32, max_vertices: usize,
+                                             length: &PathLength, path: &mut Vec<Vertex<'a>>, sampler: &mut dyn Sampler) -> Option<(Ray, Vec3)> {
+    let mut wavelengths = ray.wavelengths().cloned();
+    let mut hero_only = false;
+    // throughput of the walk alone, for Russian roulette, as beta includes emission for light subpaths
+    let mut throughput = Vec3::ones();
+    let mut depth = 0;
+
+    loop {
+        let hit = match scene.hit(&ray, 0.001, f32::MAX, sampler) {
+            Some(hit) => hit,
+            None => return Some((ray, beta)),
+        };
+
+        let mut vertex = Vertex::surface(hit, ray.clone(), beta.clone(), hero_only);
+        vertex.pdf_fwd = path[path.len() - 1].convert_density(pdf_fwd, &vertex);
+        path.push(vertex);
+        if path.len() >= max_vertices {
+            return None;
+        }
+
+        let n = path.len();
+        let hit = path[n - 1].hit.as_ref().unwrap();
+        stats::count_scatter(hit.material.type_name());
+        let srec = hit.material.scatter(&ray, hit, sampler)?;
+
+        let attenuation = ray.colour(srec.attenuation.clone());
+        beta = beta * &attenuation;
+        throughput = throughput * &attenuation;
+        if srec.wavelength_dependent {
+            if let Some(ref mut wavelengths) = wavelengths {
+                wavelengths.terminate_secondary(&mut beta);
+                hero_only = true;
+            }
+        }
+
+        let pdf_rev = match srec.pdf {
+            Some(pdf) => {
+                pdf_fwd = pdf;
+                // pdf of scattering back along the incoming ray, for light arriving along the scattered one
+                let hit = path[n - 1].hit.as_ref().unwrap();
+                let reversed = Ray::new_at_time(&hit.point + srec.ray.direction(), -srec.ray.direction().clone(), ray.time());
+                let back = Ray::new_at_time(hit.point.clone(), -ray.direction().clone(), ray.time());
+                hit.material.scattering_pdf(&reversed, hit, &back)
+            },
+            None => {
+                path[n - 1].delta = true;
+                pdf_fwd = 0.0;
+                0.0
+            },
+        };
+        path[n - 2].pdf_rev = path[n - 1].convert_density(pdf_rev, &path[n - 2]);
+
+        match length.roulette(&throughput, depth, sampler) {
+            Some(survival) => {
+                beta /= survival;
+                throughput /= survival;
+            },
+            None => {
+                path[n - 1].scatter = Some(srec);
+                return None;
+            },
+        }
+
+        ray = srec.ray.clone().with_wavelengths(wavelengths);
+        path[n - 1].scatter = Some(srec);
+        depth += 1;
+    }
+}
+
+// whether the straight line between two points is unoccluded
+fn visible<T: Hitable + Send + Sync>(scene: &Scene<T>, a: &Vec3, b: &Vec3, time: f32, sampler: &mut dyn Sampler) -> bool {
+    let direction = b - a;
+    let distance = direction.length();
+    let shadow_ray = Ray::new_at_time(a.clone(), direction / distance, time);
+    scene.hit(&shadow_ray, 0.001, distance - 0.001, sampler).is_none()
+}
+
+// scales contributions of paths where both subpaths carry only their hero wavelength, which would
+// otherwise have been scaled up for it twice
+fn hero_scale(a: &Vertex, b: &Vertex) -> f32 {
+    if a.hero_only && b.hero_only {
+        1.0 / 3.0
+    } else {
+        1.0
+    }
+}
+
+// contribution of the path made from the first `s` vertices of the light subpath and the first
+// `t` (at least 2) of the camera subpath, weighted by MIS
+fn connect<T: Hitable + Send + Sync>(scene: &Scene<T>, light_path: &[Vertex], camera_path: &[Vertex], s: usize, t: usize, r: &Ray,
+                                     sampler: &mut dyn Sampler) -> Vec3 {
+    let pt = &camera_path[t - 1];
+    let pt_prev = &camera_path[t - 2].point;
+
+    if s == 0 {
+        // camera subpath found a light by itself
+        let emitted = pt.emitted(r);
+        if emitted.squared_length() == 0.0 {
+            return Vec3::zeros();
+        }
+        let contribution = &pt.beta * emitted;
+        return mis_weight(scene, light_path, camera_path, s, t, None, sampler) * contribution;
+    }
+
+    if pt.delta {
+        return Vec3::zeros();
+    }
+
+    if s == 1 {
+        // sample a new point on a light, rather than using the light subpath's
+        let (hit, pdf_pos) = match scene.lights.sample_surface(sampler) {
+            Some(sample) if sample.1 > 0.0 => sample,
+            _ => return Vec3::zeros(),
+        };
+        let light = Vertex::light(hit, Vec3::zeros(), pdf_pos);
+        let emitted = light.emitted(r);
+        let f = pt.bsdf(pt_prev, &light.point, r);
+        if emitted.squared_length() == 0.0 || f.squared_length() == 0.0 {
+            return Vec3::zeros();
+        }
+
+        let w = &light.point - &pt.point;
+        let distance_squared = w.squared_length();
+        let cosine = light.normal.dot(&w).abs() / distance_squared.sqrt();
+        let contribution = &pt.beta * &f * emitted * (cosine / (distance_squared * pdf_pos));
+        if contribution.squared_length() == 0.0 || !visible(scene, &pt.point, &light.point, r.time(), sampler) {
+            return Vec3::zeros();
+        }
+        return mis_weight(scene, light_path, camera_path, s, t, Some(&light), sampler) * contribution;
+    }
+
+    let qs = &light_path[s - 1];
+    if qs.delta {
+        return Vec3::zeros();
+    }
+    let f_light = qs.bsdf(&light_path[s - 2].point, &pt.point, r);
+    let f_camera = pt.bsdf(pt_prev, &qs.point, r);
+    let distance_squared = (&qs.point - &pt.point).squared_length();
+    let contribution = &qs.beta * &f_light * (&pt.beta * &f_camera) * (hero_scale(qs, pt) / distance_squared);
+    if contribution.squared_length() == 0.0 || !visible(scene, &qs.point, &pt.point, r.time(), sampler) {
+        return Vec3::zeros();
+    }
+    mis_weight(scene, light_path, camera_path, s, t, None, sampler) * contribution
+}
+
+// film position and MIS weighted contribution of the path made by connecting the first `s`
+// vertices of the light subpath directly to the camera
+fn connect_to_camera<T: Hitable + Send + Sync>(scene: &Scene<T>, light_path: &[Vertex], s: usize, r: &Ray,
+                                               sampler: &mut dyn Sampler) -> Option<((f32, f32), Vec3)> {
+    let qs = &light_path[s - 1];
+    if qs.delta || qs.is_light {
+        return None;
+    }
+    let (lens_point, film_pos, importance) = scene.camera.sample_importance(&qs.point, sampler)?;
+    let camera = Vertex::camera(lens_point);
+
+    let f = qs.bsdf(&light_path[s - 2].point, &camera.point, r);
+    let contribution = &qs.beta * f * importance;
+    if contribution.squared_length() == 0.0 || !visible(scene, &qs.point, &camera.point, r.time(), sampler) {
+        return None;
+    }
+    let weight = mis_weight(scene, light_path, std::slice::from_ref(&camera), s, 1, Some(&camera), sampler);
+    Some((film_pos, weight * contribution))
+}
+
+// area pdfs along a path, as seen by one of its connection strategies
+#[derive(Clone, Copy)]
+struct Pdfs {
+    fwd: f32,
+    rev: f32,
+    delta: bool,
+}
+
+// MIS weight (power heuristic) of the strategy connecting `s` light subpath vertices to `t`
+// camera subpath vertices, relative to every other strategy that could have made the same path.
+// `sampled` replaces the subpath's end vertex where one was sampled separately for the connection
+// (for s or t of 1).
+fn mis_weight<T: Hitable + Send + Sync>(scene: &Scene<T>, light_path: &[Vertex], camera_path: &[Vertex], s: usize, t: usize,
+                                        sampled: Option<&Vertex>, sampler: &mut dyn Sampler) -> f32 {
+    if s + t == 2 {
+        return 1.0;
+    }
+
+    let qs = if s == 1 { sampled } else { s.checked_sub(1).map(|i| &light_path[i]) };
+    let pt = if t == 1 { sampled } else { Some(&camera_path[t - 1]) };
+    let qs_prev = s.checked_sub(2).map(|i| &light_path[i]);
+    let pt_prev = t.checked_sub(2).map(|i| &camera_path[i]);
+
+    let pdfs = |v: &Vertex| Pdfs { fwd: v.pdf_fwd, rev: v.pdf_rev, delta: v.delta };
+    let mut light: Vec<Pdfs> = light_path[..s].iter().map(pdfs).collect();
+    let mut camera: Vec<Pdfs> = camera_path[..t].iter().map(pdfs).collect();
+    if s == 1 {
+        light[0] = pdfs(sampled.unwrap());
+    }
+
+    // reverse pdfs of the vertices either side of the connection change with the strategy
+    let pt = pt.unwrap();
+    camera[t - 1].delta = false;
+    camera[t - 1].rev = match qs {
+        Some(qs) => qs.pdf(&scene.camera, qs_prev, pt),
+        None => pt.pdf_light_origin(scene, pt_prev.unwrap(), sampler),
+    };
+    if let Some(pt_prev) = pt_prev {
+        camera[t - 2].rev = match qs {
+            Some(qs) => pt.pdf(&scene.camera, Some(qs), pt_prev),
+            None => pt.pdf_light(pt_prev),
+        };
+    }
+    if let Some(qs) = qs {
+        light[s - 1].delta = false;
+        light[s - 1].rev = pt.pdf(&scene.camera, pt_prev, qs);
+    }
+    if let (Some(qs), Some(qs_prev)) = (qs, qs_prev) {
+        light[s - 2].rev = qs.pdf(&scene.camera, Some(pt), qs_prev);
+    }
+
+    // specular vertices have no pdf, but cancel out of the ratios
+    let remap = |pdf: f32| if pdf != 0.0 { pdf } else { 1.0 };
+    let mut sum_ratios = 0.0;
+
+    let mut ratio = 1.0;
+    for i in (1..t).rev() {
+        ratio *= remap(camera[i].rev) / remap(camera[i].fwd);
+        if !camera[i].delta && !camera[i - 1].delta {
+            sum_ratios += ratio * ratio;
+        }
+    }
+
+    let mut ratio = 1.0;
+    for i in (0..s).rev() {
+        ratio *= remap(light[i].rev) / remap(light[i].fwd);
+        let prev_delta = i > 0 && light[i - 1].delta;
+        if !light[i].delta && !prev_delta {
+            sum_ratios += ratio * ratio;
+        }
+    }
+
+    1.0 / (1.0 + sum_ratios)
+}
@@ -0,0 +1,78 @@
+use crate::sampler::Sampler;
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::hitable::{HitRecord, Hitable};
+use crate::scenes::Scene;
+use crate::bvh::Traversal;
+use crate::utils::{self, Onb};
+use crate::integrator::{Integrator, Precomputed};
+
+/// Value of one of the debug integrators along ray `r`, from the first surface it hits (black
+/// where it hits nothing).
+pub fn colour<T: Hitable + Send + Sync>(integrator: Integrator, r: &Ray, scene: &Scene<T>, precomputed: Precomputed,
+                                        sampler: &mut dyn Sampler) -> Vec3 {
+    let (hit, traversal) = Traversal::measure(|| scene.hit(r, 0.001, f32::MAX, sampler));
+    let count = match integrator {
+        Integrator::BvhNodes => Some(traversal.nodes),
+        Integrator::PrimitiveTests => Some(traversal.primitives),
+        _ => None,
+    };
+    if let Some(count) = count {
+        return Vec3::new(count as f32, count as f32, count as f32);
+    }
+
+    let hit = match hit {
+        Some(hit) => hit,
+        None => return Vec3::zeros(),
+    };
+    match integrator {
+        Integrator::Normals => 0.5 * (facing(r, &hit) + 1.0),
+        Integrator::Uv => Vec3::new(hit.u, hit.v, 0.0),
+        Integrator::Distance => {
+            let distance = hit.t * r.direction().length();
+            Vec3::new(distance, distance, distance)
+        },
+        Integrator::AmbientOcclusion => {
+            let radius = precomputed.ao_radius.unwrap_or_else(|| default_ao_radius(scene));
+            let visible = ambient_occlusion(r, &hit, scene, radius, sampler);
+            Vec3::new(visible, visible, visible)
+        },
+        _ => unreachable!("{} isn't a debug integrator", integrator),
+    }
+}
+
+/// Distance within which surfaces occlude each other for the ambient occlusion integrator, unless
+/// set: 10% of the size of the scene, or unlimited if it isn't bounded.
+pub fn default_ao_radius<T: Hitable + Send + Sync>(scene: &Scene<T>) -> f32 {
+    match scene.hitables.bounding_box(0.0, 1.0) {
+        Some(bbox) => 0.1 * (bbox.max() - bbox.min()).length(),
+        None => f32::INFINITY,
+    }
+}
+
+// surface normal, flipped to face back along `r`
+fn facing(r: &Ray, hit: &HitRecord) -> Vec3 {
+    if hit.normal.dot(r.direction()) > 0.0 {
+        -hit.normal.clone()
+    } else {
+        hit.normal.clone()
+    }
+}
+
+// estimate of the (cosine weighted) fraction of the hemisphere above `hit` which isn't occluded
+// within `radius`: 1 if a cosine distributed ray escapes, else 0. Points within media have no
+// surface, so look in every direction.
+fn ambient_occlusion<T: Hitable + Send + Sync>(r: &Ray, hit: &HitRecord, scene: &Scene<T>, radius: f32,
+                                               sampler: &mut dyn Sampler) -> f32 {
+    let direction = if hit.normal.squared_length() > 0.0 {
+        Onb::from_w(&facing(r, hit)).local(&utils::cosine_hemisphere(sampler.get_2d()))
+    } else {
+        utils::uniform_sphere(sampler.get_2d())
+    };
+    let direction = direction.to_unit_vector();
+    let occlusion_ray = Ray::new_at_time(hit.point.clone(), direction, r.time());
+    match scene.hit(&occlusion_ray, 0.001, radius, sampler) {
+        Some(_) => 0.0,
+        None => 1.0,
+    }
+}
@@ -0,0 +1,113 @@
+use crate::sampler::Sampler;
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::hitable::{HitRecord, Hitable};
+use crate::scenes::Scene;
+use crate::integrator::{Bounce, CausticPath, IncidentRadiance, PathLength, PathObserver, Precomputed};
+
+/// Path tracer with next event estimation: at each non-specular bounce a ray is traced towards a
+/// point sampled on one of the scene's lights or its environment. Light reached by the scattered
+/// ray is then not counted, so that it isn't included twice.
+pub fn colour<T: Hitable + Send + Sync, O: PathObserver>(r: &Ray, scene: &Scene<T>, length: &PathLength, precomputed: Precomputed,
+                                                         observer: &mut O, sampler: &mut dyn Sampler) -> Vec3 {
+    let mut radiance = Vec3::zeros();
+    let mut throughput = Vec3::ones();
+    let mut ray = r.clone();
+    let mut wavelengths = r.wavelengths().cloned();
+    let mut depth = 0;
+    let mut caustic = CausticPath::default();
+    let mut incident = IncidentRadiance::default();
+    let mut count_emitted = true;
+
+    loop {
+        let hit = match scene.hit(&ray, 0.001, f32::MAX, sampler) {
+            Some(hit) => hit,
+            None => {
+                if count_emitted {
+                    let contribution = length.clamp(&throughput * ray.colour(scene.background(ray.direction())), depth);
+                    observer.escaped(depth, &ray, &contribution);
+                    incident.add(&contribution);
+                    radiance += contribution;
+                }
+                break;
+            },
+        };
+
+        let mut contribution = if count_emitted && caustic.counts_emission() {
+            length.clamp(&throughput * ray.colour(hit.material.emitted(hit.u, hit.v, &hit.point)), depth)
+        } else {
+            Vec3::zeros()
+        };
+
+        let srec = if depth < length.max_depth {
+            precomputed.scatter(&ray, &hit, sampler).map(|mut srec| {
+                srec.attenuation = ray.colour(srec.attenuation);
+                srec
+            })
+        } else {
+            None
+        };
+
+        if let Some(ref srec) = srec {
+            // with no lights to sample, fall back to finding them by chance
+            let sample_direct = !srec.is_specular() && scene.has_emitters();
+            if sample_direct {
+                // light sampled from here has bounced once more than light hit here
+                contribution += length.clamp(&throughput * sample_lights(&ray, &hit, scene, sampler), depth + 1);
+            }
+            count_emitted = !sample_direct;
+            contribution += length.clamp(&throughput * caustic.gather(precomputed.caustics, &ray, &hit, srec), depth + 2);
+        }
+
+        observer.bounce(&Bounce { depth, ray: &ray, hit: &hit, throughput: &throughput, contribution: &contribution, scatter: srec.as_ref() });
+        incident.add(&contribution);
+        radiance += contribution;
+
+        let srec = match srec {
+            Some(srec) => srec,
+            None => break,
+        };
+
+        throughput = throughput * &srec.attenuation;
+        if srec.wavelength_dependent {
+            if let Some(ref mut wavelengths) = wavelengths {
+                wavelengths.terminate_secondary(&mut throughput);
+            }
+        }
+        match length.roulette(&throughput, depth, sampler) {
+            Some(survival) => throughput /= survival,
+            None => break,
+        }
+
+        incident.scattered(&hit, &srec, &throughput);
+        ray = srec.ray.with_wavelengths(wavelengths);
+        depth += 1;
+    }
+
+    incident.report(observer);
+    radiance
+}
+
+/// Estimate of light arriving directly from the scene's lights (or environment) and scattered
+/// back along `r`.
+pub fn sample_lights<T: Hitable + Send + Sync>(r: &Ray, hit: &HitRecord, scene: &Scene<T>, sampler: &mut dyn Sampler) -> Vec3 {
+    let (direction, light_pdf) = match scene.sample_emitters(&hit.point, sampler) {
+        Some(sample) => sample,
+        None => return Vec3::zeros(),
+    };
+
+    let shadow_ray = Ray::new_at_time(hit.point.clone(), direction, r.time());
+    let bsdf = r.colour(hit.material.bsdf(r, hit, &shadow_ray));
+    if light_pdf <= 0.0 || bsdf.squared_length() == 0.0 {
+        return Vec3::zeros();
+    }
+
+    // whatever is hit first is either the light, or something occluding it
+    match scene.hit(&shadow_ray, 0.001, f32::MAX, sampler) {
+        Some(light_hit) => {
+            let emitted = r.colour(light_hit.material.emitted(light_hit.u, light_hit.v, &light_hit.point));
+            &bsdf * &emitted * (1.0 / light_pdf)
+        },
+        None => &bsdf * &r.colour(scene.background(shadow_ray.direction())) * (1.0 / light_pdf),
+    }
+}
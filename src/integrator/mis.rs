@@ -0,0 +1,130 @@
+use crate::sampler::Sampler;
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::hitable::{HitRecord, Hitable};
+use crate::scenes::Scene;
+use crate::integrator::{Bounce, CausticPath, IncidentRadiance, PathLength, PathObserver, Precomputed};
+
+/// Path tracer combining material and light sampling with multiple importance sampling. Emission
+/// found by each material sampled ray is weighted against the pdf of sampling it from the lights.
+pub fn colour<T: Hitable + Send + Sync, O: PathObserver>(r: &Ray, scene: &Scene<T>, length: &PathLength, precomputed: Precomputed,
+                                                         observer: &mut O, sampler: &mut dyn Sampler) -> Vec3 {
+    let mut radiance = Vec3::zeros();
+    let mut throughput = Vec3::ones();
+    let mut ray = r.clone();
+    let mut wavelengths = r.wavelengths().cloned();
+    let mut depth = 0;
+    let mut caustic = CausticPath::default();
+    let mut incident = IncidentRadiance::default();
+
+    // pdf of the material sampling which generated ray (None for camera rays and specular
+    // bounces, which light sampling can't generate)
+    let mut scattering_pdf: Option<f32> = None;
+
+    loop {
+        let hit = match scene.hit(&ray, 0.001, f32::MAX, sampler) {
+            Some(hit) => hit,
+            None => {
+                let mut contribution = &throughput * ray.colour(scene.background(ray.direction()));
+                if let Some(scattering_pdf) = scattering_pdf {
+                    contribution *= power_heuristic(scattering_pdf, scene.emitters_pdf(ray.origin(), ray.direction(), sampler));
+                }
+                let contribution = length.clamp(contribution, depth);
+                observer.escaped(depth, &ray, &contribution);
+                incident.add(&contribution);
+                radiance += contribution;
+                break;
+            },
+        };
+
+        let mut contribution = if caustic.counts_emission() {
+            &throughput * ray.colour(hit.material.emitted(hit.u, hit.v, &hit.point))
+        } else {
+            Vec3::zeros()
+        };
+        if let Some(scattering_pdf) = scattering_pdf {
+            let light_pdf = scene.emitters_pdf(ray.origin(), ray.direction(), sampler);
+            contribution *= power_heuristic(scattering_pdf, light_pdf);
+        }
+        let mut contribution = length.clamp(contribution, depth);
+
+        let srec = if depth < length.max_depth {
+            precomputed.scatter(&ray, &hit, sampler).map(|mut srec| {
+                srec.attenuation = ray.colour(srec.attenuation);
+                srec
+            })
+        } else {
+            None
+        };
+
+        if let Some(ref srec) = srec {
+            if !srec.is_specular() {
+                // light sampled from here has bounced once more than light hit here
+                contribution += length.clamp(&throughput * sample_lights(&ray, &hit, scene, precomputed, sampler), depth + 1);
+            }
+            contribution += length.clamp(&throughput * caustic.gather(precomputed.caustics, &ray, &hit, srec), depth + 2);
+        }
+
+        observer.bounce(&Bounce { depth, ray: &ray, hit: &hit, throughput: &throughput, contribution: &contribution, scatter: srec.as_ref() });
+        incident.add(&contribution);
+        radiance += contribution;
+
+        let srec = match srec {
+            Some(srec) => srec,
+            None => break,
+        };
+
+        throughput = throughput * &srec.attenuation;
+        if srec.wavelength_dependent {
+            if let Some(ref mut wavelengths) = wavelengths {
+                wavelengths.terminate_secondary(&mut throughput);
+            }
+        }
+        match length.roulette(&throughput, depth, sampler) {
+            Some(survival) => throughput /= survival,
+            None => break,
+        }
+
+        scattering_pdf = srec.pdf;
+        incident.scattered(&hit, &srec, &throughput);
+        ray = srec.ray.with_wavelengths(wavelengths);
+        depth += 1;
+    }
+
+    incident.report(observer);
+    radiance
+}
+
+/// Estimate of light arriving directly from the scene's lights (or environment) and scattered
+/// back along `r`, weighted against the pdf of the path being scattered in the same direction.
+fn sample_lights<T: Hitable + Send + Sync>(r: &Ray, hit: &HitRecord, scene: &Scene<T>, precomputed: Precomputed, sampler: &mut dyn Sampler) -> Vec3 {
+    let (direction, light_pdf) = match scene.sample_emitters(&hit.point, sampler) {
+        Some(sample) => sample,
+        None => return Vec3::zeros(),
+    };
+
+    let shadow_ray = Ray::new_at_time(hit.point.clone(), direction, r.time());
+    let scattering_pdf = precomputed.scattering_pdf(r, hit, &shadow_ray);
+    if light_pdf <= 0.0 || scattering_pdf <= 0.0 {
+        return Vec3::zeros();
+    }
+
+    let emitted = match scene.hit(&shadow_ray, 0.001, f32::MAX, sampler) {
+        Some(light_hit) => r.colour(light_hit.material.emitted(light_hit.u, light_hit.v, &light_hit.point)),
+        None => r.colour(scene.background(shadow_ray.direction())),
+    };
+    let bsdf = r.colour(hit.material.bsdf(r, hit, &shadow_ray));
+    let weight = power_heuristic(light_pdf, scattering_pdf);
+    &bsdf * &emitted * (weight / light_pdf)
+}
+
+/// Weight for a sample drawn with pdf `f`, when it could also have been drawn with pdf `g`.
+pub fn power_heuristic(f: f32, g: f32) -> f32 {
+    let f2 = f * f;
+    let g2 = g * g;
+    if f2 + g2 > 0.0 {
+        f2 / (f2 + g2)
+    } else {
+        0.0
+    }
+}
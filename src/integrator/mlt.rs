@@ -0,0 +1,327 @@
+use rand::prelude::*;
+use rand_pcg::Pcg32;
+use rayon::prelude::*;
+use indicatif::ProgressBar;
+
+use crate::sampler::Sampler;
+use crate::vec3::Vec3;
+use crate::utils;
+
+// mutations made by each chain between adding their splats to the image, which bounds the memory
+// held by splats waiting to be added
+const ROUND_MUTATIONS: u64 = 1024;
+
+/// Primary sample space Metropolis light transport (Kelemen et al. 2002). Paths are traced from
+/// the random numbers supplied by a sampler, but rather than drawing new numbers for every path,
+/// Markov chains mutate the numbers which gave the last path: usually by perturbing them slightly,
+/// which finds paths close to bright ones already found (e.g. light squeezing through a small
+/// gap), and sometimes by replacing them all (a large step), which finds new paths. Chains visit
+/// paths in proportion to their brightness, so the brightness of the whole image is estimated
+/// beforehand from independently traced paths (bootstrapping), which the chains also start from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Metropolis {
+    bootstrap: u32,
+    chains: u32,
+    large_step_probability: f32,
+    sigma: f32,
+}
+
+impl Metropolis {
+    /// Defaults to 100000 bootstrap paths and 1000 chains, with 30% of mutations being large
+    /// steps, and the rest perturbing each number by 1% (standard deviation).
+    pub fn new() -> Self {
+        Self { bootstrap: 100_000, chains: 1000, large_step_probability: 0.3, sigma: 0.01 }
+    }
+
+    /// Set number of independent paths traced to estimate the brightness of the image, and to
+    /// start chains from.
+    pub fn with_bootstrap(mut self, bootstrap: u32) -> Self {
+        self.bootstrap = bootstrap;
+        self
+    }
+
+    /// Set number of Markov chains. Each chain spends its time around the paths it's found, so
+    /// with too few chains bright regions of the image can be missed until late in the render.
+    pub fn with_chains(mut self, chains: u32) -> Self {
+        self.chains = chains;
+        self
+    }
+
+    /// Set probability of each mutation being a large step, replacing all of a path's random
+    /// numbers rather than perturbing them.
+    pub fn with_large_step_probability(mut self, large_step_probability: f32) -> Self {
+        self.large_step_probability = large_step_probability;
+        self
+    }
+
+    /// Renders an image `width` x `height` pixels in size, from `mutations` mutations in total.
+    /// `radiance` traces a path using the numbers from a sampler, returning the film position (in
+    /// pixels) it passes through along with the radiance it carries. Returns the pixels in
+    /// row-major order.
+    pub fn render<F>(&self, width: u32, height: u32, mutations: u64, seed: u64, progress: &ProgressBar, radiance: F) -> Vec<Vec3>
+        where F: Fn(&mut dyn Sampler) -> ((f32, f32), Vec3) + Sync
+    {
+        let mut image = vec![Vec3::zeros(); (width * height) as usize];
+        let bootstrap_seed = |idx: usize| utils::mix_seed(utils::mix_seed(seed, 1), idx as u64);
+        let chain_seed = |chain: u32| utils::mix_seed(utils::mix_seed(seed, 2), u64::from(chain));
+
+        let weights: Vec<f32> = (0..self.bootstrap as usize).into_par_iter()
+            .map(|idx| contribution(&radiance(&mut self.primary_sample(bootstrap_seed(idx))).1))
+            .collect();
+        let cdf: Vec<f64> = weights.iter()
+            .scan(0.0, |total, &w| {
+                *total += f64::from(w);
+                Some(*total)
+            })
+            .collect();
+        let total = cdf.last().cloned().unwrap_or(0.0);
+        if total <= 0.0 || mutations == 0 || self.chains == 0 {
+            return image;
+        }
+        let brightness = total / f64::from(self.bootstrap);
+
+        let chains = u64::from(self.chains);
+        let mut chains: Vec<Chain> = (0..self.chains).into_par_iter()
+            .map(|chain| {
+                // start from a bootstrap path chosen in proportion to its brightness, replaying
+                // the numbers it was traced with
+                let mut rng = utils::seeded_rng(chain_seed(chain));
+                let u = rng.gen::<f64>() * total;
+                let idx = cdf.partition_point(|&c| c <= u).min(cdf.len() - 1);
+                let mut sampler = self.primary_sample(bootstrap_seed(idx));
+                let (film_pos, radiance) = radiance(&mut sampler);
+                sampler.rng = utils::seeded_rng(rng.gen());
+
+                let remaining = mutations / chains + if u64::from(chain) < mutations % chains { 1 } else { 0 };
+                let contribution = contribution(&radiance);
+                Chain { sampler, rng, film_pos, radiance, contribution, remaining }
+            })
+            .collect();
+
+        // splats are added in the order of the chains, so the image doesn't depend on how chains
+        // were scheduled on threads
+        while chains.iter().any(|chain| chain.remaining > 0) {
+            let splats: Vec<Vec<(usize, Vec3)>> = chains.par_iter_mut()
+                .map(|chain| {
+                    let splats = chain.run(ROUND_MUTATIONS, width, height, &radiance);
+                    progress.inc(splats.1);
+                    splats.0
+                })
+                .collect();
+            for (idx, col) in splats.into_iter().flatten() {
+                image[idx] += &col;
+            }
+        }
+
+        // each pixel covers 1 / (width * height) of the film, and chains visit it in proportion
+        // to the brightness of the light through it
+        let scale = (brightness * f64::from(width * height) / mutations as f64) as f32;
+        image.into_iter().map(|col| scale * &col).collect()
+    }
+
+    fn primary_sample(&self, seed: u64) -> PrimarySample {
+        PrimarySample {
+            rng: utils::seeded_rng(seed),
+            values: Vec::new(),
+            dimension: 0,
+            iteration: 0,
+            large_step: true,
+            last_large_step: 0,
+            large_step_probability: self.large_step_probability,
+            sigma: self.sigma,
+        }
+    }
+}
+
+impl Default for Metropolis {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// brightness of a path, which chains visit paths in proportion to
+fn contribution(radiance: &Vec3) -> f32 {
+    radiance.luminance().max(0.0)
+}
+
+// Markov chain, along with the path it's currently at
+struct Chain {
+    sampler: PrimarySample,
+    rng: Pcg32,
+    film_pos: (f32, f32),
+    radiance: Vec3,
+    contribution: f32,
+    remaining: u64,
+}
+
+impl Chain {
+    // makes up to `mutations` mutations, returning the light they splat onto the image (as pixel
+    // indices and colours), and the number of mutations made
+    fn run<F>(&mut self, mutations: u64, width: u32, height: u32, radiance: &F) -> (Vec<(usize, Vec3)>, u64)
+        where F: Fn(&mut dyn Sampler) -> ((f32, f32), Vec3)
+    {
+        let mutations = mutations.min(self.remaining);
+        self.remaining -= mutations;
+
+        let pixel = |(x, y): (f32, f32)| {
+            let i = (x.max(0.0) as u32).min(width - 1);
+            let j = (y.max(0.0) as u32).min(height - 1);
+            (j * width + i) as usize
+        };
+
+        let mut splats = Vec::with_capacity(2 * mutations as usize);
+        for _ in 0..mutations {
+            self.sampler.start_iteration();
+            let (film_pos, proposed) = radiance(&mut self.sampler);
+            let contribution = contribution(&proposed);
+            let accept = if self.contribution > 0.0 {
+                (contribution / self.contribution).min(1.0)
+            } else {
+                1.0
+            };
+
+            // both paths are splatted, weighted by how likely the chain is to move to each, which
+            // spends the work of tracing rejected paths on the image too
+            if accept > 0.0 {
+                splats.push((pixel(film_pos), (accept / contribution) * &proposed));
+            }
+            if accept < 1.0 {
+                splats.push((pixel(self.film_pos), ((1.0 - accept) / self.contribution) * &self.radiance));
+            }
+
+            if self.rng.gen::<f32>() < accept {
+                self.film_pos = film_pos;
+                self.radiance = proposed;
+                self.contribution = contribution;
+                self.sampler.accept();
+            } else {
+                self.sampler.reject();
+            }
+        }
+        (splats, mutations)
+    }
+}
+
+// random numbers a path was traced with, which are only mutated once they're used by the next
+// path, as paths often use few of the dimensions used by earlier ones
+struct PrimarySample {
+    rng: Pcg32,
+    values: Vec<PrimaryValue>,
+    dimension: usize,
+    iteration: u64,
+    large_step: bool,
+    last_large_step: u64, // iteration of the last accepted large step
+    large_step_probability: f32,
+    sigma: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct PrimaryValue {
+    value: f32,
+    modified: u64, // iteration the value was last mutated in
+    backup: f32,
+    backup_modified: u64,
+}
+
+impl PrimarySample {
+    // starts mutating the numbers for a new path
+    fn start_iteration(&mut self) {
+        self.iteration += 1;
+        self.large_step = self.rng.gen::<f32>() < self.large_step_probability;
+        self.dimension = 0;
+    }
+
+    fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step = self.iteration;
+        }
+    }
+
+    // restores the numbers the current path was traced with
+    fn reject(&mut self) {
+        for v in &mut self.values {
+            if v.modified == self.iteration {
+                v.value = v.backup;
+                v.modified = v.backup_modified;
+            }
+        }
+        self.iteration -= 1;
+    }
+
+    fn next(&mut self) -> f32 {
+        let d = self.dimension;
+        self.dimension += 1;
+
+        // dimensions no earlier path used are drawn fresh
+        if d == self.values.len() {
+            let value = self.rng.gen();
+            self.values.push(PrimaryValue { value, modified: self.iteration, backup: value, backup_modified: 0 });
+            return value;
+        }
+
+        let v = &mut self.values[d];
+        // values which weren't used by the last accepted large step should have been replaced by it
+        if v.modified < self.last_large_step {
+            v.value = self.rng.gen();
+            v.modified = self.last_large_step;
+        }
+
+        v.backup = v.value;
+        v.backup_modified = v.modified;
+        if self.large_step {
+            v.value = self.rng.gen();
+        } else {
+            // the perturbations for every iteration since it was last used, combined
+            let n = (self.iteration - v.modified) as f32;
+            let (u1, u2): (f32, f32) = (self.rng.gen(), self.rng.gen());
+            let normal = (-2.0 * (1.0 - u1).ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos();
+            v.value += normal * self.sigma * n.sqrt();
+            v.value -= v.value.floor();
+        }
+        v.modified = self.iteration;
+        v.value
+    }
+}
+
+impl Sampler for PrimarySample {
+    // mutations are started by `start_iteration`, so this only restarts the dimensions of the
+    // current path
+    fn start_sample(&mut self, _pixel: u64, _index: u32) {
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        self.next().min(1.0 - f32::EPSILON / 2.0)
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_primary_sample_restores_rejected_values() {
+        let mut sampler = Metropolis::new().primary_sample(0);
+        let values = |sampler: &PrimarySample| sampler.values.iter().map(|v| v.value).collect::<Vec<f32>>();
+        let first: Vec<f32> = (0..4).map(|_| sampler.get_1d()).collect();
+
+        for _ in 0..20 {
+            sampler.start_iteration();
+            let mutated: Vec<f32> = (0..6).map(|_| sampler.get_1d()).collect();
+            assert!(mutated.iter().all(|x| (0.0..1.0).contains(x)));
+            assert_ne!(&mutated[..4], &first[..]);
+
+            sampler.reject();
+            assert_eq!(&values(&sampler)[..4], &first[..]);
+        }
+
+        sampler.start_iteration();
+        let mutated: Vec<f32> = (0..4).map(|_| sampler.get_1d()).collect();
+        sampler.accept();
+        assert_eq!(&values(&sampler)[..4], &mutated[..]);
+    }
+}
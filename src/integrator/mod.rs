@@ -0,0 +1,412 @@
+//! Integrators estimate the radiance arriving back along camera rays.
+
+use std::fmt;
+use std::str::FromStr;
+use crate::sampler::Sampler;
+
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::hitable::{HitRecord, Hitable};
+use crate::material::ScatterRecord;
+use crate::scenes::Scene;
+use crate::photon::PhotonMap;
+use crate::guiding::PathGuide;
+use crate::stats;
+
+mod path;
+mod direct;
+mod mis;
+mod bdpt;
+mod debug;
+pub use debug::default_ao_radius;
+
+mod mlt;
+pub use mlt::Metropolis;
+
+mod observer;
+pub use observer::{Bounce, BounceRecord, PathLog, PathObserver};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Integrator {
+    /// Naive path tracer, which only finds lights when scattered rays happen to hit them.
+    #[default]
+    Path,
+    /// Path tracer which also samples the scene's lights directly at each diffuse bounce (next
+    /// event estimation).
+    Direct,
+    /// Path tracer which samples both materials and lights at each bounce, combining them with
+    /// multiple importance sampling (power heuristic).
+    Mis,
+    /// Bidirectional path tracer, connecting subpaths traced from the camera and from lights,
+    /// which finds caustics and light reaching the camera indirectly far more often.
+    Bdpt,
+    /// Metropolis light transport, exploring paths traced by the multiple importance sampling
+    /// path tracer with Markov chains, which keep finding light that reaches most of the scene
+    /// through small openings once one path through them has been found. The whole image is
+    /// rendered at once (see `Metropolis`), so radiance along a single ray is estimated as for
+    /// `Mis`.
+    Mlt,
+    /// Debug view of the shading normal of the first surface hit, facing the camera, with each
+    /// component mapped from [-1, 1] to [0, 1].
+    Normals,
+    /// Debug view of the texture coordinates of the first surface hit, as red and green.
+    Uv,
+    /// Debug view of the distance to the first surface hit. Renderers show it from white
+    /// (nearest) to black (furthest).
+    Distance,
+    /// Debug view of ambient occlusion: the fraction of the (cosine weighted) hemisphere above
+    /// the first surface hit which isn't blocked by other surfaces within a radius.
+    AmbientOcclusion,
+    /// Debug view of the number of bounding volume hierarchy nodes visited in finding the first
+    /// surface hit. Renderers show it as a false colour heatmap.
+    BvhNodes,
+    /// Debug view of the number of intersection tests against primitives made in finding the
+    /// first surface hit. Renderers show it as a false colour heatmap.
+    PrimitiveTests,
+}
+
+impl Integrator {
+    pub const NAMES: &'static [&'static str] = &["path", "direct", "mis", "bdpt", "mlt", "normals", "uv", "distance", "ao",
+                                                  "bvh-nodes", "primitive-tests"];
+
+    /// Whether this is one of the debug views of the scene's geometry, rather than an estimate
+    /// of radiance.
+    pub fn is_debug(self) -> bool {
+        match self {
+            Integrator::Path | Integrator::Direct | Integrator::Mis | Integrator::Bdpt | Integrator::Mlt => false,
+            Integrator::Normals | Integrator::Uv | Integrator::Distance | Integrator::AmbientOcclusion
+                | Integrator::BvhNodes | Integrator::PrimitiveTests => true,
+        }
+    }
+
+    /// Radiance arriving along ray `r`, following paths terminated according to `length`.
+    pub fn colour<T: Hitable + Send + Sync>(self, r: &Ray, scene: &Scene<T>, length: &PathLength, sampler: &mut dyn Sampler) -> Vec3 {
+        self.colour_observed(r, scene, length, Precomputed::default(), &mut (), sampler)
+    }
+
+    /// As `colour`, but passing details of each bounce along the path to `observer`, and making use
+    /// of anything `precomputed` for the scene.
+    pub fn colour_observed<T, O>(self, r: &Ray, scene: &Scene<T>, length: &PathLength, precomputed: Precomputed,
+                                 observer: &mut O, sampler: &mut dyn Sampler) -> Vec3
+        where T: Hitable + Send + Sync,
+              O: PathObserver
+    {
+        let observer = &mut CountVertices(observer);
+        match self {
+            Integrator::Path => path::colour(r, scene, length, precomputed, observer, sampler),
+            Integrator::Direct => direct::colour(r, scene, length, precomputed, observer, sampler),
+            Integrator::Mis | Integrator::Mlt => mis::colour(r, scene, length, precomputed, observer, sampler),
+            Integrator::Bdpt => bdpt::colour(r, scene, length, observer, sampler),
+            _ => debug::colour(self, r, scene, precomputed, sampler),
+        }
+    }
+}
+
+/// Data learned about the scene before tracing paths, which integrators use to find light more
+/// efficiently, along with settings for the debug integrators. Ignored by the bidirectional path
+/// tracer, which finds caustics and indirect light well itself.
+#[derive(Clone, Copy, Default)]
+pub struct Precomputed<'a> {
+    /// Photons for estimating caustics at diffuse surfaces.
+    pub caustics: Option<&'a PhotonMap>,
+    /// Learned distributions of incident light, which paths are scattered towards.
+    pub guide: Option<&'a PathGuide>,
+    /// Distance within which surfaces occlude each other for the ambient occlusion integrator,
+    /// `default_ao_radius` if `None`.
+    pub ao_radius: Option<f32>,
+}
+
+impl<'a> Precomputed<'a> {
+    // scatters a path from `hit`, guided by the learned distributions if there are any
+    fn scatter(&self, r: &Ray, hit: &HitRecord, sampler: &mut dyn Sampler) -> Option<ScatterRecord> {
+        stats::count_scatter(hit.material.type_name());
+        let srec = hit.material.scatter(r, hit, sampler)?;
+        Some(match self.guide {
+            Some(guide) => guide.scatter(r, hit, srec, sampler),
+            None => srec,
+        })
+    }
+
+    // solid angle pdf of `scatter` sampling `scattered`
+    fn scattering_pdf(&self, r: &Ray, hit: &HitRecord, scattered: &Ray) -> f32 {
+        match self.guide {
+            Some(guide) => guide.scattering_pdf(r, hit, scattered),
+            None => hit.material.scattering_pdf(r, hit, scattered),
+        }
+    }
+}
+
+/// Controls when paths are terminated, and how much light they may carry back.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PathLength {
+    /// Paths are always terminated after this many bounces.
+    pub max_depth: usize,
+    /// After this many bounces paths are randomly terminated with Russian roulette, with
+    /// probability based on how much light they can still carry. Surviving paths are boosted to
+    /// compensate, so the result is unbiased.
+    pub min_depth: usize,
+    /// Maximum brightness (of any channel) of indirect light, which has bounced off more than
+    /// one surface on its way to the camera. Brighter light is scaled down, which removes
+    /// fireflies from rarely found paths (e.g. caustics through glass) at the cost of some bias.
+    pub indirect_clamp: Option<f32>,
+}
+
+impl PathLength {
+    pub fn new(max_depth: usize, min_depth: usize) -> Self {
+        Self { max_depth, min_depth, indirect_clamp: None }
+    }
+
+    /// Clamps light found after `bounces` scattering events to `indirect_clamp`, if it's
+    /// indirect.
+    pub fn clamp(&self, contribution: Vec3, bounces: usize) -> Vec3 {
+        let max = contribution[0].max(contribution[1]).max(contribution[2]);
+        match self.indirect_clamp {
+            Some(clamp) if bounces > 1 && max > clamp => (clamp / max) * contribution,
+            _ => contribution,
+        }
+    }
+
+    /// Plays Russian roulette for a path which has made `depth` bounces, and will carry
+    /// `throughput` of any light it finds from here on. Returns the probability the path
+    /// survived with if it should continue, or `None` if it should be terminated.
+    pub fn roulette(&self, throughput: &Vec3, depth: usize, sampler: &mut dyn Sampler) -> Option<f32> {
+        if depth < self.min_depth {
+            return Some(1.0);
+        }
+
+        let survival = throughput[0].max(throughput[1]).max(throughput[2]).min(0.95);
+        if sampler.get_1d() < survival {
+            Some(survival)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for PathLength {
+    fn default() -> Self {
+        Self::new(50, 3)
+    }
+}
+
+/// Tracks how a path relates to the caustics gathered from a photon map along it. Light reaching
+/// a light through specular bounces from a diffuse surface where caustics were gathered is already
+/// included in them, so mustn't be counted again.
+#[derive(Clone, Copy, Debug, Default)]
+struct CausticPath {
+    gathered: bool, // caustics were gathered at the last non-specular bounce
+    specular: bool, // the path has bounced specularly since then
+}
+
+impl CausticPath {
+    /// Caustic light scattered back along `r` from `hit`, where it was scattered as described by
+    /// `srec`. Only diffuse surfaces gather caustics, as the photon map holds none for media or
+    /// specular surfaces.
+    fn gather(&mut self, caustics: Option<&PhotonMap>, r: &Ray, hit: &HitRecord, srec: &ScatterRecord) -> Vec3 {
+        if srec.is_specular() {
+            self.specular = true;
+            return Vec3::zeros();
+        }
+
+        self.specular = false;
+        match caustics {
+            Some(caustics) if hit.normal.squared_length() > 0.0 => {
+                self.gathered = true;
+                r.colour(caustics.radiance(r, hit))
+            },
+            _ => {
+                self.gathered = false;
+                Vec3::zeros()
+            },
+        }
+    }
+
+    /// Whether emission found at the end of the path so far should be counted.
+    fn counts_emission(&self) -> bool {
+        !(self.gathered && self.specular)
+    }
+}
+
+/// Radiance arriving at each non-specular surface a path scattered from, along the direction it
+/// scattered in, built up from the light found further along the path.
+#[derive(Default)]
+struct IncidentRadiance {
+    vertices: Vec<IncidentVertex>,
+}
+
+struct IncidentVertex {
+    point: Vec3,
+    direction: Vec3,
+    pdf: f32,
+    throughput: Vec3, // of the path leaving the vertex
+    radiance: Vec3,
+}
+
+impl IncidentRadiance {
+    /// Records that the path scattered from `hit` as described by `srec`, and carries `throughput`
+    /// from then on.
+    fn scattered(&mut self, hit: &HitRecord, srec: &ScatterRecord, throughput: &Vec3) {
+        if let Some(pdf) = srec.pdf {
+            if hit.normal.squared_length() > 0.0 {
+                let (point, direction) = (hit.point.clone(), srec.ray.direction().clone());
+                self.vertices.push(IncidentVertex { point, direction, pdf, throughput: throughput.clone(), radiance: Vec3::zeros() });
+            }
+        }
+    }
+
+    /// Adds light found at the end of the path so far (already multiplied by its throughput).
+    fn add(&mut self, contribution: &Vec3) {
+        for vertex in &mut self.vertices {
+            for c in 0..3 {
+                if vertex.throughput[c] > 0.0 {
+                    vertex.radiance[c] += contribution[c] / vertex.throughput[c];
+                }
+            }
+        }
+    }
+
+    fn report<O: PathObserver>(&self, observer: &mut O) {
+        for vertex in &self.vertices {
+            observer.incident(&vertex.point, &vertex.direction, &vertex.radiance, vertex.pdf);
+        }
+    }
+}
+
+// forwards everything to the wrapped observer, counting path vertices
+struct CountVertices<'a, O>(&'a mut O);
+
+impl<'a, O: PathObserver> PathObserver for CountVertices<'a, O> {
+    fn bounce(&mut self, bounce: &Bounce) {
+        stats::count(|stats| stats.path_vertices += 1);
+        self.0.bounce(bounce);
+    }
+
+    fn escaped(&mut self, depth: usize, ray: &Ray, contribution: &Vec3) {
+        self.0.escaped(depth, ray, contribution);
+    }
+
+    fn splat(&mut self, film_pos: (f32, f32), contribution: &Vec3) {
+        self.0.splat(film_pos, contribution);
+    }
+
+    fn incident(&mut self, point: &Vec3, direction: &Vec3, radiance: &Vec3, pdf: f32) {
+        self.0.incident(point, direction, radiance, pdf);
+    }
+}
+
+impl FromStr for Integrator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "path" => Ok(Integrator::Path),
+            "direct" => Ok(Integrator::Direct),
+            "mis" => Ok(Integrator::Mis),
+            "bdpt" => Ok(Integrator::Bdpt),
+            "mlt" => Ok(Integrator::Mlt),
+            "normals" => Ok(Integrator::Normals),
+            "uv" => Ok(Integrator::Uv),
+            "distance" => Ok(Integrator::Distance),
+            "ao" => Ok(Integrator::AmbientOcclusion),
+            "bvh-nodes" => Ok(Integrator::BvhNodes),
+            "primitive-tests" => Ok(Integrator::PrimitiveTests),
+            _ => Err(format!("unknown integrator: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Integrator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Integrator::Path => "path",
+            Integrator::Direct => "direct",
+            Integrator::Mis => "mis",
+            Integrator::Bdpt => "bdpt",
+            Integrator::Mlt => "mlt",
+            Integrator::Normals => "normals",
+            Integrator::Uv => "uv",
+            Integrator::Distance => "distance",
+            Integrator::AmbientOcclusion => "ao",
+            Integrator::BvhNodes => "bvh-nodes",
+            Integrator::PrimitiveTests => "primitive-tests",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+    use crate::hitable::Sphere;
+    use crate::material::DiffuseLight;
+    use crate::texture;
+    use crate::sampler::{self, Sampler};
+
+    fn light_scene() -> Scene<Vec<Box<dyn Hitable + Send + Sync>>> {
+        let camera = Camera::new(Vec3::zeros(), Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0),
+                                 90.0, 1.0, 0.0, 1.0, 0.0, 1.0);
+        let light = Sphere::new(Vec3::new(0.0, 0.0, -5.0), 1.0, DiffuseLight::new(texture::Constant::from_rgb(2.0, 3.0, 4.0)));
+        let hitables: Vec<Box<dyn Hitable + Send + Sync>> = vec![Box::new(light.clone())];
+        let lights: Vec<Box<dyn Hitable + Send + Sync>> = vec![Box::new(light)];
+        Scene { camera, hitables, lights, environment: None }
+    }
+
+    #[test]
+    fn test_path_log_records_light() {
+        let scene = light_scene();
+        let r = Ray::new(Vec3::zeros(), Vec3::new(0.0, 0.0, -1.0));
+        for integrator in [Integrator::Path, Integrator::Direct, Integrator::Mis, Integrator::Bdpt, Integrator::Mlt].iter() {
+            let mut log = PathLog::new();
+            let mut sampler = sampler::Independent::new(0);
+            sampler.start_sample(0, 0);
+            let col = integrator.colour_observed(&r, &scene, &PathLength::default(), Precomputed::default(), &mut log, &mut sampler);
+            assert_eq!(col, Vec3::new(2.0, 3.0, 4.0));
+            assert_eq!(log.total(), col);
+            assert_eq!(log.bounces.len(), 1);
+            assert_eq!(log.bounces[0].distance, 4.0);
+        }
+    }
+
+    #[test]
+    fn test_debug_integrators() {
+        let scene = light_scene();
+        let colour = |integrator: Integrator, r: &Ray| {
+            let mut sampler = sampler::Independent::new(0);
+            sampler.start_sample(0, 0);
+            integrator.colour(r, &scene, &PathLength::default(), &mut sampler)
+        };
+        let hit = Ray::new(Vec3::zeros(), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(colour(Integrator::Normals, &hit), Vec3::new(0.5, 0.5, 1.0));
+        assert_eq!(colour(Integrator::Distance, &hit), Vec3::new(4.0, 4.0, 4.0));
+        assert_eq!(colour(Integrator::AmbientOcclusion, &hit), Vec3::ones());
+        assert_eq!(colour(Integrator::PrimitiveTests, &hit), Vec3::ones());
+        assert_eq!(colour(Integrator::BvhNodes, &hit), Vec3::zeros());
+
+        let miss = Ray::new(Vec3::zeros(), Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(colour(Integrator::Normals, &miss), Vec3::zeros());
+        assert_eq!(colour(Integrator::PrimitiveTests, &miss), Vec3::ones());
+        assert_eq!(Integrator::NAMES.iter().filter(|name| name.parse::<Integrator>().unwrap().is_debug()).count(), 6);
+    }
+
+    #[test]
+    fn test_indirect_clamp() {
+        let mut length = PathLength::default();
+        let bright = Vec3::new(20.0, 10.0, 5.0);
+        assert_eq!(length.clamp(bright.clone(), 3), bright);
+
+        length.indirect_clamp = Some(10.0);
+        assert_eq!(length.clamp(bright.clone(), 1), bright);
+        assert_eq!(length.clamp(bright.clone(), 2), Vec3::new(10.0, 5.0, 2.5));
+        assert_eq!(length.clamp(Vec3::new(1.0, 2.0, 3.0), 2), Vec3::new(1.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn test_integrator_names() {
+        for name in Integrator::NAMES {
+            let integrator: Integrator = name.parse().unwrap();
+            assert_eq!(&integrator.to_string(), name);
+        }
+        assert!("nope".parse::<Integrator>().is_err());
+    }
+}
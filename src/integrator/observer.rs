@@ -0,0 +1,98 @@
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::hitable::HitRecord;
+use crate::material::ScatterRecord;
+
+/// Data about one vertex of a path, passed to `PathObserver::bounce`.
+pub struct Bounce<'a> {
+    /// Number of bounces made before reaching this vertex, 0 for the camera ray's first hit.
+    pub depth: usize,
+    /// Ray which arrived at this vertex.
+    pub ray: &'a Ray,
+    pub hit: &'a HitRecord<'a>,
+    /// Fraction of light leaving this vertex (back along `ray`) which reaches the camera.
+    pub throughput: &'a Vec3,
+    /// Radiance added to the path's total at this vertex, from emission and any light sampling
+    /// (already multiplied by `throughput`).
+    pub contribution: &'a Vec3,
+    /// How the path continues from here, `None` if it was absorbed or hit the maximum depth.
+    pub scatter: Option<&'a ScatterRecord>,
+}
+
+/// Hooks called by integrators as they trace each path, e.g. for debugging or collecting
+/// auxiliary outputs. Methods do nothing by default, `()` can be used to observe nothing.
+pub trait PathObserver {
+    /// Called at each interaction along a path.
+    fn bounce(&mut self, _bounce: &Bounce) {}
+
+    /// Called when a path leaves the scene after `depth` bounces, with the (throughput weighted)
+    /// radiance it picked up from the background.
+    fn escaped(&mut self, _depth: usize, _ray: &Ray, _contribution: &Vec3) {}
+
+    /// Called with light found by tracing paths from lights to the camera, which arrives at film
+    /// position (s, t) (as passed to `Camera::get_ray`) rather than along the observed ray.
+    fn splat(&mut self, _film_pos: (f32, f32), _contribution: &Vec3) {}
+
+    /// Called once a path has been traced, for each non-specular surface it scattered from, with
+    /// the radiance the rest of the path found arriving at `point` from `direction` (which was
+    /// sampled with solid angle pdf `pdf`).
+    fn incident(&mut self, _point: &Vec3, _direction: &Vec3, _radiance: &Vec3, _pdf: f32) {}
+}
+
+impl PathObserver for () {}
+
+/// Owned summary of a single bounce, as recorded by `PathLog`.
+#[derive(Clone, Debug)]
+pub struct BounceRecord {
+    pub depth: usize,
+    /// Distance travelled along the incoming ray.
+    pub distance: f32,
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub throughput: Vec3,
+    pub contribution: Vec3,
+    pub specular: bool,
+}
+
+/// Records every bounce of every path it observes.
+#[derive(Clone, Debug)]
+pub struct PathLog {
+    pub bounces: Vec<BounceRecord>,
+    /// Radiance picked up by paths leaving the scene.
+    pub escaped: Vec3,
+}
+
+impl PathLog {
+    pub fn new() -> Self {
+        Self { bounces: Vec::new(), escaped: Vec3::zeros() }
+    }
+
+    /// Total radiance recorded, equal to the integrator's result for the observed paths.
+    pub fn total(&self) -> Vec3 {
+        self.bounces.iter().fold(self.escaped.clone(), |acc, b| acc + &b.contribution)
+    }
+}
+
+impl Default for PathLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PathObserver for PathLog {
+    fn bounce(&mut self, bounce: &Bounce) {
+        self.bounces.push(BounceRecord {
+            depth: bounce.depth,
+            distance: bounce.hit.t * bounce.ray.direction().length(),
+            point: bounce.hit.point.clone(),
+            normal: bounce.hit.normal.clone(),
+            throughput: bounce.throughput.clone(),
+            contribution: bounce.contribution.clone(),
+            specular: bounce.scatter.map(|s| s.is_specular()).unwrap_or(false),
+        });
+    }
+
+    fn escaped(&mut self, _depth: usize, _ray: &Ray, contribution: &Vec3) {
+        self.escaped += contribution;
+    }
+}
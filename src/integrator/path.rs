@@ -0,0 +1,76 @@
+use crate::sampler::Sampler;
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::hitable::Hitable;
+use crate::scenes::Scene;
+use crate::integrator::{Bounce, CausticPath, IncidentRadiance, PathLength, PathObserver, Precomputed};
+
+pub fn colour<T: Hitable + Send + Sync, O: PathObserver>(r: &Ray, scene: &Scene<T>, length: &PathLength, precomputed: Precomputed,
+                                                         observer: &mut O, sampler: &mut dyn Sampler) -> Vec3 {
+    let mut radiance = Vec3::zeros();
+    let mut throughput = Vec3::ones(); // fraction of light found from here on which reaches the camera
+    let mut ray = r.clone();
+    let mut wavelengths = r.wavelengths().cloned();
+    let mut depth = 0;
+    let mut caustic = CausticPath::default();
+    let mut incident = IncidentRadiance::default();
+
+    loop {
+        // shadow acne problem - due to numerical inaccuracy, t can be e.g. -0.00000001 or 0.0000001,
+        // so ignore values very close to 0
+        let hit = match scene.hit(&ray, 0.001, f32::MAX, sampler) {
+            Some(hit) => hit,
+            None => {
+                let contribution = length.clamp(&throughput * ray.colour(scene.background(ray.direction())), depth);
+                observer.escaped(depth, &ray, &contribution);
+                incident.add(&contribution);
+                radiance += contribution;
+                break;
+            },
+        };
+
+        let mut contribution = if caustic.counts_emission() {
+            length.clamp(&throughput * ray.colour(hit.material.emitted(hit.u, hit.v, &hit.point)), depth)
+        } else {
+            Vec3::zeros()
+        };
+        let srec = if depth < length.max_depth {
+            precomputed.scatter(&ray, &hit, sampler).map(|mut srec| {
+                srec.attenuation = ray.colour(srec.attenuation);
+                srec
+            })
+        } else {
+            None
+        };
+        if let Some(ref srec) = srec {
+            // caustic light has bounced at least twice before being scattered from here
+            contribution += length.clamp(&throughput * caustic.gather(precomputed.caustics, &ray, &hit, srec), depth + 2);
+        }
+        observer.bounce(&Bounce { depth, ray: &ray, hit: &hit, throughput: &throughput, contribution: &contribution, scatter: srec.as_ref() });
+        incident.add(&contribution);
+        radiance += contribution;
+
+        let srec = match srec {
+            Some(srec) => srec,
+            None => break,
+        };
+
+        throughput = throughput * &srec.attenuation;
+        if srec.wavelength_dependent {
+            if let Some(ref mut wavelengths) = wavelengths {
+                wavelengths.terminate_secondary(&mut throughput);
+            }
+        }
+        match length.roulette(&throughput, depth, sampler) {
+            Some(survival) => throughput /= survival,
+            None => break,
+        }
+
+        incident.scattered(&hit, &srec, &throughput);
+        ray = srec.ray.with_wavelengths(wavelengths);
+        depth += 1;
+    }
+
+    incident.report(observer);
+    radiance
+}
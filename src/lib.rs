@@ -8,3 +8,15 @@ pub mod config;
 pub mod bvh;
 pub mod texture;
 pub mod scenes;
+pub mod sampler;
+pub mod filter;
+pub mod render;
+pub mod output;
+pub mod tonemap;
+pub mod denoise;
+pub mod spectrum;
+pub mod integrator;
+pub mod environment;
+pub mod photon;
+pub mod guiding;
+pub mod stats;
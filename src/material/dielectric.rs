@@ -1,44 +1,117 @@
-use rand::prelude::*;
+use crate::sampler::Sampler;
 use crate::utils;
 use crate::ray::Ray;
 use crate::vec3::Vec3;
 use crate::hitable::HitRecord;
-use crate::material::Material;
+use crate::material::{Material, ScatterRecord};
+
+/// Wavelength (in nm) of the Fraunhofer d line, at which refractive indices are usually quoted.
+const D_LINE: f32 = 587.6;
+
+/// Refractive index of a dielectric, which may vary with wavelength (dispersion).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ior {
+    Constant(f32),
+    /// Cauchy's equation, n = a + b / λ², with λ in micrometres.
+    Cauchy { a: f32, b: f32 },
+    /// Sellmeier equation, n² = 1 + Σ b_i λ² / (λ² - c_i), with λ in micrometres.
+    Sellmeier { b: [f32; 3], c: [f32; 3] },
+}
+
+impl Ior {
+    /// Refractive index at a wavelength, in nm.
+    pub fn at(&self, wavelength: f32) -> f32 {
+        let l2 = (wavelength / 1000.0) * (wavelength / 1000.0);
+        match *self {
+            Ior::Constant(n) => n,
+            Ior::Cauchy { a, b } => a + b / l2,
+            Ior::Sellmeier { b, c } => {
+                let n2 = 1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f32>();
+                n2.sqrt()
+            },
+        }
+    }
+
+    /// Refractive index used by RGB rays, at the d line.
+    pub fn nominal(&self) -> f32 {
+        self.at(D_LINE)
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        match self {
+            Ior::Constant(_) => false,
+            Ior::Cauchy { .. } | Ior::Sellmeier { .. } => true,
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct Dielectric {
-    reflective_index: f32,
+    ior: Ior,
 }
 
 impl Dielectric {
     pub fn new(reflective_index: f32) -> Self {
-        Dielectric { reflective_index }
+        Self::new_with_ior(Ior::Constant(reflective_index))
+    }
+
+    /// New dielectric whose refractive index may vary with wavelength. This only splits light
+    /// into its colours when rendering spectrally, otherwise the index at the d line is used.
+    pub fn new_with_ior(ior: Ior) -> Self {
+        Dielectric { ior }
     }
 
     /// New glass-like dielectric (relective index 1.5).
     pub fn new_glass() -> Self {
         Self::new(1.5)
     }
+
+    /// New dielectric with the dispersion of Schott N-BK7 crown glass (n_d = 1.517).
+    pub fn new_crown_glass() -> Self {
+        Self::new_with_ior(Ior::Sellmeier {
+            b: [1.039_612, 0.231_792_34, 1.010_469_5],
+            c: [0.006_000_699, 0.020_017_914, 103.560_65],
+        })
+    }
+
+    /// New dielectric with the dispersion of Schott SF11 flint glass (n_d = 1.785), which splits
+    /// light much more than crown glass.
+    pub fn new_flint_glass() -> Self {
+        Self::new_with_ior(Ior::Sellmeier {
+            b: [1.737_597, 0.313_747_35, 1.898_781],
+            c: [0.013_188_707, 0.062_306_814, 155.236_3],
+        })
+    }
+
+    /// New dielectric with the dispersion of diamond (n_d = 2.417).
+    pub fn new_diamond() -> Self {
+        Self::new_with_ior(Ior::Sellmeier { b: [4.3356, 0.3306, 0.0], c: [0.011_236, 0.030_625, 0.0] })
+    }
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r: &Ray, hit_rec: &HitRecord) -> Option<(Vec3, Ray)> {
-        let reflected = utils::reflect(&r.direction(), &hit_rec.normal);
+    fn scatter(&self, r: &Ray, hit_rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<ScatterRecord> {
+        let reflected = utils::reflect(r.direction(), &hit_rec.normal);
+        // with dispersion spectral rays can only be followed for a single wavelength
+        let (reflective_index, wavelength_dependent) = match r.wavelengths() {
+            Some(wavelengths) if self.ior.is_dispersive() => (self.ior.at(wavelengths.hero()), true),
+            _ => (self.ior.nominal(), false),
+        };
 
         let attenuation = Vec3::ones();
 
         let d = r.direction().dot(&hit_rec.normal);
         let (outward_normal, ni_over_nt, cosine) = if d > 0.0 {
-            let cosine = self.reflective_index * d / r.direction().length();
-            (-hit_rec.normal.clone(), self.reflective_index, cosine)
+            let cosine = reflective_index * d / r.direction().length();
+            (-hit_rec.normal.clone(), reflective_index, cosine)
         } else {
             let cosine = -d / r.direction().length();
-            (hit_rec.normal.clone(), 1.0 / self.reflective_index, cosine)
+            (hit_rec.normal.clone(), 1.0 / reflective_index, cosine)
         };
 
-        let (refracted_ray, reflect_prob) = match utils::refract(&r.direction(), &outward_normal, ni_over_nt) {
+        let (refracted_ray, reflect_prob) = match utils::refract(r.direction(), &outward_normal, ni_over_nt) {
             Some(refracted) => {
-                let reflect_prob = utils::schlick(cosine, self.reflective_index);
+                let reflect_prob = utils::schlick(cosine, reflective_index);
                 (Some(refracted), reflect_prob)
             },
             None => {
@@ -49,13 +122,40 @@ impl Material for Dielectric {
             },
         };
 
-        let mut rng = rand::thread_rng();
-        let scattered_ray = if rng.gen::<f32>() < reflect_prob {
+        let scattered_ray = if sampler.get_1d() < reflect_prob {
             Ray::new_at_time(hit_rec.point.clone(), reflected, r.time())
         } else {
             Ray::new_at_time(hit_rec.point.clone(), refracted_ray.unwrap(), r.time())
         };
 
-        Some((attenuation, scattered_ray))
+        let mut srec = ScatterRecord::new_specular(attenuation, scattered_ray);
+        srec.wavelength_dependent = wavelength_dependent;
+        Some(srec)
+    }
+
+    // glass doesn't absorb any light
+    fn albedo(&self, _hit_rec: &HitRecord) -> Vec3 {
+        Vec3::ones()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ior() {
+        assert_eq!(Ior::Constant(1.5).at(400.0), 1.5);
+        assert!(!Ior::Constant(1.5).is_dispersive());
+
+        // refractive indices at the d line, and blue light bending more than red
+        for (dielectric, n_d) in &[(Dielectric::new_crown_glass(), 1.5168), (Dielectric::new_flint_glass(), 1.7847),
+                                    (Dielectric::new_diamond(), 2.4175)] {
+            assert!((dielectric.ior.nominal() - n_d).abs() < 1e-3, "{:?} isn't {}", dielectric.ior, n_d);
+            assert!(dielectric.ior.at(450.0) > dielectric.ior.at(650.0));
+        }
+
+        let cauchy = Ior::Cauchy { a: 1.5, b: 0.004 };
+        assert!((cauchy.at(500.0) - 1.516).abs() < 1e-6);
     }
 }
@@ -1,8 +1,9 @@
+use crate::sampler::Sampler;
 use crate::vec3::Vec3;
 use crate::ray::Ray;
 use crate::hitable::HitRecord;
 use crate::texture::Texture;
-use super::Material;
+use super::{Material, ScatterRecord};
 
 #[derive(Clone)]
 pub struct DiffuseLight<T: Texture + Clone> {
@@ -16,7 +17,7 @@ impl<T: Texture + Clone> DiffuseLight<T> {
 }
 
 impl<T: Texture + Clone> Material for DiffuseLight<T> {
-    fn scatter(&self, _ray_in: &Ray, _hit_rec: &HitRecord) -> Option<(Vec3, Ray)> {
+    fn scatter(&self, _ray_in: &Ray, _hit_rec: &HitRecord, _sampler: &mut dyn Sampler) -> Option<ScatterRecord> {
         None
     }
 
@@ -1,9 +1,10 @@
-use crate::vec3::Vec3;
+use crate::sampler::Sampler;
 use crate::ray::Ray;
+use crate::vec3::Vec3;
 use crate::utils;
 use crate::hitable::HitRecord;
 use crate::texture::Texture;
-use super::Material;
+use super::{Material, ScatterRecord};
 
 #[derive(Clone)]
 pub struct Isotropic<T: Texture + Clone> {
@@ -18,9 +19,17 @@ impl<T: Texture + Clone> Isotropic<T> {
 
 impl<T: Texture + Clone> Material for Isotropic<T> {
     // pick uniform random direction for scattering
-    fn scatter(&self, _ray_in: &Ray, hit_rec: &HitRecord) -> Option<(Vec3, Ray)> {
-        let scattered_ray = Ray::new_at_time(hit_rec.point.clone(), utils::random_in_unit_sphere(), hit_rec.t);
+    fn scatter(&self, _ray_in: &Ray, hit_rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<ScatterRecord> {
+        let scattered_ray = Ray::new_at_time(hit_rec.point.clone(), utils::uniform_sphere(sampler.get_2d()), hit_rec.t);
         let attenuation = self.albedo.value(hit_rec.u, hit_rec.v, &hit_rec.point);
-        Some((attenuation, scattered_ray))
+        Some(ScatterRecord::new(attenuation, scattered_ray, 1.0 / (4.0 * std::f32::consts::PI)))
+    }
+
+    fn scattering_pdf(&self, _ray_in: &Ray, _hit_rec: &HitRecord, _scattered: &Ray) -> f32 {
+        1.0 / (4.0 * std::f32::consts::PI)
+    }
+
+    fn albedo(&self, hit_rec: &HitRecord) -> Vec3 {
+        self.albedo.value(hit_rec.u, hit_rec.v, &hit_rec.point)
     }
 }
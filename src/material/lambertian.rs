@@ -1,9 +1,10 @@
-use crate::vec3::Vec3;
+use crate::sampler::Sampler;
 use crate::ray::Ray;
-use crate::utils;
+use crate::vec3::Vec3;
+use crate::utils::{self, Onb};
 use crate::hitable::HitRecord;
 use crate::texture::Texture;
-use super::Material;
+use super::{Material, ScatterRecord};
 
 #[derive(Clone)]
 pub struct Lambertian<T: Texture + Clone> {
@@ -17,14 +18,28 @@ impl<T: Texture + Clone> Lambertian<T> {
 }
 
 impl<T: Texture + Clone> Material for Lambertian<T> {
-    fn scatter(&self, ray_in: &Ray, hit_rec: &HitRecord) -> Option<(Vec3, Ray)> {
-        // get random scatter direction from unit sphere
-        let target = &hit_rec.point + &hit_rec.normal + utils::random_in_unit_sphere();
+    fn scatter(&self, ray_in: &Ray, hit_rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<ScatterRecord> {
+        // get cosine distributed scatter direction in hemisphere around normal
+        let direction = Onb::from_w(&hit_rec.normal).local(&utils::cosine_hemisphere(sampler.get_2d()));
 
         // new ray from hit point
-        let scattered_ray = Ray::new_at_time(hit_rec.point.clone(), &target - &hit_rec.point, ray_in.time());
+        let scattered_ray = Ray::new_at_time(hit_rec.point.clone(), direction, ray_in.time());
         let attenuation = self.albedo.value(hit_rec.u, hit_rec.v, &hit_rec.point);
+        let pdf = self.scattering_pdf(ray_in, hit_rec, &scattered_ray);
+
+        Some(ScatterRecord::new(attenuation, scattered_ray, pdf))
+    }
+
+    fn scattering_pdf(&self, _ray_in: &Ray, hit_rec: &HitRecord, scattered: &Ray) -> f32 {
+        let cosine = hit_rec.normal.dot(&scattered.direction().to_unit_vector());
+        if cosine > 0.0 {
+            cosine / std::f32::consts::PI
+        } else {
+            0.0
+        }
+    }
 
-        Some((attenuation, scattered_ray))
+    fn albedo(&self, hit_rec: &HitRecord) -> Vec3 {
+        self.albedo.value(hit_rec.u, hit_rec.v, &hit_rec.point)
     }
 }
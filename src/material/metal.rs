@@ -1,7 +1,8 @@
-use crate::vec3::Vec3;
+use crate::sampler::Sampler;
 use crate::ray::Ray;
+use crate::vec3::Vec3;
 use crate::hitable::HitRecord;
-use super::Material;
+use super::{Material, ScatterRecord};
 use crate::utils;
 use crate::texture::Texture;
 
@@ -23,20 +24,54 @@ impl<T: Texture + Clone> Metal<T> {
 }
 
 impl<T: Texture + Clone> Material for Metal<T> {
-    fn scatter(&self, ray_in: &Ray, hit_rec: &HitRecord) -> Option<(Vec3, Ray)> {
+    fn scatter(&self, ray_in: &Ray, hit_rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<ScatterRecord> {
         let unit_dir = ray_in.direction().to_unit_vector();
         let reflected = utils::reflect(&unit_dir, &hit_rec.normal);
 
         // new ray from hit point
-        let scattered_ray = Ray::new_at_time(hit_rec.point.clone(), reflected + self.fuzz * utils::random_in_unit_sphere(), ray_in.time());
+        let scattered_ray = Ray::new_at_time(hit_rec.point.clone(), reflected + self.fuzz * utils::uniform_in_sphere(sampler.get_2d(), sampler.get_1d()), ray_in.time());
 
         let x = scattered_ray.direction().dot(&hit_rec.normal);
         if x > 0.0 {
             let attenuation = self.albedo.value(hit_rec.u, hit_rec.v, &hit_rec.point);
-            Some((attenuation, scattered_ray))
+            if self.fuzz > 0.0 {
+                let pdf = self.scattering_pdf(ray_in, hit_rec, &scattered_ray);
+                Some(ScatterRecord::new(attenuation, scattered_ray, pdf))
+            } else {
+                Some(ScatterRecord::new_specular(attenuation, scattered_ray))
+            }
         } else {
             None
         }
     }
-}
 
+    // scattered direction is the reflection plus a random point in a sphere of radius fuzz, so the
+    // pdf of a direction is the fraction of that sphere's volume lying along it
+    fn scattering_pdf(&self, ray_in: &Ray, hit_rec: &HitRecord, scattered: &Ray) -> f32 {
+        if self.fuzz <= 0.0 || scattered.direction().dot(&hit_rec.normal) <= 0.0 {
+            return 0.0;
+        }
+
+        let reflected = utils::reflect(&ray_in.direction().to_unit_vector(), &hit_rec.normal);
+        let cosine = scattered.direction().to_unit_vector().dot(&reflected);
+
+        // distances t0 < t1 along the direction where it enters and leaves the fuzz sphere
+        let discriminant = cosine.powi(2) - 1.0 + self.fuzz.powi(2);
+        if discriminant <= 0.0 {
+            return 0.0;
+        }
+        let t0 = (cosine - discriminant.sqrt()).max(0.0);
+        let t1 = cosine + discriminant.sqrt();
+        if t1 <= 0.0 {
+            return 0.0;
+        }
+
+        // integral of t^2 dt from t0 to t1, over the volume of the fuzz sphere
+        let chord = (t1 - t0) * (t1 * t1 + t1 * t0 + t0 * t0) / 3.0;
+        chord / (4.0 / 3.0 * std::f32::consts::PI * self.fuzz.powi(3))
+    }
+
+    fn albedo(&self, hit_rec: &HitRecord) -> Vec3 {
+        self.albedo.value(hit_rec.u, hit_rec.v, &hit_rec.point)
+    }
+}
@@ -1,3 +1,4 @@
+
 mod metal;
 mod lambertian;
 mod dielectric;
@@ -6,20 +7,70 @@ mod isotropic;
 
 pub use self::metal::Metal;
 pub use self::lambertian::Lambertian;
-pub use self::dielectric::Dielectric;
+pub use self::dielectric::{Dielectric, Ior};
 pub use self::diffuse_light::DiffuseLight;
 pub use self::isotropic::Isotropic;
 
+use crate::sampler::Sampler;
 use crate::ray::Ray;
 use crate::vec3::Vec3;
 use crate::hitable::HitRecord;
 
+pub struct ScatterRecord {
+    /// Weight applied to light arriving along `ray`, i.e. the BSDF (times cosine) divided by `pdf`
+    /// for non-specular scattering. This is the albedo for materials sampling their BSDF exactly.
+    pub attenuation: Vec3,
+    pub ray: Ray,
+    /// Solid angle pdf of sampling `ray`, or `None` if scattering is specular (a delta
+    /// distribution which can't be sampled by other means).
+    pub pdf: Option<f32>,
+    /// Whether `ray` was chosen for the hero wavelength of a spectral ray alone (e.g. refraction
+    /// with dispersion), so that the path can't carry its other wavelengths any further.
+    pub wavelength_dependent: bool,
+}
+
+impl ScatterRecord {
+    pub fn new(attenuation: Vec3, ray: Ray, pdf: f32) -> Self {
+        Self { attenuation, ray, pdf: Some(pdf), wavelength_dependent: false }
+    }
+
+    pub fn new_specular(attenuation: Vec3, ray: Ray) -> Self {
+        Self { attenuation, ray, pdf: None, wavelength_dependent: false }
+    }
+
+    pub fn is_specular(&self) -> bool {
+        self.pdf.is_none()
+    }
+}
+
 pub trait Material: Send + Sync {
-    // (attenuation, ray)
-    fn scatter(&self, r: &Ray, hit_rec: &HitRecord) -> Option<(Vec3, Ray)>;
+    fn scatter(&self, r: &Ray, hit_rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<ScatterRecord>;
+
+    // pdf of scattering r in direction of scattered, defaults to 0 (e.g. for specular materials)
+    fn scattering_pdf(&self, _r: &Ray, _hit_rec: &HitRecord, _scattered: &Ray) -> f32 {
+        0.0
+    }
+
+    /// BSDF times cosine, i.e. the fraction of light arriving along `scattered` which is scattered
+    /// back along `r`, per unit solid angle. Defaults to the albedo times `scattering_pdf`, which
+    /// holds for materials whose scattering is sampled exactly (and is 0 for specular ones).
+    fn bsdf(&self, r: &Ray, hit_rec: &HitRecord, scattered: &Ray) -> Vec3 {
+        self.scattering_pdf(r, hit_rec, scattered) * &self.albedo(hit_rec)
+    }
+
+    /// Reflectance at a hit, ignoring lighting, used as an auxiliary output (e.g. for
+    /// compositing or denoising). Defaults to black.
+    fn albedo(&self, _hit_rec: &HitRecord) -> Vec3 {
+        Vec3::zeros()
+    }
 
     // default to emitting black
     fn emitted(&self, _u: f32, _v: f32, _point: &Vec3) -> Vec3 {
         Vec3::zeros()
     }
+
+    /// Name of the material's type, used in render statistics.
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
 }
@@ -0,0 +1,243 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::vec3::Vec3;
+
+const EXR_MAGIC: u32 = 20_000_630;
+// single part scanline image, with attribute names of at most 31 characters
+const EXR_VERSION: u32 = 2;
+
+/// Type channel values are stored as.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PixelType {
+    /// 16-bit floats, which halves file sizes but keeps only about 3 significant digits.
+    Half,
+    /// 32-bit floats, as rendered.
+    Float,
+}
+
+impl PixelType {
+    fn id(self) -> i32 {
+        match self {
+            PixelType::Half => 1,
+            PixelType::Float => 2,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            PixelType::Half => 2,
+            PixelType::Float => 4,
+        }
+    }
+}
+
+struct Channel {
+    name: String,
+    values: Vec<f32>,
+}
+
+/// Writer of uncompressed OpenEXR images made up of any number of named channels, each holding
+/// one value per pixel in row-major order, with row 0 at the top of the image.
+pub struct ExrWriter {
+    width: u32,
+    height: u32,
+    pixel_type: PixelType,
+    channels: Vec<Channel>,
+}
+
+impl ExrWriter {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height, pixel_type: PixelType::Float, channels: Vec::new() }
+    }
+
+    /// Set type all channels are stored as (32-bit float by default).
+    pub fn with_pixel_type(mut self, pixel_type: PixelType) -> Self {
+        self.pixel_type = pixel_type;
+        self
+    }
+
+    pub fn add_channel(&mut self, name: &str, values: Vec<f32>) {
+        assert_eq!(values.len(), (self.width * self.height) as usize, "channel {} is the wrong size", name);
+        assert!(!name.is_empty() && name.len() < 32, "invalid channel name: {}", name);
+        self.channels.push(Channel { name: name.to_owned(), values });
+    }
+
+    /// Adds R, G and B channels, named with the given layer prefix (e.g. "albedo." for
+    /// "albedo.R") so that compositing software groups them together.
+    pub fn add_rgb(&mut self, prefix: &str, pixels: &[Vec3]) {
+        for (k, name) in ["R", "G", "B"].iter().enumerate() {
+            let values = pixels.iter().map(|p| p[k]).collect();
+            self.add_channel(&format!("{}{}", prefix, name), values);
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn write<W: Write>(&mut self, w: &mut W) -> io::Result<()> {
+        // readers expect channels in alphabetical order, both in the header and in each scanline
+        self.channels.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let header = self.header();
+        w.write_all(&header)?;
+
+        // offset table, giving the position in the file of each scanline
+        let line_size = 8 + self.channels.len() * self.width as usize * self.pixel_type.size();
+        let table_size = 8 * self.height as usize;
+        for y in 0..self.height as usize {
+            let offset = (header.len() + table_size + y * line_size) as u64;
+            w.write_all(&offset.to_le_bytes())?;
+        }
+
+        let mut line = Vec::with_capacity(line_size);
+        for y in 0..self.height {
+            line.clear();
+            line.extend_from_slice(&(y as i32).to_le_bytes());
+            line.extend_from_slice(&((line_size - 8) as i32).to_le_bytes());
+            let start = (y * self.width) as usize;
+            for channel in &self.channels {
+                for &v in &channel.values[start..start + self.width as usize] {
+                    match self.pixel_type {
+                        PixelType::Half => line.extend_from_slice(&to_half(v).to_le_bytes()),
+                        PixelType::Float => line.extend_from_slice(&v.to_le_bytes()),
+                    }
+                }
+            }
+            w.write_all(&line)?;
+        }
+        Ok(())
+    }
+
+    fn header(&self) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&EXR_MAGIC.to_le_bytes());
+        header.extend_from_slice(&EXR_VERSION.to_le_bytes());
+
+        let mut channels = Vec::new();
+        for channel in &self.channels {
+            channels.extend_from_slice(channel.name.as_bytes());
+            channels.push(0);
+            channels.extend_from_slice(&self.pixel_type.id().to_le_bytes());
+            channels.extend_from_slice(&[0, 0, 0, 0]); // linear flag, then reserved
+            channels.extend_from_slice(&1i32.to_le_bytes()); // x sampling
+            channels.extend_from_slice(&1i32.to_le_bytes()); // y sampling
+        }
+        channels.push(0);
+        write_attribute(&mut header, "channels", "chlist", &channels);
+
+        write_attribute(&mut header, "compression", "compression", &[0]);
+        let mut window = Vec::new();
+        for &v in &[0, 0, self.width as i32 - 1, self.height as i32 - 1] {
+            window.extend_from_slice(&i32::to_le_bytes(v));
+        }
+        write_attribute(&mut header, "dataWindow", "box2i", &window);
+        write_attribute(&mut header, "displayWindow", "box2i", &window);
+        write_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+        write_attribute(&mut header, "pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+        write_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+        write_attribute(&mut header, "screenWindowWidth", "float", &1.0f32.to_le_bytes());
+        header.push(0);
+        header
+    }
+}
+
+fn write_attribute(header: &mut Vec<u8>, name: &str, type_name: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(type_name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+// converts to the bits of the nearest 16-bit float (rounding halfway values to even), with values
+// too large for a half becoming infinity
+fn to_half(x: f32) -> u16 {
+    let bits = x.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    let (half, shift, rest) = if exponent <= 0 {
+        // too small to be normalised as a half, so stored as a denormal
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        (mantissa >> shift, shift, mantissa)
+    } else {
+        (((exponent as u32) << 10) | (mantissa >> 13), 13, mantissa)
+    };
+
+    let remainder = rest & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    let round_up = remainder > halfway || (remainder == halfway && half & 1 == 1);
+    // rounding up may carry into the exponent, which is still correct
+    sign | (half + round_up as u32) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_half() {
+        assert_eq!(to_half(0.0), 0x0000);
+        assert_eq!(to_half(-0.0), 0x8000);
+        assert_eq!(to_half(1.0), 0x3c00);
+        assert_eq!(to_half(-2.0), 0xc000);
+        assert_eq!(to_half(0.333_333_34), 0x3555);
+        assert_eq!(to_half(65504.0), 0x7bff);
+        assert_eq!(to_half(1e6), 0x7c00);
+        assert_eq!(to_half(f32::INFINITY), 0x7c00);
+        assert_eq!(to_half(f32::NAN) & 0x7e00, 0x7e00);
+        // smallest denormal, and values rounding to it or to zero
+        assert_eq!(to_half(5.960_464_5e-8), 0x0001);
+        assert_eq!(to_half(4e-8), 0x0001);
+        assert_eq!(to_half(2e-8), 0x0000);
+    }
+
+    #[test]
+    fn test_exr_layout() {
+        let mut exr = ExrWriter::new(2, 3).with_pixel_type(PixelType::Half);
+        exr.add_channel("Z", vec![0.0; 6]);
+        exr.add_rgb("", &vec![Vec3::new(1.0, 2.0, 3.0); 6]);
+        let mut data = Vec::new();
+        exr.write(&mut data).unwrap();
+
+        assert_eq!(&data[..4], &[0x76, 0x2f, 0x31, 0x01]);
+        // channels are sorted by name
+        let chlist = data.windows(6).position(|w| w == b"chlist").unwrap();
+        assert_eq!(&data[chlist + 11..chlist + 13], b"B\0");
+
+        // each scanline starts with its y coordinate and size, and the last ends the file
+        let header_len = exr.header().len();
+        let line_size = 8 + 4 * 2 * 2;
+        for y in 0..3 {
+            let offset_pos = header_len + 8 * y;
+            let mut offset = [0; 8];
+            offset.copy_from_slice(&data[offset_pos..offset_pos + 8]);
+            let offset = u64::from_le_bytes(offset) as usize;
+            assert_eq!(&data[offset..offset + 8], &[y as u8, 0, 0, 0, 16, 0, 0, 0]);
+            // first channel in each line is B
+            assert_eq!(&data[offset + 8..offset + 10], &to_half(3.0).to_le_bytes());
+        }
+        assert_eq!(data.len(), header_len + 8 * 3 + 3 * line_size);
+    }
+}
@@ -0,0 +1,23 @@
+use std::io::{self, Write};
+
+use image::Rgb;
+use image::hdr::HDREncoder;
+
+use crate::vec3::Vec3;
+
+/// Writes RGB pixels (in row-major order, with row 0 at the top of the image) as a Radiance HDR
+/// image. These store a shared exponent per pixel, so can't hold negative values, which are
+/// clamped to zero.
+pub fn write_hdr<W: Write>(w: &mut W, width: u32, height: u32, pixels: &[Vec3]) -> io::Result<()> {
+    assert_eq!(pixels.len(), (width * height) as usize);
+
+    let data: Vec<Rgb<f32>> = pixels.iter()
+        .map(|p| Rgb([non_negative(p[0]), non_negative(p[1]), non_negative(p[2])]))
+        .collect();
+    HDREncoder::new(w).encode(&data, width as usize, height as usize)
+}
+
+// also maps NaN to zero
+fn non_negative(x: f32) -> f32 {
+    if x > 0.0 { x } else { 0.0 }
+}
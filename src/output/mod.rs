@@ -0,0 +1,123 @@
+//! Image file formats renders can be written in. 8-bit formats hold gamma corrected radiance
+//! clamped to [0, 1], while high dynamic range formats hold the linear radiance as rendered, for
+//! later exposure adjustment or compositing.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use image::{DynamicImage, ImageOutputFormat, RgbImage};
+
+mod exr;
+pub use exr::{ExrWriter, PixelType};
+
+mod hdr;
+pub use hdr::write_hdr;
+
+mod pfm;
+pub use pfm::write_pfm;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    #[default]
+    Png,
+    Jpeg,
+    /// OpenEXR, with 16 or 32-bit float channels.
+    Exr,
+    /// Radiance RGBE.
+    Hdr,
+    /// Portable float map, with 32-bit float channels.
+    Pfm,
+}
+
+impl OutputFormat {
+    pub const NAMES: &'static [&'static str] = &["png", "jpeg", "exr", "hdr", "pfm"];
+
+    /// Format implied by a path's extension, if it's one of the supported formats.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let ext = path.as_ref().extension()?.to_str()?.to_lowercase();
+        match ext.as_str() {
+            "jpg" => Some(OutputFormat::Jpeg),
+            ext => ext.parse().ok(),
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Exr => "exr",
+            OutputFormat::Hdr => "hdr",
+            OutputFormat::Pfm => "pfm",
+        }
+    }
+
+    /// Whether the format stores linear radiance without clamping it.
+    pub fn is_hdr(self) -> bool {
+        match self {
+            OutputFormat::Png | OutputFormat::Jpeg => false,
+            OutputFormat::Exr | OutputFormat::Hdr | OutputFormat::Pfm => true,
+        }
+    }
+}
+
+/// Writes an 8-bit image as a PNG or JPEG.
+pub fn save_image<P: AsRef<Path>>(path: P, image: RgbImage, format: OutputFormat) -> io::Result<()> {
+    let image_format = match format {
+        OutputFormat::Png => ImageOutputFormat::PNG,
+        OutputFormat::Jpeg => ImageOutputFormat::JPEG(90),
+        _ => panic!("{} isn't an 8-bit format", format),
+    };
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    DynamicImage::ImageRgb8(image).write_to(&mut writer, image_format).map_err(io::Error::other)?;
+    writer.flush()
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "png" => Ok(OutputFormat::Png),
+            "jpeg" => Ok(OutputFormat::Jpeg),
+            "exr" => Ok(OutputFormat::Exr),
+            "hdr" => Ok(OutputFormat::Hdr),
+            "pfm" => Ok(OutputFormat::Pfm),
+            _ => Err(format!("unknown output format: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg => "jpeg",
+            OutputFormat::Exr => "exr",
+            OutputFormat::Hdr => "hdr",
+            OutputFormat::Pfm => "pfm",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(OutputFormat::from_path("out.png"), Some(OutputFormat::Png));
+        assert_eq!(OutputFormat::from_path("out.JPG"), Some(OutputFormat::Jpeg));
+        assert_eq!(OutputFormat::from_path("dir.v2/out.exr"), Some(OutputFormat::Exr));
+        assert_eq!(OutputFormat::from_path("out.tiff"), None);
+        assert_eq!(OutputFormat::from_path("out"), None);
+        for name in OutputFormat::NAMES {
+            let format: OutputFormat = name.parse().unwrap();
+            assert_eq!(OutputFormat::from_path(format!("out.{}", format.extension())), Some(format));
+        }
+    }
+}
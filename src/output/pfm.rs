@@ -0,0 +1,23 @@
+use std::io::{self, Write};
+
+use crate::vec3::Vec3;
+
+/// Writes RGB pixels (in row-major order, with row 0 at the top of the image) as a portable float
+/// map. Pixels are stored as little endian 32-bit floats, from the bottom row up.
+pub fn write_pfm<W: Write>(w: &mut W, width: u32, height: u32, pixels: &[Vec3]) -> io::Result<()> {
+    assert_eq!(pixels.len(), (width * height) as usize);
+
+    // a negative scale marks the data as little endian
+    write!(w, "PF\n{} {}\n-1.0\n", width, height)?;
+    let mut line = Vec::with_capacity(width as usize * 12);
+    for row in pixels.chunks(width as usize).rev() {
+        line.clear();
+        for p in row {
+            for k in 0..3 {
+                line.extend_from_slice(&p[k].to_le_bytes());
+            }
+        }
+        w.write_all(&line)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,171 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::vec3::Vec3;
+
+/// Balanced kd-tree of items at points in space, for finding the items nearest to a point. The
+/// tree is stored implicitly: each node is the median (along its split axis) of the items in its
+/// range, with the items before and after it forming its left and right subtrees.
+pub struct KdTree<T> {
+    items: Vec<(Vec3, T)>,
+    axes: Vec<usize>, // split axis of the node at each index
+}
+
+impl<T> KdTree<T> {
+    pub fn new(mut items: Vec<(Vec3, T)>) -> Self {
+        let mut axes = vec![0; items.len()];
+        build(&mut items, &mut axes);
+        Self { items, axes }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Up to `k` of the items nearest to `point` and within `max_distance` of it, along with their
+    /// squared distances from it, in no particular order.
+    pub fn nearest(&self, point: &Vec3, k: usize, max_distance: f32) -> Vec<(f32, &T)> {
+        if k == 0 {
+            return Vec::new();
+        }
+
+        let mut found = BinaryHeap::with_capacity(k + 1);
+        self.search(0, self.items.len(), point, k, max_distance * max_distance, &mut found);
+        found.into_iter()
+            .map(|n| (n.distance2, &self.items[n.index].1))
+            .collect()
+    }
+
+    fn search(&self, start: usize, end: usize, point: &Vec3, k: usize, max_distance2: f32, found: &mut BinaryHeap<Neighbour>) {
+        if start >= end {
+            return;
+        }
+
+        let mid = start + (end - start) / 2;
+        let node = &self.items[mid].0;
+        let axis = self.axes[mid];
+        let delta = point[axis] - node[axis];
+        let (near, far) = if delta < 0.0 { ((start, mid), (mid + 1, end)) } else { ((mid + 1, end), (start, mid)) };
+
+        self.search(near.0, near.1, point, k, max_distance2, found);
+
+        let distance2 = (point - node).squared_length();
+        if distance2 <= search_radius2(found, k, max_distance2) {
+            found.push(Neighbour { distance2, index: mid });
+            if found.len() > k {
+                found.pop();
+            }
+        }
+
+        // the far side can only hold closer items if the splitting plane is within range
+        if delta * delta <= search_radius2(found, k, max_distance2) {
+            self.search(far.0, far.1, point, k, max_distance2, found);
+        }
+    }
+}
+
+// squared distance within which items are still worth finding
+fn search_radius2(found: &BinaryHeap<Neighbour>, k: usize, max_distance2: f32) -> f32 {
+    match found.peek() {
+        Some(furthest) if found.len() == k => furthest.distance2.min(max_distance2),
+        _ => max_distance2,
+    }
+}
+
+fn build<T>(items: &mut [(Vec3, T)], axes: &mut [usize]) {
+    if items.is_empty() {
+        return;
+    }
+
+    // split along the axis the items are most spread out on
+    let mut min = Vec3::new(f32::MAX, f32::MAX, f32::MAX);
+    let mut max = Vec3::new(f32::MIN, f32::MIN, f32::MIN);
+    for (p, _) in items.iter() {
+        for a in 0..3 {
+            min[a] = min[a].min(p[a]);
+            max[a] = max[a].max(p[a]);
+        }
+    }
+    let extent = &max - &min;
+    let axis = if extent[0] > extent[1] && extent[0] > extent[2] {
+        0
+    } else if extent[1] > extent[2] {
+        1
+    } else {
+        2
+    };
+
+    let mid = items.len() / 2;
+    items.select_nth_unstable_by(mid, |a, b| a.0[axis].partial_cmp(&b.0[axis]).unwrap_or(Ordering::Equal));
+    axes[mid] = axis;
+
+    let (left, right) = items.split_at_mut(mid);
+    let (left_axes, right_axes) = axes.split_at_mut(mid);
+    build(left, left_axes);
+    build(&mut right[1..], &mut right_axes[1..]);
+}
+
+// item found by a search, ordered so the furthest is at the top of the heap
+struct Neighbour {
+    distance2: f32,
+    index: usize,
+}
+
+impl PartialEq for Neighbour {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Neighbour {}
+
+impl PartialOrd for Neighbour {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Neighbour {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance2.partial_cmp(&other.distance2).unwrap_or(Ordering::Equal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+    use crate::utils;
+
+    #[test]
+    fn test_nearest_matches_brute_force() {
+        let mut rng = utils::seeded_rng(1);
+        let points: Vec<Vec3> = (0..500)
+            .map(|_| Vec3::new(rng.gen(), rng.gen::<f32>() * 2.0, rng.gen::<f32>() * 0.5))
+            .collect();
+        let tree = KdTree::new(points.iter().cloned().enumerate().map(|(i, p)| (p, i)).collect());
+        assert_eq!(tree.len(), 500);
+
+        for _ in 0..50 {
+            let query = Vec3::new(rng.gen(), rng.gen::<f32>() * 2.0, rng.gen::<f32>() * 0.5);
+            for &(k, max_distance) in &[(1, 10.0), (10, 10.0), (30, 0.15)] {
+                let mut expected: Vec<(f32, usize)> = points.iter().enumerate()
+                    .map(|(i, p)| ((&query - p).squared_length(), i))
+                    .filter(|&(d2, _)| d2 <= max_distance * max_distance)
+                    .collect();
+                expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                expected.truncate(k);
+
+                let mut found: Vec<(f32, usize)> = tree.nearest(&query, k, max_distance).into_iter()
+                    .map(|(d2, &i)| (d2, i))
+                    .collect();
+                found.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                assert_eq!(found, expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! Photon mapping (Jensen 1996) for caustics. Photons are emitted from the scene's lights and
+//! followed through specular surfaces (glass, metal), and where they land on the first diffuse
+//! surface after that they're stored in a kd-tree. The density of photons around a point then
+//! estimates the light focused onto it, which the path tracers would otherwise only find when a
+//! path scattered off a diffuse surface happens to pass through the glass to a light.
+
+use rayon::prelude::*;
+
+use crate::sampler::{self, Sampler};
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::hitable::{HitRecord, Hitable};
+use crate::scenes::Scene;
+use crate::spectrum::Wavelengths;
+use crate::stats;
+
+mod kdtree;
+use kdtree::KdTree;
+
+// photons are followed through at most this many specular bounces
+const MAX_BOUNCES: usize = 50;
+
+// photon arriving at a diffuse surface
+struct Photon {
+    direction: Vec3, // direction it was travelling in
+    power: Vec3,
+}
+
+/// Photons which reached diffuse surfaces via one or more specular bounces, for estimating the
+/// caustics seen at those surfaces.
+pub struct PhotonMap {
+    photons: KdTree<Photon>,
+    lookup: usize,
+    max_radius: f32,
+}
+
+impl PhotonMap {
+    /// Traces `emitted` photons from the scene's lights and keeps those forming caustics. With
+    /// `spectral` each photon is traced at its own sampled wavelengths, so dispersive glass splits
+    /// caustics into their colours. Radiance is estimated from the 50 photons nearest each
+    /// point, gathered from at most 1% of the size of the scene away.
+    pub fn caustics<T: Hitable + Send + Sync>(scene: &Scene<T>, emitted: usize, spectral: bool, seed: u64) -> Self {
+        let photons: Vec<(Vec3, Photon)> = (0..emitted).into_par_iter()
+            .filter_map(|idx| {
+                let mut sampler = sampler::Independent::new(seed);
+                sampler.start_sample(idx as u64, 0);
+                trace_caustic(scene, emitted, spectral, &mut sampler)
+            })
+            .collect();
+
+        let max_radius = match scene.hitables.bounding_box(0.0, 1.0) {
+            Some(bbox) => 0.01 * (bbox.max() - bbox.min()).length(),
+            None => 1.0,
+        };
+        Self { photons: KdTree::new(photons), lookup: 50, max_radius }
+    }
+
+    /// Set number of nearby photons radiance is estimated from. More photons give smoother but
+    /// blurrier caustics.
+    pub fn with_lookup(mut self, lookup: usize) -> Self {
+        self.lookup = lookup;
+        self
+    }
+
+    /// Set maximum distance photons are gathered from, which limits how far caustics are blurred
+    /// where there are few photons.
+    pub fn with_max_radius(mut self, max_radius: f32) -> Self {
+        self.max_radius = max_radius;
+        self
+    }
+
+    /// Number of photons stored.
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.photons.is_empty()
+    }
+
+    /// Estimate of the caustic light scattered back along `r` from (a diffuse surface at) `hit`.
+    pub fn radiance(&self, r: &Ray, hit: &HitRecord) -> Vec3 {
+        let nearby = self.photons.nearest(&hit.point, self.lookup, self.max_radius);
+        if nearby.is_empty() {
+            return Vec3::zeros();
+        }
+
+        // photons were gathered from the disc reaching the furthest one found, unless the search
+        // was cut short by the maximum radius
+        let radius2 = if nearby.len() == self.lookup {
+            nearby.iter().fold(0.0, |r2: f32, &(d2, _)| r2.max(d2))
+        } else {
+            self.max_radius * self.max_radius
+        };
+        if radius2 <= 0.0 {
+            return Vec3::zeros();
+        }
+
+        let mut total = Vec3::zeros();
+        for (_, photon) in nearby {
+            let incoming = -photon.direction.to_unit_vector();
+            let cosine = incoming.dot(&hit.normal).abs();
+            if cosine <= 0.0 {
+                continue;
+            }
+            let scattered = Ray::new_at_time(hit.point.clone(), incoming, r.time());
+            total += (1.0 / cosine) * &hit.material.bsdf(r, hit, &scattered) * &photon.power;
+        }
+        total / (std::f32::consts::PI * radius2)
+    }
+}
+
+// traces a photon from a light, returning it with the point it landed at if it reached a diffuse
+// surface after at least one specular bounce
+fn trace_caustic<T: Hitable + Send + Sync>(scene: &Scene<T>, emitted: usize, spectral: bool, sampler: &mut dyn Sampler) -> Option<(Vec3, Photon)> {
+    let mut wavelengths = if spectral {
+        Some(Wavelengths::sample(sampler.get_1d()))
+    } else {
+        None
+    };
+    let time = scene.camera.time(sampler.get_1d());
+    let light = scene.sample_light_ray(time, wavelengths, sampler)?;
+    let mut ray = light.ray;
+    let mut power = light.beta / emitted as f32;
+    let mut specular = false;
+
+    for _ in 0..=MAX_BOUNCES {
+        let hit = scene.hit(&ray, 0.001, f32::MAX, sampler)?;
+        stats::count_scatter(hit.material.type_name());
+        let srec = hit.material.scatter(&ray, &hit, sampler)?;
+        if !srec.is_specular() {
+            // light scattered straight onto diffuse surfaces, or within media (which have no
+            // normal), is left to the path tracers
+            if !specular || hit.normal.squared_length() == 0.0 {
+                return None;
+            }
+            let power = match wavelengths {
+                Some(wavelengths) => wavelengths.to_rgb(&power),
+                None => power,
+            };
+            return Some((hit.point.clone(), Photon { direction: ray.direction().clone(), power }));
+        }
+
+        specular = true;
+        power = power * &ray.colour(srec.attenuation);
+        if srec.wavelength_dependent {
+            if let Some(ref mut wavelengths) = wavelengths {
+                wavelengths.terminate_secondary(&mut power);
+            }
+        }
+        ray = srec.ray.with_wavelengths(wavelengths);
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+    use crate::hitable::{Rectangle, Sphere};
+    use crate::material::{Dielectric, DiffuseLight, Lambertian};
+    use crate::texture;
+
+    // floor lit by a light above it, with a glass sphere between them if `glass`
+    fn scene(glass: bool) -> Scene<Vec<Box<dyn Hitable + Send + Sync>>> {
+        let camera = Camera::new(Vec3::new(0.0, 1.0, 5.0), Vec3::zeros(), Vec3::new(0.0, 1.0, 0.0),
+                                 40.0, 1.0, 0.0, 1.0, 0.0, 1.0);
+        let light = Rectangle::new_xz((-0.5, 0.5), (-0.5, 0.5), 4.0, DiffuseLight::new(texture::Constant::from_rgb(10.0, 10.0, 10.0)));
+        let mut hitables: Vec<Box<dyn Hitable + Send + Sync>> = vec![
+            Box::new(Rectangle::new_xz((-10.0, 10.0), (-10.0, 10.0), 0.0, Lambertian::new(texture::Constant::from_rgb(0.5, 0.5, 0.5)))),
+            Box::new(light.clone()),
+        ];
+        if glass {
+            hitables.push(Box::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 0.5, Dielectric::new(1.5))));
+        }
+        let lights: Vec<Box<dyn Hitable + Send + Sync>> = vec![Box::new(light)];
+        Scene { camera, hitables, lights, environment: None }
+    }
+
+    #[test]
+    fn test_caustics() {
+        let map = PhotonMap::caustics(&scene(false), 10000, false, 0);
+        assert!(map.is_empty());
+
+        let scene = scene(true);
+        let map = PhotonMap::caustics(&scene, 100_000, false, 0);
+        assert!(!map.is_empty());
+
+        // light is focused onto the floor below the sphere, but not far to the side of it
+        let mut sampler = sampler::Independent::new(0);
+        sampler.start_sample(0, 0);
+        let radiance_at = |x: f32, sampler: &mut dyn Sampler| {
+            let r = Ray::new(Vec3::new(x, 1.0, 0.5), Vec3::new(0.0, -1.0, -0.5));
+            let hit = scene.hitables[0].hit(&r, 0.001, f32::MAX, sampler).unwrap();
+            map.radiance(&r, &hit)
+        };
+        eprintln!("RAD {:?}", radiance_at(0.0, &mut sampler)); assert!(radiance_at(0.0, &mut sampler)[1] > 0.5);
+        assert_eq!(radiance_at(5.0, &mut sampler), Vec3::zeros());
+    }
+}
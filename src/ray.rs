@@ -1,10 +1,12 @@
 use crate::vec3::Vec3;
+use crate::spectrum::Wavelengths;
 
 #[derive(Clone, PartialEq)]
 pub struct Ray {
     a: Vec3,
     b: Vec3,
     time: f32,
+    wavelengths: Option<Wavelengths>,
 }
 
 impl Ray {
@@ -13,7 +15,14 @@ impl Ray {
     }
 
     pub fn new_at_time(a: Vec3, b: Vec3, time: f32) -> Self {
-        Ray { a, b, time }
+        Ray { a, b, time, wavelengths: None }
+    }
+
+    /// Set wavelengths of a spectral ray, whose radiance is carried at each wavelength rather than
+    /// as RGB.
+    pub fn with_wavelengths(mut self, wavelengths: Option<Wavelengths>) -> Self {
+        self.wavelengths = wavelengths;
+        self
     }
 
     pub fn origin(&self) -> &Vec3 {
@@ -28,6 +37,19 @@ impl Ray {
         self.time
     }
 
+    pub fn wavelengths(&self) -> Option<&Wavelengths> {
+        self.wavelengths.as_ref()
+    }
+
+    /// Converts an RGB colour to the form of radiance along this ray: unchanged for RGB rays, or
+    /// its spectrum at each wavelength for spectral rays.
+    pub fn colour(&self, rgb: Vec3) -> Vec3 {
+        match self.wavelengths {
+            Some(ref wavelengths) => wavelengths.upsample(&rgb),
+            None => rgb,
+        }
+    }
+
     pub fn point_at_parameter(&self, t: f32) -> Vec3 {
         let tmp = t * &self.b;
         &self.a + &tmp
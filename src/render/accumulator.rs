@@ -0,0 +1,410 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, Write};
+use std::fmt;
+use std::path::Path;
+
+use crate::vec3::Vec3;
+use crate::sampler::SamplerType;
+use crate::integrator::Integrator;
+use super::{Film, FilmPixel, Framebuffer};
+
+const CHECKPOINT_MAGIC: &[u8; 4] = b"RTCK";
+const CHECKPOINT_VERSION: u32 = 5;
+
+// longest name of a setting read from checkpoints
+const MAX_SETTING_LEN: u32 = 1024;
+
+// bytes each pixel's statistics, film pixel and splat take up in checkpoints
+const PIXEL_STATS_BYTES: u64 = 24;
+const FILM_PIXEL_BYTES: u64 = 16;
+const SPLAT_BYTES: u64 = 12;
+
+/// Running sum of a pixel's samples, along with the variance of their luminance (using Welford's
+/// algorithm).
+#[derive(Clone, Debug, PartialEq)]
+pub struct PixelStats {
+    count: u32,
+    sum: Vec3,
+    lum_mean: f32,
+    lum_m2: f32,
+}
+
+impl PixelStats {
+    pub fn new() -> Self {
+        Self { count: 0, sum: Vec3::zeros(), lum_mean: 0.0, lum_m2: 0.0 }
+    }
+
+    pub fn add(&mut self, col: Vec3) {
+        self.count += 1;
+        let lum = col.luminance();
+        let delta = lum - self.lum_mean;
+        self.lum_mean += delta / self.count as f32;
+        self.lum_m2 += delta * (lum - self.lum_mean);
+        self.sum += col;
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    pub fn mean(&self) -> Vec3 {
+        if self.count == 0 {
+            return Vec3::zeros();
+        }
+        &self.sum / self.count as f32
+    }
+
+    /// Standard error of the mean luminance relative to the mean, with a floor on the mean so
+    /// that near black pixels aren't sampled endlessly.
+    pub fn relative_error(&self) -> f32 {
+        if self.count < 2 {
+            return f32::MAX;
+        }
+        let variance = self.lum_m2 / (self.count - 1) as f32;
+        let standard_error = (variance / self.count as f32).sqrt();
+        standard_error / self.lum_mean.max(0.01)
+    }
+}
+
+impl Default for PixelStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Renderer settings which determine what samples estimate, recorded with accumulated samples so
+/// that they're only ever added to by the same estimator.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SampleSettings {
+    pub seed: u64,
+    pub sampler: SamplerType,
+    pub integrator: Integrator,
+    /// Reconstruction filter, as formatted by `Debug` (its type and parameters).
+    pub filter: String,
+}
+
+impl fmt::Display for SampleSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "seed {}, {} sampler, {} integrator and filter {}", self.seed, self.sampler, self.integrator, self.filter)
+    }
+}
+
+/// Per-pixel sample statistics, along with the film the samples have been splatted onto,
+/// accumulated over any number of rendering passes. These can be saved to and restored from
+/// checkpoint files.
+#[derive(Clone)]
+pub struct Accumulator {
+    width: u32,
+    height: u32,
+    pixels: Vec<PixelStats>,
+    film: Film,
+    settings: Option<SampleSettings>,
+}
+
+impl Accumulator {
+    pub fn new(width: u32, height: u32) -> Self {
+        let pixels = vec![PixelStats::new(); (width * height) as usize];
+        Self { width, height, pixels, film: Film::new(width, height), settings: None }
+    }
+
+    /// Set number of batches samples are split into on the film, for median of means estimation.
+    pub fn with_batches(mut self, batches: u32) -> Self {
+        self.film = Film::new(self.width, self.height).with_batches(batches);
+        self
+    }
+
+    /// Set the settings of the renderer the samples are from.
+    pub fn with_settings(mut self, settings: SampleSettings) -> Self {
+        self.settings = Some(settings);
+        self
+    }
+
+    /// Loads an accumulator previously written by `save`. Files which aren't checkpoints, or whose
+    /// sizes don't match their contents, are rejected as invalid data.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != CHECKPOINT_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a checkpoint file"));
+        }
+        let version = read_u32(&mut reader)?;
+        if version != CHECKPOINT_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("unsupported checkpoint version: {}", version)));
+        }
+
+        let settings = match read_u8(&mut reader)? {
+            0 => None,
+            _ => {
+                let seed = read_u64(&mut reader)?;
+                let sampler = read_string(&mut reader)?.parse().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                let integrator = read_string(&mut reader)?.parse().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                let filter = read_string(&mut reader)?;
+                Some(SampleSettings { seed, sampler, integrator, filter })
+            },
+        };
+        let width = read_u32(&mut reader)?;
+        let height = read_u32(&mut reader)?;
+        // sizes are checked against the file before allocating anything for them
+        let pixel_count = width.checked_mul(height)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("image size {}x{} is too large", width, height)))?;
+        if reader.stream_position()? + u64::from(pixel_count) * PIXEL_STATS_BYTES + 4 > len {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "checkpoint is truncated"));
+        }
+        let mut pixels = Vec::with_capacity(pixel_count as usize);
+        for _ in 0..pixel_count {
+            let count = read_u32(&mut reader)?;
+            let sum = Vec3::new(read_f32(&mut reader)?, read_f32(&mut reader)?, read_f32(&mut reader)?);
+            let lum_mean = read_f32(&mut reader)?;
+            let lum_m2 = read_f32(&mut reader)?;
+            pixels.push(PixelStats { count, sum, lum_mean, lum_m2 });
+        }
+        let batches = read_u32(&mut reader)?;
+        if batches == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "checkpoint has no batches of samples"));
+        }
+        let film_pixel_count = pixel_count.checked_mul(batches)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("{} batches is too many", batches)))?;
+        let expected = reader.stream_position()? + u64::from(film_pixel_count) * FILM_PIXEL_BYTES
+            + u64::from(pixel_count) * SPLAT_BYTES;
+        if expected != len {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("checkpoint is {} bytes, expected {}", len, expected)));
+        }
+        let mut film_pixels = Vec::with_capacity(film_pixel_count as usize);
+        for _ in 0..film_pixel_count {
+            let sum = Vec3::new(read_f32(&mut reader)?, read_f32(&mut reader)?, read_f32(&mut reader)?);
+            let weight = read_f32(&mut reader)?;
+            film_pixels.push(FilmPixel { sum, weight });
+        }
+        let mut splats = Vec::with_capacity(pixel_count as usize);
+        for _ in 0..pixel_count {
+            splats.push(Vec3::new(read_f32(&mut reader)?, read_f32(&mut reader)?, read_f32(&mut reader)?));
+        }
+
+        let film = Film::from_pixels(width, height, batches, film_pixels, splats);
+        Ok(Self { width, height, pixels, film, settings })
+    }
+
+    /// Writes accumulated statistics to a checkpoint file. The file is written alongside the
+    /// destination and then renamed over it, so an interrupted save never leaves a truncated
+    /// checkpoint behind.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+
+        {
+            let mut writer = BufWriter::new(File::create(&tmp_path)?);
+            writer.write_all(CHECKPOINT_MAGIC)?;
+            writer.write_all(&CHECKPOINT_VERSION.to_le_bytes())?;
+            match self.settings {
+                Some(ref settings) => {
+                    writer.write_all(&[1])?;
+                    writer.write_all(&settings.seed.to_le_bytes())?;
+                    write_string(&mut writer, &settings.sampler.to_string())?;
+                    write_string(&mut writer, &settings.integrator.to_string())?;
+                    write_string(&mut writer, &settings.filter)?;
+                },
+                None => writer.write_all(&[0])?,
+            }
+            writer.write_all(&self.width.to_le_bytes())?;
+            writer.write_all(&self.height.to_le_bytes())?;
+            for p in &self.pixels {
+                writer.write_all(&p.count.to_le_bytes())?;
+                for k in 0..3 {
+                    writer.write_all(&p.sum[k].to_bits().to_le_bytes())?;
+                }
+                writer.write_all(&p.lum_mean.to_bits().to_le_bytes())?;
+                writer.write_all(&p.lum_m2.to_bits().to_le_bytes())?;
+            }
+            writer.write_all(&self.film.batches().to_le_bytes())?;
+            for p in self.film.pixels() {
+                for k in 0..3 {
+                    writer.write_all(&p.sum[k].to_bits().to_le_bytes())?;
+                }
+                writer.write_all(&p.weight.to_bits().to_le_bytes())?;
+            }
+            for splat in self.film.splats() {
+                for k in 0..3 {
+                    writer.write_all(&splat[k].to_bits().to_le_bytes())?;
+                }
+            }
+            writer.flush()?;
+        }
+
+        fs::rename(&tmp_path, path)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Settings of the renderer the samples are from, if known.
+    pub fn settings(&self) -> Option<&SampleSettings> {
+        self.settings.as_ref()
+    }
+
+    pub fn get(&self, i: u32, j: u32) -> &PixelStats {
+        &self.pixels[(j * self.width + i) as usize]
+    }
+
+    /// Statistics for each pixel, in row-major order with row 0 at the top of the image.
+    pub fn pixels(&self) -> &[PixelStats] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [PixelStats] {
+        &mut self.pixels
+    }
+
+    pub fn film(&self) -> &Film {
+        &self.film
+    }
+
+    pub fn film_mut(&mut self) -> &mut Film {
+        &mut self.film
+    }
+
+    /// Total number of samples taken over all pixels.
+    pub fn total_samples(&self) -> u64 {
+        self.pixels.iter().map(|p| u64::from(p.count)).sum()
+    }
+
+    /// Filtered radiance and sample count of each pixel, plus any light splatted onto it.
+    pub fn to_framebuffer(&self) -> Framebuffer {
+        let mut fb = Framebuffer::new(self.width, self.height);
+        let splat_scale = (self.width * self.height) as f32 / self.total_samples().max(1) as f32;
+        for j in 0..self.height {
+            for i in 0..self.width {
+                let splat = &self.film.splats()[(j * self.width + i) as usize];
+                fb.set(i, j, self.film.colour(i, j) + splat_scale * splat);
+                fb.set_sample_count(i, j, self.get(i, j).count());
+            }
+        }
+        fb
+    }
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+// UTF-8 string, preceded by its length in bytes
+fn read_string<R: Read>(reader: &mut R) -> io::Result<String> {
+    let len = read_u32(reader)?;
+    if len > MAX_SETTING_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("setting is {} bytes long", len)));
+    }
+    let mut buf = vec![0; len as usize];
+    reader.read_exact(&mut buf)?;
+    String::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_string<W: Write>(writer: &mut W, s: &str) -> io::Result<()> {
+    writer.write_all(&(s.len() as u32).to_le_bytes())?;
+    writer.write_all(s.as_bytes())
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_f32<R: Read>(reader: &mut R) -> io::Result<f32> {
+    Ok(f32::from_bits(read_u32(reader)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::{Filter, Tent};
+
+    #[test]
+    fn test_pixel_stats() {
+        let mut stats = PixelStats::new();
+        assert_eq!(stats.relative_error(), f32::MAX);
+
+        stats.add(Vec3::new(1.0, 1.0, 1.0));
+        stats.add(Vec3::new(3.0, 3.0, 3.0));
+        assert_eq!(stats.count(), 2);
+        assert_eq!(stats.mean(), Vec3::new(2.0, 2.0, 2.0));
+        // variance 2, standard error 1, relative to mean of 2
+        assert!((stats.relative_error() - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let settings = SampleSettings { seed: 7, sampler: SamplerType::Halton, integrator: Integrator::Bdpt,
+                                        filter: "Tent { radius: 1.0 }".to_owned() };
+        let mut acc = Accumulator::new(3, 2).with_batches(2).with_settings(settings.clone());
+        let filter = Tent::new(1.0);
+        let mut tile = acc.film().tile((0, 0), (3, 2), filter.radius());
+        for (n, p) in acc.pixels_mut().iter_mut().enumerate() {
+            for k in 0..n {
+                let col = Vec3::new(k as f32, 0.5, 0.25 * n as f32);
+                tile.add_sample(((n % 3) as f32 + 0.2, (n / 3) as f32 + 0.7), &col, &filter, k as u32);
+                tile.add_splat((2.5, 0.5), &col);
+                p.add(col);
+            }
+        }
+        acc.film_mut().merge_tile(&tile);
+
+        let path = std::env::temp_dir().join(format!("rtracer-test-{}.ckpt", std::process::id()));
+        acc.save(&path).unwrap();
+        let loaded = Accumulator::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.width(), 3);
+        assert_eq!(loaded.height(), 2);
+        assert_eq!(loaded.settings(), Some(&settings));
+        assert_eq!(loaded.pixels(), acc.pixels());
+        assert_eq!(loaded.film().batches(), 2);
+        assert_eq!(loaded.film().pixels(), acc.film().pixels());
+        assert_eq!(loaded.film().splats(), acc.film().splats());
+        assert_eq!(loaded.total_samples(), 15);
+    }
+
+    #[test]
+    fn test_load_rejects_bad_sizes() {
+        let path = std::env::temp_dir().join(format!("rtracer-test-{}-bad.ckpt", std::process::id()));
+        Accumulator::new(3, 2).with_batches(2).save(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        let load = |bytes: &[u8]| {
+            fs::write(&path, bytes).unwrap();
+            Accumulator::load(&path).map(|_| ()).map_err(|e| e.kind())
+        };
+        assert_eq!(load(&bytes), Ok(()));
+
+        // width * height overflows, and then only fits in a much larger file
+        let mut huge = bytes.clone();
+        huge[9..13].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(load(&huge), Err(io::ErrorKind::InvalidData));
+        huge[9..13].copy_from_slice(&65536u32.to_le_bytes());
+        assert_eq!(load(&huge), Err(io::ErrorKind::InvalidData));
+        // too many batches for the film, and a truncated film
+        let batches_at = bytes.len() - 6 * (2 * FILM_PIXEL_BYTES + SPLAT_BYTES) as usize - 4;
+        let mut batches = bytes.clone();
+        batches[batches_at..batches_at + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(load(&batches), Err(io::ErrorKind::InvalidData));
+        assert_eq!(load(&bytes[..bytes.len() - 1]), Err(io::ErrorKind::InvalidData));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,376 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use image::{Rgb, RgbImage};
+
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::hitable::HitRecord;
+use crate::material::Material;
+use crate::output::{self, ExrWriter, OutputFormat};
+use crate::tonemap::ToneMapping;
+use super::Framebuffer;
+
+/// Arbitrary output variables: per-pixel data about the surfaces seen through each pixel, rather
+/// than the light leaving them, for use in compositing or denoising.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aov {
+    /// Distance from the camera to the first surface hit.
+    Depth,
+    /// World space surface normal, facing the side of the surface hit.
+    Normal,
+    /// Reflectance of the material hit, ignoring lighting.
+    Albedo,
+    /// World space position of the first surface hit.
+    Position,
+    /// Texture coordinates of the first surface hit.
+    Uv,
+    /// Matte identifying each object, numbered from 1 (0 for the background).
+    ObjectId,
+    /// Matte identifying each material, numbered from 1 (0 for the background). Materials are
+    /// identified by instance, so objects only share an ID if they share the same material value.
+    MaterialId,
+}
+
+impl Aov {
+    pub const NAMES: &'static [&'static str] = &["depth", "normal", "albedo", "position", "uv", "object-id", "material-id"];
+
+    /// Names of the EXR channels holding this AOV, following OpenEXR's naming conventions where
+    /// there are any.
+    pub fn channels(self) -> &'static [&'static str] {
+        match self {
+            Aov::Depth => &["Z"],
+            Aov::Normal => &["N.X", "N.Y", "N.Z"],
+            Aov::Albedo => &["albedo.R", "albedo.G", "albedo.B"],
+            Aov::Position => &["P.X", "P.Y", "P.Z"],
+            Aov::Uv => &["uv.U", "uv.V"],
+            Aov::ObjectId => &["objectId"],
+            Aov::MaterialId => &["materialId"],
+        }
+    }
+}
+
+impl FromStr for Aov {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "depth" => Ok(Aov::Depth),
+            "normal" => Ok(Aov::Normal),
+            "albedo" => Ok(Aov::Albedo),
+            "position" => Ok(Aov::Position),
+            "uv" => Ok(Aov::Uv),
+            "object-id" => Ok(Aov::ObjectId),
+            "material-id" => Ok(Aov::MaterialId),
+            _ => Err(format!("unknown AOV: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Aov {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Position => "position",
+            Aov::Uv => "uv",
+            Aov::ObjectId => "object-id",
+            Aov::MaterialId => "material-id",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Surface data gathered from the camera rays through a pixel. Continuous values are averaged over
+/// the rays which hit something, while IDs are those hit by most rays.
+pub(crate) struct AovPixel {
+    hits: u32,
+    depth: f32,
+    normal: Vec3,
+    albedo: Vec3,
+    position: Vec3,
+    uv: (f32, f32),
+    objects: Vec<(usize, u32)>,
+    materials: Vec<(usize, u32)>,
+}
+
+impl AovPixel {
+    pub fn new() -> Self {
+        Self {
+            hits: 0,
+            depth: 0.0,
+            normal: Vec3::zeros(),
+            albedo: Vec3::zeros(),
+            position: Vec3::zeros(),
+            uv: (0.0, 0.0),
+            objects: Vec::new(),
+            materials: Vec::new(),
+        }
+    }
+
+    /// Adds the first hit along a camera ray.
+    pub fn add(&mut self, r: &Ray, hit: &HitRecord) {
+        self.hits += 1;
+        self.depth += hit.t * r.direction().length();
+        // surfaces' normals may point either way, so flip them towards the camera
+        if hit.normal.dot(r.direction()) > 0.0 {
+            self.normal += &-hit.normal.clone();
+        } else {
+            self.normal += &hit.normal;
+        }
+        self.albedo += &hit.material.albedo(hit);
+        self.position += &hit.point;
+        self.uv = (self.uv.0 + hit.u, self.uv.1 + hit.v);
+        if let Some(object) = hit.object {
+            vote(&mut self.objects, object);
+        }
+        vote(&mut self.materials, hit.material as *const dyn Material as *const () as usize);
+    }
+}
+
+// counts a vote for `key`, keeping keys in the order they were first seen
+fn vote(votes: &mut Vec<(usize, u32)>, key: usize) {
+    match votes.iter_mut().find(|(k, _)| *k == key) {
+        Some((_, count)) => *count += 1,
+        None => votes.push((key, 1)),
+    }
+}
+
+// key with the most votes, with ties going to the first seen
+fn winner(votes: &[(usize, u32)]) -> Option<usize> {
+    let mut best: Option<(usize, u32)> = None;
+    for &(key, count) in votes {
+        if best.map_or(true, |(_, best_count)| count > best_count) {
+            best = Some((key, count));
+        }
+    }
+    best.map(|(key, _)| key)
+}
+
+// numbers keys from 1 in the order they first appear, with no key becoming 0
+fn remap_ids(keys: &[Option<usize>]) -> Vec<Vec3> {
+    let mut ids: HashMap<usize, usize> = HashMap::new();
+    keys.iter()
+        .map(|key| match key {
+            Some(key) => {
+                let next = ids.len() + 1;
+                let id = *ids.entry(*key).or_insert(next);
+                Vec3::new(id as f32, 0.0, 0.0)
+            },
+            None => Vec3::zeros(),
+        })
+        .collect()
+}
+
+/// A set of AOVs rendered for an image, stored in row-major order with row 0 at the top. Single
+/// valued AOVs are stored in the first component of each `Vec3`, and UVs in the first two.
+pub struct AovImage {
+    width: u32,
+    height: u32,
+    layers: Vec<(Aov, Vec<Vec3>)>,
+    // whether any camera ray through each pixel hit anything
+    covered: Vec<bool>,
+}
+
+impl AovImage {
+    pub(crate) fn from_pixels(width: u32, height: u32, aovs: &[Aov], pixels: &[AovPixel]) -> Self {
+        assert_eq!(pixels.len(), (width * height) as usize);
+
+        let layers = aovs.iter()
+            .map(|&aov| {
+                let values = match aov {
+                    Aov::ObjectId => remap_ids(&pixels.iter().map(|p| winner(&p.objects)).collect::<Vec<_>>()),
+                    Aov::MaterialId => remap_ids(&pixels.iter().map(|p| winner(&p.materials)).collect::<Vec<_>>()),
+                    _ => pixels.iter().map(|p| mean(aov, p)).collect(),
+                };
+                (aov, values)
+            })
+            .collect();
+        let covered = pixels.iter().map(|p| p.hits > 0).collect();
+        Self { width, height, layers, covered }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn aovs(&self) -> Vec<Aov> {
+        self.layers.iter().map(|(aov, _)| *aov).collect()
+    }
+
+    /// Values of an AOV, if it was rendered. Pixels where nothing was hit have infinite depth, and
+    /// zero for everything else.
+    pub fn get(&self, aov: Aov) -> Option<&[Vec3]> {
+        self.layers.iter().find(|(a, _)| *a == aov).map(|(_, values)| &values[..])
+    }
+
+    /// Adds the given AOVs, which must have been rendered, as channels of an EXR image.
+    pub fn add_to_exr(&self, exr: &mut ExrWriter, aovs: &[Aov]) {
+        for &aov in aovs {
+            let values = self.get(aov).unwrap_or_else(|| panic!("{} AOV wasn't rendered", aov));
+            for (k, name) in aov.channels().iter().enumerate() {
+                exr.add_channel(name, values.iter().map(|v| v[k]).collect());
+            }
+        }
+    }
+
+    /// Raw values of an AOV as an image, with single valued AOVs in every channel. As not every
+    /// format can hold infinity, pixels where nothing was hit have a depth of 0.
+    pub fn to_framebuffer(&self, aov: Aov) -> Framebuffer {
+        let values = self.get(aov).unwrap_or_else(|| panic!("{} AOV wasn't rendered", aov));
+        let mut fb = Framebuffer::new(self.width, self.height);
+        for (idx, v) in values.iter().enumerate() {
+            let col = match aov {
+                Aov::Depth | Aov::ObjectId | Aov::MaterialId => {
+                    let x = if v[0].is_finite() { v[0] } else { 0.0 };
+                    Vec3::new(x, x, x)
+                },
+                _ => v.clone(),
+            };
+            fb.set(idx as u32 % self.width, idx as u32 / self.width, col);
+        }
+        fb
+    }
+
+    /// Visualisation of an AOV as an 8-bit image: depth from white (nearest) to black (furthest),
+    /// normals, positions and UVs mapped to colours, and each ID given a random colour.
+    pub fn to_image(&self, aov: Aov) -> RgbImage {
+        let values = self.get(aov).unwrap_or_else(|| panic!("{} AOV wasn't rendered", aov));
+        let covered: Vec<&Vec3> = values.iter().zip(&self.covered).filter(|(_, &c)| c).map(|(v, _)| v).collect();
+        let (min, max) = bounds(&covered);
+
+        let colours: Vec<[u8; 3]> = values.iter().zip(&self.covered).map(|(v, &covered)| {
+            if !covered {
+                return [0; 3];
+            }
+            let col = match aov {
+                Aov::Albedo => return ToneMapping::default().to_colour(v),
+                Aov::Depth => {
+                    let range = max[0] - min[0];
+                    let x = if range > 0.0 { 1.0 - (v[0] - min[0]) / range } else { 1.0 };
+                    Vec3::new(x, x, x)
+                },
+                Aov::Normal => 0.5 * (v + 1.0),
+                Aov::Position => {
+                    let range = &max - &min;
+                    let mut col = v - &min;
+                    for k in 0..3 {
+                        col[k] = if range[k] > 0.0 { col[k] / range[k] } else { 0.5 };
+                    }
+                    col
+                },
+                Aov::Uv => Vec3::new(v[0], v[1], 0.0),
+                Aov::ObjectId | Aov::MaterialId => id_colour(v[0] as u32),
+            };
+            let mut rgb = [0; 3];
+            for (k, c) in rgb.iter_mut().enumerate() {
+                *c = (255.0 * col[k].clamp(0.0, 1.0) + 0.5) as u8;
+            }
+            rgb
+        }).collect();
+        RgbImage::from_fn(self.width, self.height, |i, j| Rgb(colours[(j * self.width + i) as usize]))
+    }
+
+    /// Writes an AOV to `path` in the given format, as a visualisation for 8-bit formats or raw
+    /// values for HDR formats.
+    pub fn save<P: AsRef<Path>>(&self, aov: Aov, path: P, format: OutputFormat) -> io::Result<()> {
+        match format {
+            OutputFormat::Png | OutputFormat::Jpeg => output::save_image(path, self.to_image(aov), format),
+            OutputFormat::Exr => {
+                let mut exr = ExrWriter::new(self.width, self.height);
+                for (k, name) in aov.channels().iter().enumerate() {
+                    exr.add_channel(name, self.get(aov).unwrap().iter().map(|v| v[k]).collect());
+                }
+                exr.save(path)
+            },
+            _ => self.to_framebuffer(aov).save(path, format, &ToneMapping::default()),
+        }
+    }
+}
+
+// average of the continuous AOVs, with normals renormalised
+fn mean(aov: Aov, p: &AovPixel) -> Vec3 {
+    if p.hits == 0 {
+        return match aov {
+            Aov::Depth => Vec3::new(f32::INFINITY, 0.0, 0.0),
+            _ => Vec3::zeros(),
+        };
+    }
+    let n = p.hits as f32;
+    match aov {
+        Aov::Depth => Vec3::new(p.depth / n, 0.0, 0.0),
+        Aov::Normal => {
+            let len = p.normal.length();
+            if len > 0.0 { &p.normal / len } else { Vec3::zeros() }
+        },
+        Aov::Albedo => &p.albedo / n,
+        Aov::Position => &p.position / n,
+        Aov::Uv => Vec3::new(p.uv.0 / n, p.uv.1 / n, 0.0),
+        Aov::ObjectId | Aov::MaterialId => unreachable!(),
+    }
+}
+
+fn bounds(values: &[&Vec3]) -> (Vec3, Vec3) {
+    let mut min = Vec3::new(f32::MAX, f32::MAX, f32::MAX);
+    let mut max = Vec3::new(f32::MIN, f32::MIN, f32::MIN);
+    for v in values {
+        for k in 0..3 {
+            min[k] = min[k].min(v[k]);
+            max[k] = max[k].max(v[k]);
+        }
+    }
+    (min, max)
+}
+
+// bright, well separated colour for an ID, from hashing it to a hue
+fn id_colour(id: u32) -> Vec3 {
+    let hue = (id.wrapping_mul(0x9e37_79b9) >> 8) as f32 / (1 << 24) as f32;
+    let h = 6.0 * hue;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+    match h as u32 {
+        0 => Vec3::new(1.0, x, 0.0),
+        1 => Vec3::new(x, 1.0, 0.0),
+        2 => Vec3::new(0.0, 1.0, x),
+        3 => Vec3::new(0.0, x, 1.0),
+        4 => Vec3::new(x, 0.0, 1.0),
+        _ => Vec3::new(1.0, 0.0, x),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aov_names() {
+        for name in Aov::NAMES {
+            let aov: Aov = name.parse().unwrap();
+            assert_eq!(&aov.to_string(), name);
+        }
+        assert!("beauty".parse::<Aov>().is_err());
+    }
+
+    #[test]
+    fn test_ids() {
+        let mut votes = Vec::new();
+        for &key in &[7, 3, 3, 7, 5] {
+            vote(&mut votes, key);
+        }
+        // ties go to the key seen first
+        assert_eq!(winner(&votes), Some(7));
+        assert_eq!(winner(&[]), None);
+
+        let ids: Vec<f32> = remap_ids(&[None, Some(40), Some(8), Some(40), None, Some(16)]).iter()
+            .map(|v| v[0])
+            .collect();
+        assert_eq!(ids, vec![0.0, 1.0, 2.0, 1.0, 0.0, 3.0]);
+    }
+}
@@ -0,0 +1,268 @@
+use std::cmp::Ordering;
+
+use crate::vec3::Vec3;
+use crate::filter::Filter;
+
+/// Filter weighted sum of the samples contributing to a pixel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FilmPixel {
+    pub sum: Vec3,
+    pub weight: f32,
+}
+
+impl FilmPixel {
+    pub fn new() -> Self {
+        Self { sum: Vec3::zeros(), weight: 0.0 }
+    }
+
+    /// Weighted average radiance of the pixel's samples.
+    pub fn colour(&self) -> Vec3 {
+        if self.weight == 0.0 {
+            return Vec3::zeros();
+        }
+        &self.sum / self.weight
+    }
+}
+
+impl Default for FilmPixel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Image reconstructed from samples at arbitrary positions on the film, each of which is splatted
+/// onto every pixel within its filter's radius.
+///
+/// Film coordinates are continuous pixel coordinates, with pixel (i, j) covering
+/// [i, i + 1) x [j, j + 1) and row 0 at the top of the image.
+///
+/// Samples can be split into batches by sample number, in which case each pixel is estimated by
+/// the median of its batches' means rather than the mean of every sample. The median of means is
+/// barely affected by rare, very bright samples (fireflies), which only ever land in a few of the
+/// batches, but is biased where the distribution of samples is skewed. In particular pixels come
+/// out too dark where most samples find little light, so each batch needs plenty of samples.
+///
+/// Light reaching the camera from paths traced from lights (which may land on any pixel, not just
+/// the one being sampled) is summed separately, unfiltered and unbatched, as splats.
+#[derive(Clone)]
+pub struct Film {
+    width: u32,
+    height: u32,
+    batches: u32,
+    // each pixel's batches are stored together
+    pixels: Vec<FilmPixel>,
+    splats: Vec<Vec3>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32) -> Self {
+        let splats = vec![Vec3::zeros(); (width * height) as usize];
+        Self::from_pixels(width, height, 1, vec![FilmPixel::new(); (width * height) as usize], splats)
+    }
+
+    /// Set number of batches samples are split into (1 by default, for the mean of all samples).
+    /// Any samples already added are discarded.
+    pub fn with_batches(mut self, batches: u32) -> Self {
+        assert!(batches > 0, "film needs at least one batch");
+        self.batches = batches;
+        self.pixels = vec![FilmPixel::new(); (self.width * self.height * batches) as usize];
+        self
+    }
+
+    pub(crate) fn from_pixels(width: u32, height: u32, batches: u32, pixels: Vec<FilmPixel>, splats: Vec<Vec3>) -> Self {
+        assert_eq!(pixels.len(), (width * height * batches) as usize);
+        assert_eq!(splats.len(), (width * height) as usize);
+        Self { width, height, batches, pixels, splats }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn batches(&self) -> u32 {
+        self.batches
+    }
+
+    /// Sum of all samples contributing to a pixel, whichever batches they're in.
+    pub fn get(&self, i: u32, j: u32) -> FilmPixel {
+        let mut total = FilmPixel::new();
+        for p in self.pixel_batches(i, j) {
+            total.sum += &p.sum;
+            total.weight += p.weight;
+        }
+        total
+    }
+
+    /// Estimated radiance of a pixel: the mean of its samples, or with more than one batch the
+    /// median (by luminance) of the means of the batches with samples in.
+    pub fn colour(&self, i: u32, j: u32) -> Vec3 {
+        if self.batches == 1 {
+            return self.get(i, j).colour();
+        }
+
+        let mut means: Vec<Vec3> = self.pixel_batches(i, j).iter().filter(|p| p.weight != 0.0).map(|p| p.colour()).collect();
+        if means.is_empty() {
+            return Vec3::zeros();
+        }
+        means.sort_by(|a, b| a.luminance().partial_cmp(&b.luminance()).unwrap_or(Ordering::Equal));
+        let mid = means.len() / 2;
+        if means.len() % 2 == 1 {
+            means.swap_remove(mid)
+        } else {
+            0.5 * (&means[mid - 1] + &means[mid])
+        }
+    }
+
+    /// Pixels in row-major order, with row 0 at the top of the image, and each pixel's batches
+    /// stored together.
+    pub fn pixels(&self) -> &[FilmPixel] {
+        &self.pixels
+    }
+
+    /// Sums of the light splatted onto each pixel, in row-major order. These are scaled by the
+    /// mean number of samples per pixel (one light path is traced per sample) to give radiance.
+    pub fn splats(&self) -> &[Vec3] {
+        &self.splats
+    }
+
+    fn pixel_batches(&self, i: u32, j: u32) -> &[FilmPixel] {
+        let start = ((j * self.width + i) * self.batches) as usize;
+        &self.pixels[start..start + self.batches as usize]
+    }
+
+    /// Tile for adding samples within pixels [x0, x1) x [y0, y1), covering every pixel that
+    /// those samples can contribute to with the given filter radius.
+    pub fn tile(&self, (x0, y0): (u32, u32), (x1, y1): (u32, u32), radius: f32) -> FilmTile {
+        // samples reach pixels whose centres are less than `radius` away
+        let margin = (radius - 0.5).ceil().max(0.0) as u32;
+        let (x0, y0) = (x0.saturating_sub(margin), y0.saturating_sub(margin));
+        let (x1, y1) = ((x1 + margin).min(self.width), (y1 + margin).min(self.height));
+        let pixels = vec![FilmPixel::new(); ((x1 - x0) * (y1 - y0) * self.batches) as usize];
+        FilmTile { x0, y0, x1, y1, batches: self.batches, pixels, splats: Vec::new() }
+    }
+
+    /// Adds the samples splatted onto a tile.
+    pub fn merge_tile(&mut self, tile: &FilmTile) {
+        assert_eq!(tile.batches, self.batches);
+        let row_len = ((tile.x1 - tile.x0) * self.batches) as usize;
+        for j in tile.y0..tile.y1 {
+            let src_start = (j - tile.y0) as usize * row_len;
+            let dst_start = ((j * self.width + tile.x0) * self.batches) as usize;
+            let src = &tile.pixels[src_start..src_start + row_len];
+            for (dst, src) in self.pixels[dst_start..dst_start + row_len].iter_mut().zip(src) {
+                dst.sum += &src.sum;
+                dst.weight += src.weight;
+            }
+        }
+
+        for ((x, y), col) in &tile.splats {
+            let i = (*x as u32).min(self.width - 1);
+            let j = (*y as u32).min(self.height - 1);
+            self.splats[(j * self.width + i) as usize] += col;
+        }
+    }
+}
+
+/// Part of a film that samples are added to independently of the rest, so that separate parts of
+/// an image can be rendered in parallel. Tiles overlap where samples near their edges are
+/// splatted onto neighbouring pixels, and are merged back into the film in a fixed order so that
+/// the result doesn't depend on thread scheduling.
+pub struct FilmTile {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+    batches: u32,
+    pixels: Vec<FilmPixel>,
+    splats: Vec<((f32, f32), Vec3)>,
+}
+
+impl FilmTile {
+    /// Splats the radiance of a sample at film position (x, y) onto nearby pixels. The sample's
+    /// number (among those taken for the pixel it's in) decides which batch it's added to.
+    pub fn add_sample(&mut self, (x, y): (f32, f32), col: &Vec3, filter: &dyn Filter, index: u32) {
+        let radius = filter.radius();
+        // range of pixels whose centres are within the radius, clipped to the tile
+        let i0 = ((x - 0.5 - radius).ceil().max(0.0) as u32).max(self.x0);
+        let j0 = ((y - 0.5 - radius).ceil().max(0.0) as u32).max(self.y0);
+        let i1 = ((x - 0.5 + radius).floor() + 1.0).max(0.0) as u32;
+        let j1 = ((y - 0.5 + radius).floor() + 1.0).max(0.0) as u32;
+
+        let tile_width = self.x1 - self.x0;
+        for j in j0..j1.min(self.y1) {
+            for i in i0..i1.min(self.x1) {
+                let weight = filter.evaluate(x - (i as f32 + 0.5), y - (j as f32 + 0.5));
+                if weight != 0.0 {
+                    let idx = ((j - self.y0) * tile_width + i - self.x0) * self.batches + index % self.batches;
+                    let p = &mut self.pixels[idx as usize];
+                    p.sum += weight * col;
+                    p.weight += weight;
+                }
+            }
+        }
+    }
+
+    /// Adds light arriving at film position (x, y), anywhere on the film, from a path traced from
+    /// a light.
+    pub fn add_splat(&mut self, (x, y): (f32, f32), col: &Vec3) {
+        self.splats.push(((x, y), col.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::{BoxFilter, Tent};
+
+    #[test]
+    fn test_box_filter_averages_pixel() {
+        let mut film = Film::new(3, 3);
+        let mut tile = film.tile((0, 0), (3, 3), 0.5);
+        let filter = BoxFilter::new(0.5);
+        tile.add_sample((1.1, 1.2), &Vec3::new(1.0, 2.0, 3.0), &filter, 0);
+        tile.add_sample((1.9, 1.0), &Vec3::new(3.0, 2.0, 1.0), &filter, 1);
+        film.merge_tile(&tile);
+
+        assert_eq!(film.get(1, 1).colour(), Vec3::new(2.0, 2.0, 2.0));
+        assert_eq!(film.pixels().iter().filter(|p| p.weight > 0.0).count(), 1);
+    }
+
+    #[test]
+    fn test_tent_filter_splats_neighbours() {
+        let mut film = Film::new(4, 4);
+        let filter = Tent::new(1.0);
+        // samples in the tile's pixels also reach the pixels next to them
+        let mut tile = film.tile((1, 1), (2, 2), filter.radius());
+        tile.add_sample((1.5, 1.75), &Vec3::new(1.0, 1.0, 1.0), &filter, 0);
+        film.merge_tile(&tile);
+
+        assert_eq!(film.get(1, 1).weight, 0.75);
+        assert_eq!(film.get(1, 2).weight, 0.25);
+        assert_eq!(film.get(0, 1).weight, 0.0);
+        assert_eq!(film.get(1, 0).weight, 0.0);
+        assert_eq!(film.get(1, 2).colour(), Vec3::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn test_median_of_means_rejects_outliers() {
+        let mut film = Film::new(2, 1).with_batches(3);
+        let filter = BoxFilter::new(0.5);
+        let mut tile = film.tile((0, 0), (2, 1), filter.radius());
+        for index in 0..6 {
+            let col = if index == 4 { Vec3::new(1000.0, 1000.0, 1000.0) } else { Vec3::new(1.0, 2.0, 3.0) };
+            tile.add_sample((0.5, 0.5), &col, &filter, index);
+        }
+        // only two batches have samples in the second pixel
+        tile.add_sample((1.5, 0.5), &Vec3::new(1.0, 1.0, 1.0), &filter, 0);
+        tile.add_sample((1.5, 0.5), &Vec3::new(3.0, 3.0, 3.0), &filter, 1);
+        film.merge_tile(&tile);
+
+        assert_eq!(film.colour(0, 0), Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(film.get(0, 0).weight, 6.0);
+        assert_eq!(film.colour(1, 0), Vec3::new(2.0, 2.0, 2.0));
+    }
+}
@@ -0,0 +1,135 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use image::{Rgb, RgbImage};
+
+use crate::vec3::Vec3;
+use crate::output::{self, ExrWriter, OutputFormat, PixelType};
+use crate::tonemap::ToneMapping;
+
+/// Grid of linear (not gamma corrected) radiance values, stored in row-major order with row 0 at
+/// the top of the image, along with the number of samples taken for each pixel.
+#[derive(Clone)]
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<Vec3>,
+    samples: Vec<u32>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        let pixels = vec![Vec3::zeros(); (width * height) as usize];
+        let samples = vec![0; (width * height) as usize];
+        Self { width, height, pixels, samples }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[Vec3] {
+        &self.pixels
+    }
+
+    /// Number of samples taken for each pixel, in the same order as `pixels`.
+    pub fn samples(&self) -> &[u32] {
+        &self.samples
+    }
+
+    pub fn get(&self, i: u32, j: u32) -> &Vec3 {
+        &self.pixels[self.index(i, j)]
+    }
+
+    pub fn set(&mut self, i: u32, j: u32, col: Vec3) {
+        let idx = self.index(i, j);
+        self.pixels[idx] = col;
+    }
+
+    pub fn sample_count(&self, i: u32, j: u32) -> u32 {
+        self.samples[self.index(i, j)]
+    }
+
+    pub fn set_sample_count(&mut self, i: u32, j: u32, samples: u32) {
+        let idx = self.index(i, j);
+        self.samples[idx] = samples;
+    }
+
+    /// Converts to an 8-bit sRGB image, tone mapping each pixel.
+    pub fn to_image(&self, tone_mapping: &ToneMapping) -> RgbImage {
+        RgbImage::from_fn(self.width, self.height, |i, j| Rgb(tone_mapping.to_colour(self.get(i, j))))
+    }
+
+    /// Writes the image to `path` in the given format. Tone mapping only applies to 8-bit formats,
+    /// with HDR formats holding radiance as rendered. EXR images are written with 32-bit float
+    /// channels.
+    pub fn save<P: AsRef<Path>>(&self, path: P, format: OutputFormat, tone_mapping: &ToneMapping) -> io::Result<()> {
+        let mut writer = match format {
+            OutputFormat::Png | OutputFormat::Jpeg => return output::save_image(path, self.to_image(tone_mapping), format),
+            OutputFormat::Exr => return self.save_exr(path, PixelType::Float),
+            OutputFormat::Hdr | OutputFormat::Pfm => BufWriter::new(File::create(path)?),
+        };
+        if format == OutputFormat::Hdr {
+            output::write_hdr(&mut writer, self.width, self.height, &self.pixels)?;
+        } else {
+            output::write_pfm(&mut writer, self.width, self.height, &self.pixels)?;
+        }
+        writer.flush()
+    }
+
+    /// Writes the image as an OpenEXR file with R, G and B channels of the given type.
+    pub fn save_exr<P: AsRef<Path>>(&self, path: P, pixel_type: PixelType) -> io::Result<()> {
+        self.to_exr(pixel_type).save(path)
+    }
+
+    /// EXR writer holding the image as R, G and B channels of the given type, which further layers
+    /// can be added to.
+    pub fn to_exr(&self, pixel_type: PixelType) -> ExrWriter {
+        let mut exr = ExrWriter::new(self.width, self.height).with_pixel_type(pixel_type);
+        exr.add_rgb("", &self.pixels);
+        exr
+    }
+
+    /// False colour image of the number of samples taken per pixel, from blue (fewest) through
+    /// green to red (most).
+    pub fn sample_heatmap(&self) -> RgbImage {
+        let min = self.samples.iter().cloned().min().unwrap_or(0) as f32;
+        let max = self.samples.iter().cloned().max().unwrap_or(0) as f32;
+        RgbImage::from_fn(self.width, self.height, |i, j| {
+            let col = heat_colour(self.sample_count(i, j) as f32, min, max);
+            Rgb([(255.0 * col[0]) as u8, (255.0 * col[1]) as u8, (255.0 * col[2]) as u8])
+        })
+    }
+
+    /// False colour image of the first channel of each pixel, from blue (lowest) through green to
+    /// red (highest), with the same sample counts.
+    pub fn false_colour(&self) -> Framebuffer {
+        let min = self.pixels.iter().fold(f32::MAX, |min, p| min.min(p[0]));
+        let max = self.pixels.iter().fold(f32::MIN, |max, p| max.max(p[0]));
+        let pixels = self.pixels.iter().map(|p| heat_colour(p[0], min, max)).collect();
+        Self { width: self.width, height: self.height, pixels, samples: self.samples.clone() }
+    }
+
+    fn index(&self, i: u32, j: u32) -> usize {
+        (j * self.width + i) as usize
+    }
+}
+
+// colour of `x` on a scale from blue (`min`) through green to red (`max`)
+fn heat_colour(x: f32, min: f32, max: f32) -> Vec3 {
+    let t = if max > min {
+        ((x - min) / (max - min)).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    if t < 0.5 {
+        Vec3::new(0.0, 2.0 * t, 1.0 - 2.0 * t)
+    } else {
+        Vec3::new(2.0 * t - 1.0, 2.0 - 2.0 * t, 0.0)
+    }
+}
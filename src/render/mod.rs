@@ -0,0 +1,921 @@
+//! Renders a scene into an in-memory framebuffer of linear radiance values.
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use rayon::prelude::*;
+use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::config::Config;
+use crate::integrator::{self, Integrator, Metropolis, PathLength, PathObserver, Precomputed};
+use crate::hitable::Hitable;
+use crate::scenes::Scene;
+use crate::sampler::{Sampler, SamplerType};
+use crate::filter::{Filter, FilterType};
+use crate::spectrum::Wavelengths;
+use crate::photon::PhotonMap;
+use crate::guiding::{GuideRecorder, PathGuide};
+use crate::stats;
+use crate::output::OutputFormat;
+use crate::tonemap::ToneMapping;
+
+mod framebuffer;
+pub use framebuffer::Framebuffer;
+
+mod film;
+pub use film::{Film, FilmPixel, FilmTile};
+
+mod accumulator;
+pub use accumulator::{Accumulator, PixelStats, SampleSettings};
+
+mod aov;
+pub use aov::{Aov, AovImage};
+use aov::AovPixel;
+
+mod tiles;
+pub use tiles::TileOrder;
+
+// number of cells along each side of the grid path guiding learns over
+const GUIDE_RESOLUTION: usize = 8;
+
+/// Settings for estimating caustics with a photon map.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CausticPhotons {
+    /// Number of photons emitted from the scene's lights.
+    pub photons: usize,
+    /// Maximum distance photons are gathered from, defaulting to 1% of the size of the scene.
+    pub max_radius: Option<f32>,
+}
+
+/// Settings for adaptive sampling, where pixels keep being sampled until their estimated noise
+/// drops below a threshold.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdaptiveSampling {
+    /// Pixels stop being sampled once the standard error of their mean luminance, relative to
+    /// that luminance, drops below this.
+    pub noise_threshold: f32,
+    /// Most samples to take for any pixel.
+    pub max_samples: u32,
+}
+
+/// Where and how often to save the render in progress, so that it can be resumed later.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Checkpoint {
+    pub path: PathBuf,
+    /// Minimum time between saves. Checkpoints are only written between passes, so may be
+    /// written less often than this.
+    pub interval: Duration,
+}
+
+/// Where and how often to write the image while it's rendering, so it can be watched filling in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Preview {
+    pub path: PathBuf,
+    pub format: OutputFormat,
+    pub tone_mapping: ToneMapping,
+    /// Minimum time between writes. The preview is updated as each tile finishes, and written
+    /// once more when rendering finishes.
+    pub interval: Duration,
+}
+
+pub struct Renderer {
+    width: u32,
+    height: u32,
+    samples: u32,
+    seed: u64,
+    sampler: SamplerType,
+    filter: Box<dyn Filter>,
+    adaptive: Option<AdaptiveSampling>,
+    batches: u32,
+    pass_samples: Option<u32>,
+    checkpoint: Option<Checkpoint>,
+    preview: Option<Preview>,
+    tile_size: u32,
+    tile_order: TileOrder,
+    path_length: PathLength,
+    integrator: Integrator,
+    metropolis: Metropolis,
+    spectral: bool,
+    caustics: Option<CausticPhotons>,
+    path_guiding: bool,
+    ao_radius: Option<f32>,
+    progress: bool,
+}
+
+impl Renderer {
+    /// New renderer for images of the given size, defaulting to the naive path tracer with 10
+    /// samples per pixel, a maximum path depth of 50 and Russian roulette after 3 bounces. Each
+    /// pixel is the unweighted average of the samples within it (a box filter). Images are
+    /// rendered in 16x16 pixel tiles, spiralling out from the centre.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            samples: 10,
+            seed: 0,
+            sampler: SamplerType::default(),
+            filter: FilterType::Box.new_filter(FilterType::Box.default_radius()),
+            adaptive: None,
+            batches: 1,
+            pass_samples: None,
+            checkpoint: None,
+            preview: None,
+            tile_size: 16,
+            tile_order: TileOrder::default(),
+            path_length: PathLength::default(),
+            integrator: Integrator::default(),
+            metropolis: Metropolis::new(),
+            spectral: false,
+            caustics: None,
+            path_guiding: false,
+            ao_radius: None,
+            progress: false,
+        }
+    }
+
+    pub fn from_config(conf: &Config) -> Self {
+        let mut renderer = Self::new(conf.width(), conf.height())
+            .with_samples(conf.samples())
+            .with_seed(conf.seed())
+            .with_sampler(conf.sampler())
+            .with_filter(conf.filter().new_filter(conf.filter_radius()))
+            .with_max_depth(conf.max_depth())
+            .with_min_depth(conf.min_depth())
+            .with_integrator(conf.integrator())
+            .with_spectral(conf.spectral())
+            .with_path_guiding(conf.path_guiding())
+            .with_tile_size(conf.tile_size())
+            .with_tile_order(conf.tile_order())
+            .with_progress(true);
+
+        if let Some(ao_radius) = conf.ao_radius() {
+            renderer = renderer.with_ao_radius(ao_radius);
+        }
+        if let Some(noise_threshold) = conf.noise_threshold() {
+            renderer = renderer.with_adaptive_sampling(noise_threshold, conf.max_samples());
+        }
+        if let Some(batches) = conf.median_of_means() {
+            renderer = renderer.with_median_of_means(batches);
+        }
+        if let Some(clamp) = conf.indirect_clamp() {
+            renderer = renderer.with_indirect_clamp(clamp);
+        }
+        if let Some(pass_samples) = conf.pass_samples() {
+            renderer = renderer.with_pass_samples(pass_samples);
+        }
+        if let Some(checkpoint) = conf.checkpoint() {
+            renderer = renderer.with_checkpoint(checkpoint, conf.checkpoint_interval());
+        }
+        if let Some(preview) = conf.preview() {
+            renderer = renderer.with_preview(preview, conf.format(), conf.tone_mapping(), conf.preview_interval());
+        }
+        if let Some(photons) = conf.caustic_photons() {
+            renderer = renderer.with_caustic_photons(photons, conf.caustic_radius());
+        }
+        renderer
+    }
+
+    /// Set number of samples per pixel (for antialiasing). With adaptive sampling this is the
+    /// number of samples taken before checking whether a pixel has converged.
+    pub fn with_samples(mut self, samples: u32) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    /// Set seed used to generate random numbers. Samples depend only on this and the pixel and
+    /// sample index (not on thread scheduling), so images are identical between runs with the
+    /// same seed.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Set sampler used to generate sample dimensions (e.g. pixel and lens positions).
+    pub fn with_sampler(mut self, sampler: SamplerType) -> Self {
+        self.sampler = sampler;
+        self
+    }
+
+    /// Set filter used to reconstruct pixels from the samples around them.
+    pub fn with_filter(mut self, filter: Box<dyn Filter>) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Keep sampling pixels until their relative noise is below `noise_threshold`, or
+    /// `max_samples` have been taken.
+    pub fn with_adaptive_sampling(mut self, noise_threshold: f32, max_samples: u32) -> Self {
+        self.adaptive = Some(AdaptiveSampling { noise_threshold, max_samples });
+        self
+    }
+
+    /// Estimate pixels with the median of means of the given number of batches of samples,
+    /// rather than the mean of all samples, to suppress fireflies (see `Film`). There's always at
+    /// least one batch, which is the plain mean.
+    pub fn with_median_of_means(mut self, batches: u32) -> Self {
+        self.batches = batches.max(1);
+        self
+    }
+
+    /// Render progressively, adding at most this many samples to each pixel per pass over the
+    /// image. By default all samples are taken in a single pass.
+    pub fn with_pass_samples(mut self, pass_samples: u32) -> Self {
+        self.pass_samples = Some(pass_samples.max(1));
+        self
+    }
+
+    /// Save accumulated samples to a checkpoint file between passes, at most once per
+    /// `interval`, and once more when rendering finishes.
+    pub fn with_checkpoint<P: AsRef<Path>>(mut self, path: P, interval: Duration) -> Self {
+        self.checkpoint = Some(Checkpoint { path: path.as_ref().to_path_buf(), interval });
+        self
+    }
+
+    /// Write the image in progress to `path` as tiles finish, at most once per `interval`, and
+    /// once more when rendering finishes. Pixels are shown as the mean of their samples so far,
+    /// without the reconstruction filter or light splatted from elsewhere in the image (by the
+    /// bidirectional path tracer). Has no effect on Metropolis light transport, which renders the
+    /// whole image at once.
+    pub fn with_preview<P: AsRef<Path>>(mut self, path: P, format: OutputFormat, tone_mapping: ToneMapping,
+                                        interval: Duration) -> Self {
+        self.preview = Some(Preview { path: path.as_ref().to_path_buf(), format, tone_mapping, interval });
+        self
+    }
+
+    /// Set width and height in pixels of the tiles images are divided into, which are rendered in
+    /// parallel.
+    pub fn with_tile_size(mut self, tile_size: u32) -> Self {
+        self.tile_size = tile_size.max(1);
+        self
+    }
+
+    /// Set order in which tiles are rendered.
+    pub fn with_tile_order(mut self, tile_order: TileOrder) -> Self {
+        self.tile_order = tile_order;
+        self
+    }
+
+    /// Set number of bounces after which paths are terminated.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.path_length.max_depth = max_depth;
+        self
+    }
+
+    /// Set number of bounces after which paths may be terminated by Russian roulette.
+    pub fn with_min_depth(mut self, min_depth: usize) -> Self {
+        self.path_length.min_depth = min_depth;
+        self
+    }
+
+    /// Clamp indirect light carried by each path (see `PathLength::indirect_clamp`).
+    pub fn with_indirect_clamp(mut self, clamp: f32) -> Self {
+        self.path_length.indirect_clamp = Some(clamp);
+        self
+    }
+
+    pub fn with_integrator(mut self, integrator: Integrator) -> Self {
+        self.integrator = integrator;
+        self
+    }
+
+    /// Set how Markov chains explore paths with the `Mlt` integrator.
+    pub fn with_metropolis(mut self, metropolis: Metropolis) -> Self {
+        self.metropolis = metropolis;
+        self
+    }
+
+    /// Trace each camera ray at a few randomly sampled wavelengths rather than as RGB, which lets
+    /// dispersive materials split light into its colours. This adds some colour noise.
+    pub fn with_spectral(mut self, spectral: bool) -> Self {
+        self.spectral = spectral;
+        self
+    }
+
+    /// Before rendering, trace `photons` photons from the scene's lights and estimate caustics
+    /// from those reaching diffuse surfaces through specular ones, rather than relying on paths
+    /// finding lights through glass by chance. Caustics become smooth, but blurred by up to
+    /// `max_radius`. Has no effect on the bidirectional path tracer.
+    pub fn with_caustic_photons(mut self, photons: usize, max_radius: Option<f32>) -> Self {
+        self.caustics = Some(CausticPhotons { photons, max_radius });
+        self
+    }
+
+    /// Learn where light arrives at each part of the scene from as the image is rendered, and
+    /// scatter paths towards it as well as according to materials. Helps most where light is hard
+    /// to find by scattering alone, e.g. a small sun in an environment map, or light reaching a
+    /// room through a gap. Unless a pass size is set, passes start at one sample per pixel and
+    /// double in size, with what's learned updated after each. What's learned isn't kept in
+    /// checkpoints. Has no effect on the bidirectional path tracer or Metropolis light transport.
+    pub fn with_path_guiding(mut self, path_guiding: bool) -> Self {
+        self.path_guiding = path_guiding;
+        self
+    }
+
+    /// Set distance within which surfaces occlude each other for the ambient occlusion debug
+    /// integrator (default: 10% of the scene's size).
+    pub fn with_ao_radius(mut self, ao_radius: f32) -> Self {
+        self.ao_radius = Some(ao_radius);
+        self
+    }
+
+    /// Display a progress bar on stderr while rendering.
+    pub fn with_progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn aspect_ratio(&self) -> f32 {
+        self.width as f32 / self.height as f32
+    }
+
+    pub fn render<T: Hitable + Send + Sync>(&self, scene: &Scene<T>) -> Framebuffer {
+        if self.integrator == Integrator::Mlt {
+            return self.render_metropolis(scene);
+        }
+        let acc = Accumulator::new(self.width, self.height).with_batches(self.batches)
+            .with_settings(self.sample_settings());
+        self.render_passes(scene, acc)
+    }
+
+    /// Continues rendering from previously accumulated samples (e.g. loaded from a checkpoint),
+    /// until every pixel has the number of samples this renderer is configured for. Fails if the
+    /// samples don't fit this renderer: if they're for an image of a different size or split into
+    /// a different number of batches, were rendered with different settings (seed, sampler,
+    /// integrator or filter), or the renderer uses Metropolis light transport.
+    pub fn resume<T: Hitable + Send + Sync>(&self, scene: &Scene<T>, acc: Accumulator) -> Result<Framebuffer, String> {
+        if acc.width() != self.width || acc.height() != self.height {
+            return Err(format!("accumulated image is {}x{}, expected {}x{}",
+                               acc.width(), acc.height(), self.width, self.height));
+        }
+        if acc.film().batches() != self.batches {
+            return Err(format!("accumulated samples are split into {} batches, expected {}",
+                               acc.film().batches(), self.batches));
+        }
+        let settings = self.sample_settings();
+        if let Some(acc_settings) = acc.settings() {
+            if *acc_settings != settings {
+                return Err(format!("accumulated samples were rendered with {}, expected {}", acc_settings, settings));
+            }
+        }
+        if self.integrator == Integrator::Mlt {
+            return Err("Metropolis light transport renders can't be resumed".to_owned());
+        }
+        Ok(self.render_passes(scene, acc.with_settings(settings)))
+    }
+
+    // settings which samples depend on, which must match to add to previously accumulated samples
+    fn sample_settings(&self) -> SampleSettings {
+        SampleSettings {
+            seed: self.seed,
+            sampler: self.sampler,
+            integrator: self.integrator,
+            filter: format!("{:?}", self.filter),
+        }
+    }
+
+    // adds passes of samples to `acc` until every pixel has enough
+    fn render_passes<T: Hitable + Send + Sync>(&self, scene: &Scene<T>, mut acc: Accumulator) -> Framebuffer {
+        let start = Instant::now();
+        let caustics = self.photon_map(scene);
+        let mut guide = match self.integrator {
+            Integrator::Path | Integrator::Direct | Integrator::Mis if self.path_guiding => PathGuide::new(scene, GUIDE_RESOLUTION),
+            _ => None,
+        };
+        let ao_radius = Some(self.ao_radius.unwrap_or_else(|| integrator::default_ao_radius(scene)));
+        let pb = self.progress_bar(&acc);
+        let mut pass_samples = match self.pass_samples {
+            Some(pass_samples) => pass_samples,
+            None if guide.is_some() => 1,
+            None => self.max_samples(),
+        };
+        let mut last_checkpoint = Instant::now();
+        // image in progress, and when it was last written
+        let preview = self.preview.as_ref().map(|settings| (settings, Mutex::new((acc.to_framebuffer(), Instant::now()))));
+
+        loop {
+            let precomputed = Precomputed { caustics: caustics.as_ref(), guide: guide.as_ref(), ao_radius };
+            let mut tiles = self.tiles(acc.film());
+            let mut recorders: Vec<Option<GuideRecorder>> = tiles.iter()
+                .map(|_| guide.as_ref().map(|guide| guide.recorder()))
+                .collect();
+            let tile_count = tiles.len();
+            let finished = AtomicUsize::new(0);
+            pb.set_message(&format!("tiles:0/{}", tile_count));
+            // bridging (rather than splitting the tiles between threads) has threads take tiles in
+            // order, so the image fills in in that order
+            let active: usize = tiles.iter_mut()
+                .zip(recorders.iter_mut())
+                .zip(self.tile_pixels(acc.pixels_mut()))
+                .par_bridge()
+                .map(|((tile, recorder), mut pixels)| {
+                    let mut observer = TileObserver { tile, training: recorder.as_mut(), wavelengths: None, width: self.width, height: self.height };
+                    let mut active = 0;
+                    for (idx, stats) in pixels.iter_mut() {
+                        let start = stats.count();
+                        self.render_pixel(scene, precomputed, *idx, stats, &mut observer, pass_samples);
+
+                        let converged = self.converged(stats);
+                        match self.adaptive {
+                            Some(_) => if converged && stats.count() > start { pb.inc(1) },
+                            None => pb.inc(u64::from(stats.count() - start)),
+                        }
+                        if !converged {
+                            active += 1;
+                        }
+                    }
+
+                    if let Some((settings, ref preview)) = preview {
+                        self.update_preview(settings, preview, &pixels);
+                    }
+                    let finished = finished.fetch_add(1, Ordering::Relaxed) + 1;
+                    pb.set_message(&format!("tiles:{}/{}", finished, tile_count));
+                    active
+                })
+                .sum();
+
+            // merging in order keeps the sums of samples splatted onto tile edges deterministic
+            for tile in &tiles {
+                acc.film_mut().merge_tile(tile);
+            }
+            // likewise learning from tiles in order keeps what's learned deterministic
+            if let Some(ref mut guide) = guide {
+                guide.train(&recorders.into_iter().flatten().collect::<Vec<_>>());
+                if self.pass_samples.is_none() {
+                    pass_samples = pass_samples.saturating_mul(2);
+                }
+            }
+
+            if active == 0 {
+                break;
+            }
+
+            if let Some(ref checkpoint) = self.checkpoint {
+                if last_checkpoint.elapsed() >= checkpoint.interval {
+                    save_checkpoint(&acc, &checkpoint.path);
+                    last_checkpoint = Instant::now();
+                }
+            }
+        }
+        pb.finish_with_message("done");
+
+        if let Some(ref checkpoint) = self.checkpoint {
+            save_checkpoint(&acc, &checkpoint.path);
+        }
+        stats::count(|stats| stats.render_time += start.elapsed());
+
+        let fb = self.visualise(acc.to_framebuffer());
+        if let Some(ref preview) = self.preview {
+            save_preview(&fb, preview);
+        }
+        fb
+    }
+
+    // copies the means of a finished tile's pixels into the preview, and writes it if it hasn't
+    // been for long enough
+    fn update_preview(&self, settings: &Preview, preview: &Mutex<(Framebuffer, Instant)>,
+                      pixels: &[(usize, &mut PixelStats)]) {
+        let mut preview = preview.lock().unwrap();
+        let (ref mut fb, ref mut last_write) = *preview;
+        for (idx, stats) in pixels {
+            let (i, j) = (*idx as u32 % self.width, *idx as u32 / self.width);
+            fb.set(i, j, stats.mean());
+            fb.set_sample_count(i, j, stats.count());
+        }
+        if last_write.elapsed() >= settings.interval {
+            save_preview(&self.visualise(fb.clone()), settings);
+            *last_write = Instant::now();
+        }
+    }
+
+    // debug integrators whose values only mean something relative to the rest of the image are
+    // scaled to it, so they can be viewed whatever the scene
+    fn visualise(&self, mut fb: Framebuffer) -> Framebuffer {
+        match self.integrator {
+            Integrator::Distance => {
+                // pixels which hit nothing are left black
+                let hits = fb.pixels().iter().map(|p| p[0]).filter(|&d| d > 0.0);
+                let (min, max) = hits.fold((f32::MAX, 0.0f32), |(min, max), d| (min.min(d), max.max(d)));
+                for j in 0..fb.height() {
+                    for i in 0..fb.width() {
+                        let d = fb.get(i, j)[0];
+                        let x = if d <= 0.0 {
+                            0.0
+                        } else if max > min {
+                            1.0 - (d - min) / (max - min)
+                        } else {
+                            1.0
+                        };
+                        fb.set(i, j, Vec3::new(x, x, x));
+                    }
+                }
+                fb
+            },
+            Integrator::BvhNodes | Integrator::PrimitiveTests => fb.false_colour(),
+            _ => fb,
+        }
+    }
+
+    // Metropolis light transport renders the whole image from Markov chains, which don't fit into
+    // per-pixel accumulation, so the number of samples is the average number of mutations per
+    // pixel, and adaptive sampling, median of means and checkpoints don't apply
+    fn render_metropolis<T: Hitable + Send + Sync>(&self, scene: &Scene<T>) -> Framebuffer {
+        let start = Instant::now();
+        let caustics = self.photon_map(scene);
+        let mutations = u64::from(self.samples) * u64::from(self.width * self.height);
+        let pb = self.new_progress_bar(mutations, "{elapsed_precise} (eta {eta}) [{wide_bar}] mutations:{pos}/{len}");
+
+        let pixels = self.metropolis.render(self.width, self.height, mutations, self.seed, &pb, |sampler| {
+            let (dx, dy) = sampler.get_2d();
+            let film_pos = (dx * self.width as f32, dy * self.height as f32);
+            let r = self.camera_ray(scene, film_pos, sampler);
+            let precomputed = Precomputed { caustics: caustics.as_ref(), guide: None, ao_radius: None };
+            let col = self.integrator.colour_observed(&r, scene, &self.path_length, precomputed, &mut (), sampler);
+            match r.wavelengths() {
+                Some(wavelengths) => (film_pos, wavelengths.to_rgb(&col)),
+                None => (film_pos, col),
+            }
+        });
+        pb.finish_with_message("done");
+        stats::count(|stats| stats.render_time += start.elapsed());
+
+        let mut fb = Framebuffer::new(self.width, self.height);
+        for (idx, col) in pixels.into_iter().enumerate() {
+            let (i, j) = (idx as u32 % self.width, idx as u32 / self.width);
+            fb.set(i, j, col);
+            fb.set_sample_count(i, j, self.samples);
+        }
+        fb
+    }
+
+    /// Renders AOVs from the first surface hit by each camera ray. Rays are generated exactly as
+    /// for `render`, so the AOVs line up with the rendered image.
+    pub fn render_aovs<T: Hitable + Send + Sync>(&self, scene: &Scene<T>, aovs: &[Aov]) -> AovImage {
+        let pixels: Vec<AovPixel> = (0..(self.width * self.height) as usize).into_par_iter()
+            .map(|idx| {
+                let (i, j) = (idx as u32 % self.width, idx as u32 / self.width);
+                let mut sampler = self.sampler.new_sampler(self.seed, self.samples);
+                let mut pixel = AovPixel::new();
+                for s in 0..self.samples {
+                    sampler.start_sample(idx as u64, s);
+                    let (dx, dy) = sampler.get_2d();
+                    let u = (i as f32 + dx) / self.width as f32;
+                    let v = 1.0 - (j as f32 + dy) / self.height as f32;
+                    let r = scene.camera.get_ray(u, v, &mut *sampler);
+                    if let Some(hit) = scene.hit(&r, 0.001, f32::MAX, &mut *sampler) {
+                        pixel.add(&r, &hit);
+                    }
+                }
+                pixel
+            })
+            .collect();
+        AovImage::from_pixels(self.width, self.height, aovs, &pixels)
+    }
+
+    // adds up to `pass_samples` samples to pixel `idx`, stopping early if it converges
+    fn render_pixel<T: Hitable + Send + Sync>(&self, scene: &Scene<T>, precomputed: Precomputed, idx: usize,
+                                              stats: &mut PixelStats, observer: &mut TileObserver, pass_samples: u32) {
+        if self.converged(stats) {
+            return;
+        }
+
+        let (i, j) = (idx as u32 % self.width, idx as u32 / self.width);
+        let mut sampler = self.sampler.new_sampler(self.seed, self.samples);
+        let pass_end = stats.count().saturating_add(pass_samples);
+        while stats.count() < pass_end && !self.converged(stats) {
+            sampler.start_sample(idx as u64, stats.count());
+            let (dx, dy) = sampler.get_2d();
+            let film_pos = (i as f32 + dx, j as f32 + dy);
+            let col = self.sample(scene, precomputed, film_pos, observer, &mut *sampler);
+            observer.tile.add_sample(film_pos, &col, &*self.filter, stats.count());
+            stats.add(col);
+        }
+    }
+
+    // photon map of caustics, if the renderer uses one
+    fn photon_map<T: Hitable + Send + Sync>(&self, scene: &Scene<T>) -> Option<PhotonMap> {
+        let caustics = self.caustics?;
+        if self.integrator == Integrator::Bdpt || self.integrator.is_debug() {
+            return None;
+        }
+
+        let map = PhotonMap::caustics(scene, caustics.photons, self.spectral, self.seed);
+        Some(match caustics.max_radius {
+            Some(max_radius) => map.with_max_radius(max_radius),
+            None => map,
+        })
+    }
+
+    // positions (column, row) of the tiles covering the image, in the order they're rendered
+    fn tile_order(&self) -> Vec<(u32, u32)> {
+        self.tile_order.order(self.width.div_ceil(self.tile_size), self.height.div_ceil(self.tile_size))
+    }
+
+    // film tiles covering the image, in the order they're rendered
+    fn tiles(&self, film: &Film) -> Vec<FilmTile> {
+        self.tile_order().into_iter()
+            .map(|(tx, ty)| {
+                let (x, y) = (tx * self.tile_size, ty * self.tile_size);
+                let end = ((x + self.tile_size).min(self.width), (y + self.tile_size).min(self.height));
+                film.tile((x, y), end, self.filter.radius())
+            })
+            .collect()
+    }
+
+    // groups pixels (along with their indices) by the tile they're in, with tiles in the same
+    // order as `tiles`
+    fn tile_pixels<'a>(&self, pixels: &'a mut [PixelStats]) -> Vec<Vec<(usize, &'a mut PixelStats)>> {
+        let tiles_x = self.width.div_ceil(self.tile_size);
+        let order = self.tile_order();
+        // position in the order of each tile, in row-major order
+        let mut positions = vec![0; order.len()];
+        for (position, &(tx, ty)) in order.iter().enumerate() {
+            positions[(ty * tiles_x + tx) as usize] = position;
+        }
+        let mut tile_pixels: Vec<Vec<_>> = order.iter().map(|_| Vec::new()).collect();
+        for (idx, stats) in pixels.iter_mut().enumerate() {
+            let (i, j) = (idx as u32 % self.width, idx as u32 / self.width);
+            let tile = ((j / self.tile_size) * tiles_x + i / self.tile_size) as usize;
+            tile_pixels[positions[tile]].push((idx, stats));
+        }
+        tile_pixels
+    }
+
+    // most samples that will be taken for any pixel
+    fn max_samples(&self) -> u32 {
+        match self.adaptive {
+            Some(adaptive) => adaptive.max_samples.max(self.samples),
+            None => self.samples,
+        }
+    }
+
+    // whether a pixel has had enough samples
+    fn converged(&self, stats: &PixelStats) -> bool {
+        if stats.count() >= self.max_samples() {
+            return true;
+        }
+        match self.adaptive {
+            Some(adaptive) => stats.count() >= self.samples && stats.relative_error() < adaptive.noise_threshold,
+            None => false,
+        }
+    }
+
+    fn progress_bar(&self, acc: &Accumulator) -> ProgressBar {
+        // with adaptive sampling the number of rays isn't known up front, so count pixels instead
+        let (len, template) = match self.adaptive {
+            Some(_) => {
+                let remaining = acc.pixels().iter().filter(|p| !self.converged(p)).count();
+                (remaining as u64, "{elapsed_precise} (eta {eta}) [{wide_bar}] pixels:{pos}/{len} {msg}")
+            },
+            None => {
+                let remaining = acc.pixels().iter().map(|p| u64::from(self.samples.saturating_sub(p.count()))).sum();
+                (remaining, "{elapsed_precise} (eta {eta}) [{wide_bar}] rays:{pos}/{len} {msg}")
+            },
+        };
+        self.new_progress_bar(len, template)
+    }
+
+    fn new_progress_bar(&self, len: u64, template: &str) -> ProgressBar {
+        let pb = if self.progress {
+            ProgressBar::new(len)
+        } else {
+            ProgressBar::hidden()
+        };
+        pb.set_style(ProgressStyle::default_bar()
+            .template(template)
+            .progress_chars("█▉▊▋▌▍▎▏  "));
+        pb
+    }
+
+    // radiance along a single camera ray through film position (x, y), for the sample `sampler`
+    // has been started on. Any light the integrator traces to other parts of the film is splatted
+    // onto the observer's tile.
+    fn sample<T: Hitable + Send + Sync>(&self, scene: &Scene<T>, precomputed: Precomputed, film_pos: (f32, f32),
+                                        observer: &mut TileObserver, sampler: &mut dyn Sampler) -> Vec3 {
+        let r = self.camera_ray(scene, film_pos, sampler);
+        let wavelengths = r.wavelengths().cloned();
+        observer.wavelengths = wavelengths;
+        let col = self.integrator.colour_observed(&r, scene, &self.path_length, precomputed, observer, sampler);
+        match wavelengths {
+            Some(wavelengths) => wavelengths.to_rgb(&col),
+            None => col,
+        }
+    }
+
+    // camera ray through film position (x, y), traced at sampled wavelengths when rendering
+    // spectrally
+    fn camera_ray<T: Hitable + Send + Sync>(&self, scene: &Scene<T>, (x, y): (f32, f32), sampler: &mut dyn Sampler) -> Ray {
+        let u = x / self.width as f32;
+        let v = 1.0 - y / self.height as f32; // film rows go from top to bottom, but v goes up
+        let r = scene.camera.get_ray(u, v, sampler);
+        stats::count(|stats| stats.camera_rays += 1);
+        // debug integrators show geometry, which looks the same at every wavelength
+        let wavelengths = if self.spectral && !self.integrator.is_debug() {
+            Some(Wavelengths::sample(sampler.get_1d()))
+        } else {
+            None
+        };
+        r.with_wavelengths(wavelengths)
+    }
+}
+
+// collects what integrators find for a tile's samples besides their radiance: light traced to
+// the camera from elsewhere in the scene, splatted onto the tile, and radiance arriving at
+// surfaces, for path guiding to learn from
+struct TileObserver<'a> {
+    tile: &'a mut FilmTile,
+    training: Option<&'a mut GuideRecorder>,
+    wavelengths: Option<Wavelengths>, // of the sample being traced
+    width: u32,
+    height: u32,
+}
+
+impl<'a> TileObserver<'a> {
+    fn to_rgb(&self, radiance: &Vec3) -> Vec3 {
+        match self.wavelengths {
+            Some(ref wavelengths) => wavelengths.to_rgb(radiance),
+            None => radiance.clone(),
+        }
+    }
+}
+
+impl<'a> PathObserver for TileObserver<'a> {
+    fn splat(&mut self, (u, v): (f32, f32), contribution: &Vec3) {
+        let col = self.to_rgb(contribution);
+        self.tile.add_splat((u * self.width as f32, (1.0 - v) * self.height as f32), &col);
+    }
+
+    fn incident(&mut self, point: &Vec3, direction: &Vec3, radiance: &Vec3, pdf: f32) {
+        let luminance = self.to_rgb(radiance).luminance();
+        if let Some(ref mut training) = self.training {
+            training.record(point, direction, luminance, pdf);
+        }
+    }
+}
+
+// failing to write a checkpoint shouldn't abort a long render, so only warn about it
+fn save_checkpoint(acc: &Accumulator, path: &Path) {
+    if let Err(e) = acc.save(path) {
+        eprintln!("Failed to write checkpoint to {}: {}", path.display(), e);
+    }
+}
+
+fn save_preview(fb: &Framebuffer, preview: &Preview) {
+    if let Err(e) = fb.save(&preview.path, preview.format, &preview.tone_mapping) {
+        eprintln!("Failed to write preview to {}: {}", preview.path.display(), e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+    use crate::hitable::{Rectangle, Sphere};
+    use crate::material::{Dielectric, DiffuseLight, Lambertian};
+    use crate::texture;
+
+    fn test_scene() -> Scene<Vec<Box<dyn Hitable + Send + Sync>>> {
+        let camera = Camera::new(Vec3::new(0.0, 1.0, 4.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0),
+                                 40.0, 1.0, 0.1, 4.0, 0.0, 1.0);
+        let light = Rectangle::new_xz((-1.0, 1.0), (-1.0, 1.0), 3.0,
+                                      DiffuseLight::new(texture::Constant::from_rgb(4.0, 4.0, 4.0)));
+        let hitables: Vec<Box<dyn Hitable + Send + Sync>> = vec![
+            Box::new(Sphere::new(Vec3::new(0.0, -100.0, 0.0), 100.0,
+                                 Lambertian::new(texture::Constant::from_rgb(0.5, 0.5, 0.5)))),
+            Box::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, Dielectric::new(1.5))),
+            Box::new(light.clone()),
+        ];
+        let lights: Vec<Box<dyn Hitable + Send + Sync>> = vec![Box::new(light)];
+        Scene { camera, hitables, lights, environment: None }
+    }
+
+    fn render_with_threads(renderer: &Renderer, threads: usize) -> Framebuffer {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        pool.install(|| renderer.render(&test_scene()))
+    }
+
+    #[test]
+    fn test_render_is_deterministic() {
+        let renderer = Renderer::new(8, 8).with_samples(4).with_integrator(Integrator::Mis);
+        let fb = render_with_threads(&renderer, 1);
+        assert_eq!(fb.pixels(), render_with_threads(&renderer, 4).pixels());
+
+        let progressive = Renderer::new(8, 8).with_samples(4).with_integrator(Integrator::Mis).with_pass_samples(1);
+        assert_eq!(fb.pixels(), render_with_threads(&progressive, 4).pixels());
+
+        // samples splatted across tile boundaries are summed in the same order whatever the threads
+        let filtered = Renderer::new(40, 40).with_samples(2).with_integrator(Integrator::Mis)
+            .with_filter(FilterType::Mitchell.new_filter(2.0));
+        assert_eq!(render_with_threads(&filtered, 1).pixels(), render_with_threads(&filtered, 4).pixels());
+
+        let reseeded = Renderer::new(8, 8).with_samples(4).with_integrator(Integrator::Mis).with_seed(1);
+        assert_ne!(fb.pixels(), render_with_threads(&reseeded, 4).pixels());
+    }
+
+    #[test]
+    fn test_median_of_means_needs_a_batch() {
+        let renderer = Renderer::new(8, 8).with_samples(4).with_integrator(Integrator::Mis);
+        let no_batches = Renderer::new(8, 8).with_samples(4).with_integrator(Integrator::Mis).with_median_of_means(0);
+        assert_eq!(render_with_threads(&renderer, 4).pixels(), render_with_threads(&no_batches, 4).pixels());
+    }
+
+    #[test]
+    fn test_resume_rejects_mismatched_samples() {
+        let scene = test_scene();
+        let renderer = Renderer::new(8, 8).with_samples(4);
+        assert!(renderer.resume(&scene, Accumulator::new(8, 8)).is_ok());
+        assert!(renderer.resume(&scene, Accumulator::new(8, 9)).is_err());
+        assert!(renderer.resume(&scene, Accumulator::new(8, 8).with_batches(3)).is_err());
+        let mlt = Renderer::new(8, 8).with_samples(4).with_integrator(Integrator::Mlt);
+        assert!(mlt.resume(&scene, Accumulator::new(8, 8)).is_err());
+
+        let settings = renderer.sample_settings();
+        assert!(renderer.resume(&scene, Accumulator::new(8, 8).with_settings(settings.clone())).is_ok());
+        let reseeded = SampleSettings { seed: 1, ..settings.clone() };
+        assert!(renderer.resume(&scene, Accumulator::new(8, 8).with_settings(reseeded)).is_err());
+        let filtered = Renderer::new(8, 8).with_samples(4).with_filter(FilterType::Tent.new_filter(1.0));
+        assert!(filtered.resume(&scene, Accumulator::new(8, 8).with_settings(settings)).is_err());
+    }
+
+    #[test]
+    fn test_tiling_doesnt_change_image() {
+        let renderer = Renderer::new(20, 13).with_samples(4).with_integrator(Integrator::Mis);
+        let fb = render_with_threads(&renderer, 4);
+        for &(tile_size, tile_order) in &[(4, TileOrder::Hilbert), (7, TileOrder::Scanline), (32, TileOrder::Spiral)] {
+            let tiled = Renderer::new(20, 13).with_samples(4).with_integrator(Integrator::Mis)
+                .with_tile_size(tile_size).with_tile_order(tile_order);
+            assert_eq!(fb.pixels(), render_with_threads(&tiled, 4).pixels(), "{}x{} {} tiles", tile_size, tile_size, tile_order);
+        }
+
+        let path = std::env::temp_dir().join(format!("rtracer-test-{}-preview.pfm", std::process::id()));
+        let previewed = Renderer::new(20, 13).with_samples(4).with_integrator(Integrator::Mis)
+            .with_preview(&path, OutputFormat::Pfm, ToneMapping::default(), Duration::from_secs(0));
+        assert_eq!(fb.pixels(), render_with_threads(&previewed, 4).pixels());
+        assert!(path.exists());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_bdpt_matches_mis() {
+        let mean = |fb: &Framebuffer| fb.pixels().iter().map(|p| p.luminance()).sum::<f32>() / fb.pixels().len() as f32;
+        let mis = render_with_threads(&Renderer::new(16, 16).with_samples(64).with_integrator(Integrator::Mis), 4);
+        let bdpt_renderer = Renderer::new(16, 16).with_samples(64).with_integrator(Integrator::Bdpt);
+        let bdpt = render_with_threads(&bdpt_renderer, 4);
+        assert!((mean(&bdpt) - mean(&mis)).abs() < 0.03 * mean(&mis), "bdpt {} vs mis {}", mean(&bdpt), mean(&mis));
+
+        // light splatted onto other pixels is added in the same order whatever the threads
+        assert_eq!(bdpt.pixels(), render_with_threads(&bdpt_renderer, 1).pixels());
+    }
+
+    #[test]
+    fn test_mlt_matches_mis() {
+        let mean = |fb: &Framebuffer| fb.pixels().iter().map(|p| p.luminance()).sum::<f32>() / fb.pixels().len() as f32;
+        let mis = render_with_threads(&Renderer::new(16, 16).with_samples(64).with_integrator(Integrator::Mis), 4);
+        let mlt_renderer = Renderer::new(16, 16).with_samples(64).with_integrator(Integrator::Mlt)
+            .with_metropolis(Metropolis::new().with_bootstrap(50_000).with_chains(64));
+        let mlt = render_with_threads(&mlt_renderer, 4);
+        assert!((mean(&mlt) - mean(&mis)).abs() < 0.05 * mean(&mis), "mlt {} vs mis {}", mean(&mlt), mean(&mis));
+        assert_eq!(mlt.pixels(), render_with_threads(&mlt_renderer, 1).pixels());
+    }
+
+    #[test]
+    fn test_path_guiding_matches_path() {
+        let mean = |fb: &Framebuffer| fb.pixels().iter().map(|p| p.luminance()).sum::<f32>() / fb.pixels().len() as f32;
+        let path = render_with_threads(&Renderer::new(24, 24).with_samples(128).with_integrator(Integrator::Path), 4);
+        let guided_renderer = Renderer::new(24, 24).with_samples(128).with_integrator(Integrator::Path).with_path_guiding(true);
+        let guided = render_with_threads(&guided_renderer, 4);
+        assert!((mean(&guided) - mean(&path)).abs() < 0.05 * mean(&path), "guided {} vs path {}", mean(&guided), mean(&path));
+        assert_ne!(guided.pixels(), path.pixels());
+        assert_eq!(guided.pixels(), render_with_threads(&guided_renderer, 1).pixels());
+    }
+
+    #[test]
+    fn test_render_aovs() {
+        let renderer = Renderer::new(9, 9).with_samples(4);
+        let image = renderer.render_aovs(&test_scene(), &[Aov::Depth, Aov::Normal, Aov::ObjectId]);
+        let (top_left, centre, bottom) = (0, 4 * 9 + 4, 8 * 9 + 4);
+
+        // the camera looks at the front of the glass sphere, from 3 units away
+        let depth = image.get(Aov::Depth).unwrap();
+        assert!((depth[centre][0] - 3.0).abs() < 0.05, "depth {}", depth[centre][0]);
+        assert_eq!(depth[top_left][0], f32::INFINITY);
+        assert!(image.get(Aov::Normal).unwrap()[centre][2] > 0.99);
+
+        let ids = image.get(Aov::ObjectId).unwrap();
+        assert_eq!(ids[top_left][0], 0.0);
+        assert_eq!(ids[centre][0], 1.0);
+        assert_eq!(ids[bottom][0], 2.0);
+        assert!(image.get(Aov::Albedo).is_none());
+    }
+}
@@ -0,0 +1,147 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Order in which the tiles of an image are rendered. Rendering threads take tiles in this order,
+/// so it's also the order the image fills in, and nearby tiles rendered around the same time
+/// share more of the scene in cache.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TileOrder {
+    /// Rows from top to bottom, each from left to right.
+    Scanline,
+    /// Outwards from the centre of the image, where the subject usually is.
+    #[default]
+    Spiral,
+    /// Along a Hilbert curve, which keeps consecutive tiles next to each other.
+    Hilbert,
+}
+
+impl TileOrder {
+    pub const NAMES: &'static [&'static str] = &["scanline", "spiral", "hilbert"];
+
+    /// Positions (column, row) of every tile in a grid of `tiles_x` by `tiles_y` tiles, in
+    /// rendering order.
+    pub fn order(self, tiles_x: u32, tiles_y: u32) -> Vec<(u32, u32)> {
+        match self {
+            TileOrder::Scanline => (0..tiles_y).flat_map(|y| (0..tiles_x).map(move |x| (x, y))).collect(),
+            TileOrder::Spiral => spiral(tiles_x, tiles_y),
+            TileOrder::Hilbert => hilbert(tiles_x, tiles_y),
+        }
+    }
+}
+
+// walks a square spiral out from the centre tile (right, down, left, up, with each pair of legs
+// one tile longer than the last), skipping positions outside the grid
+fn spiral(tiles_x: u32, tiles_y: u32) -> Vec<(u32, u32)> {
+    let total = (tiles_x * tiles_y) as usize;
+    let mut order = Vec::with_capacity(total);
+    let (mut x, mut y) = ((i64::from(tiles_x) - 1) / 2, (i64::from(tiles_y) - 1) / 2);
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let mut leg = 0;
+    while order.len() < total {
+        let (dx, dy) = directions[leg % 4];
+        for _ in 0..leg / 2 + 1 {
+            if x >= 0 && y >= 0 && x < i64::from(tiles_x) && y < i64::from(tiles_y) {
+                order.push((x as u32, y as u32));
+            }
+            x += dx;
+            y += dy;
+        }
+        leg += 1;
+    }
+    order
+}
+
+// follows a Hilbert curve over the smallest power of two square covering the grid, skipping
+// positions outside it
+fn hilbert(tiles_x: u32, tiles_y: u32) -> Vec<(u32, u32)> {
+    let size = tiles_x.max(tiles_y).next_power_of_two();
+    (0..u64::from(size) * u64::from(size))
+        .map(|d| hilbert_point(size, d))
+        .filter(|&(x, y)| x < tiles_x && y < tiles_y)
+        .collect()
+}
+
+// position of the `d`th cell along a Hilbert curve over a `size` by `size` grid, where `size` is
+// a power of two
+fn hilbert_point(size: u32, d: u64) -> (u32, u32) {
+    let (mut x, mut y) = (0, 0);
+    let mut t = d;
+    let mut s = 1;
+    while s < size {
+        let rx = (1 & (t / 2)) as u32;
+        let ry = (1 & (t ^ u64::from(rx))) as u32;
+        // rotate the quadrant so the sub-curves join up
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+    (x, y)
+}
+
+impl FromStr for TileOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "scanline" => Ok(TileOrder::Scanline),
+            "spiral" => Ok(TileOrder::Spiral),
+            "hilbert" => Ok(TileOrder::Hilbert),
+            _ => Err(format!("unknown tile order: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for TileOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            TileOrder::Scanline => "scanline",
+            TileOrder::Spiral => "spiral",
+            TileOrder::Hilbert => "hilbert",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tile_order_names() {
+        for name in TileOrder::NAMES {
+            let order: TileOrder = name.parse().unwrap();
+            assert_eq!(&order.to_string(), name);
+        }
+    }
+
+    #[test]
+    fn test_orders_cover_grid() {
+        for &(tiles_x, tiles_y) in &[(1, 1), (5, 3), (2, 7), (8, 8)] {
+            for name in TileOrder::NAMES {
+                let mut order = name.parse::<TileOrder>().unwrap().order(tiles_x, tiles_y);
+                order.sort();
+                let expected: Vec<_> = (0..tiles_x).flat_map(|x| (0..tiles_y).map(move |y| (x, y))).collect();
+                assert_eq!(order, expected, "{} order over {}x{} tiles", name, tiles_x, tiles_y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_order_coherence() {
+        // the spiral starts in the middle, and the Hilbert curve only steps to adjacent tiles
+        assert_eq!(TileOrder::Spiral.order(5, 3)[0], (2, 1));
+        let order = TileOrder::Hilbert.order(8, 8);
+        for pair in order.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            assert_eq!((a.0 as i32 - b.0 as i32).abs() + (a.1 as i32 - b.1 as i32).abs(), 1);
+        }
+    }
+}
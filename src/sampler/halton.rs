@@ -0,0 +1,90 @@
+use rand::prelude::*;
+use rand_pcg::Pcg32;
+
+use crate::utils;
+use super::{dimension_seed, permute, Sampler, ONE_MINUS_EPSILON};
+
+// bases of the first dimensions, after which independent random numbers are used
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+];
+
+/// Halton sequence, where dimension d of sample i is the radical inverse of i in the d'th prime
+/// base. Digits are randomly permuted per pixel (Owen scrambling), which keeps the stratification
+/// while breaking up correlation between pixels and between the higher dimensions.
+pub struct Halton {
+    seed: u64,
+    pixel: u64,
+    index: u32,
+    dimension: u32,
+    rng: Pcg32,
+}
+
+impl Halton {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, pixel: 0, index: 0, dimension: 0, rng: utils::seeded_rng(seed) }
+    }
+}
+
+impl Sampler for Halton {
+    fn start_sample(&mut self, pixel: u64, index: u32) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+        self.rng = utils::seeded_rng(utils::mix_seed(utils::mix_seed(self.seed, pixel), u64::from(index)));
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        match PRIMES.get(dimension as usize) {
+            Some(&base) => {
+                let hash = dimension_seed(self.seed, self.pixel, dimension);
+                scrambled_radical_inverse(self.index, base, hash).min(ONE_MINUS_EPSILON)
+            },
+            None => self.rng.gen(),
+        }
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+// radical inverse where each digit is permuted based on the digits before it. Digits continue past
+// the last non-zero one of `i`, as their permutations are what fill in the remaining precision.
+fn scrambled_radical_inverse(mut i: u32, base: u32, hash: u64) -> f32 {
+    let inv_base = 1.0 / f64::from(base);
+    let mut inv_base_n = 1.0;
+    let mut reversed = 0.0;
+    let mut prefix = 0u64;
+    while inv_base_n > f64::from(f32::EPSILON) / 2.0 {
+        let digit_hash = utils::mix_seed(hash, prefix) as u32;
+        let digit = permute(i % base, base, digit_hash);
+        prefix = prefix.wrapping_mul(u64::from(base)).wrapping_add(u64::from(digit) + 1);
+        inv_base_n *= inv_base;
+        reversed += f64::from(digit) * inv_base_n;
+        i /= base;
+    }
+    reversed as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scrambled_radical_inverse() {
+        // the first base^2 points fall one into each of base^2 equal intervals
+        for &base in &[2, 3, 5] {
+            let n = base * base;
+            let mut strata: Vec<u32> = (0..n)
+                .map(|i| (scrambled_radical_inverse(i, base, 42) * n as f32) as u32)
+                .collect();
+            strata.sort();
+            assert_eq!(strata, (0..n).collect::<Vec<_>>());
+        }
+        assert_ne!(scrambled_radical_inverse(1, 2, 1), scrambled_radical_inverse(1, 2, 2));
+    }
+}
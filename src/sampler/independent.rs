@@ -0,0 +1,31 @@
+use rand::prelude::*;
+use rand_pcg::Pcg32;
+
+use crate::utils;
+use super::Sampler;
+
+/// Independent uniform random numbers for every dimension.
+pub struct Independent {
+    seed: u64,
+    rng: Pcg32,
+}
+
+impl Independent {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, rng: utils::seeded_rng(seed) }
+    }
+}
+
+impl Sampler for Independent {
+    fn start_sample(&mut self, pixel: u64, index: u32) {
+        self.rng = utils::seeded_rng(utils::mix_seed(utils::mix_seed(self.seed, pixel), u64::from(index)));
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        self.rng.gen()
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.rng.gen(), self.rng.gen())
+    }
+}
@@ -0,0 +1,201 @@
+//! Samplers supply the random numbers used to render each sample of a pixel, e.g. for jittering
+//! the camera ray, picking a point on the lens and choosing scattered directions. Each call to
+//! `get_1d` or `get_2d` consumes the next dimension of the sample, and samplers which generate
+//! well distributed points in those dimensions (across all samples of a pixel) give images which
+//! converge faster than independent random numbers.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::utils;
+
+mod independent;
+pub use independent::Independent;
+
+mod stratified;
+pub use stratified::Stratified;
+
+mod halton;
+pub use halton::Halton;
+
+mod sobol;
+pub use sobol::Sobol;
+
+/// Largest f32 less than 1, used to keep samples within [0, 1).
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+pub trait Sampler {
+    /// Starts generating dimensions for sample `index` of the pixel with (row-major) index
+    /// `pixel`. The values generated depend only on these and the sampler's seed, so are the same
+    /// regardless of the order in which samples are rendered.
+    fn start_sample(&mut self, pixel: u64, index: u32);
+
+    /// Next dimension of the current sample, in [0, 1).
+    fn get_1d(&mut self) -> f32;
+
+    /// Next two dimensions of the current sample, in [0, 1)², well distributed as a pair.
+    fn get_2d(&mut self) -> (f32, f32);
+}
+
+/// Available `Sampler` implementations.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SamplerType {
+    /// Independent uniform random numbers.
+    Independent,
+    /// Jittered samples, stratified within each dimension (or pair of dimensions).
+    Stratified,
+    /// Halton sequence, Owen scrambled independently for each pixel.
+    Halton,
+    /// Sobol (0, 2)-sequence, Owen scrambled and shuffled independently for each pair of
+    /// dimensions.
+    #[default]
+    Sobol,
+}
+
+impl SamplerType {
+    pub const NAMES: &'static [&'static str] = &["independent", "stratified", "halton", "sobol"];
+
+    /// New sampler, for rendering `samples_per_pixel` samples of each pixel (used by samplers
+    /// which stratify over a fixed number of samples).
+    pub fn new_sampler(self, seed: u64, samples_per_pixel: u32) -> Box<dyn Sampler> {
+        match self {
+            SamplerType::Independent => Box::new(Independent::new(seed)),
+            SamplerType::Stratified => Box::new(Stratified::new(seed, samples_per_pixel)),
+            SamplerType::Halton => Box::new(Halton::new(seed)),
+            SamplerType::Sobol => Box::new(Sobol::new(seed)),
+        }
+    }
+}
+
+impl FromStr for SamplerType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "independent" => Ok(SamplerType::Independent),
+            "stratified" => Ok(SamplerType::Stratified),
+            "halton" => Ok(SamplerType::Halton),
+            "sobol" => Ok(SamplerType::Sobol),
+            _ => Err(format!("unknown sampler: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for SamplerType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            SamplerType::Independent => "independent",
+            SamplerType::Stratified => "stratified",
+            SamplerType::Halton => "halton",
+            SamplerType::Sobol => "sobol",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// seed for the random numbers of one dimension of a pixel, shared by all its samples
+fn dimension_seed(seed: u64, pixel: u64, dimension: u32) -> u64 {
+    utils::mix_seed(utils::mix_seed(seed, pixel), u64::from(dimension))
+}
+
+// converts 32 random bits to a float in [0, 1)
+fn to_unit_float(x: u32) -> f32 {
+    (x >> 8) as f32 * (1.0 / (1u32 << 24) as f32)
+}
+
+// element `i` of a random permutation of 0..len, chosen by `seed` (from Kensler's "Correlated
+// Multi-Jittered Sampling"), so that permutations never need to be stored
+fn permute(mut i: u32, len: u32, seed: u32) -> u32 {
+    let mut w = len - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < len {
+            break;
+        }
+    }
+    (i.wrapping_add(seed)) % len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // number of samples in each of n equal strata of [0, 1)
+    fn strata_counts(values: &[f32], n: usize) -> Vec<usize> {
+        let mut counts = vec![0; n];
+        for v in values {
+            assert!(*v >= 0.0 && *v < 1.0);
+            counts[(v * n as f32) as usize] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn test_permute() {
+        for &len in [1, 5, 16, 100].iter() {
+            let mut seen: Vec<u32> = (0..len).map(|i| permute(i, len, 1234)).collect();
+            seen.sort();
+            assert_eq!(seen, (0..len).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_samplers_are_stratified() {
+        for &sampler_type in [SamplerType::Stratified, SamplerType::Sobol].iter() {
+            let mut sampler = sampler_type.new_sampler(7, 16);
+            let mut xs = Vec::new();
+            let mut ys = Vec::new();
+            let mut zs = Vec::new();
+            for index in 0..16 {
+                sampler.start_sample(3, index);
+                xs.push(sampler.get_1d());
+                let (y, z) = sampler.get_2d();
+                ys.push(y);
+                zs.push(z);
+            }
+            assert_eq!(strata_counts(&xs, 16), vec![1; 16], "{}", sampler_type);
+            assert_eq!(strata_counts(&ys, 4), vec![4; 4], "{}", sampler_type);
+            assert_eq!(strata_counts(&zs, 4), vec![4; 4], "{}", sampler_type);
+        }
+    }
+
+    #[test]
+    fn test_samplers_are_deterministic() {
+        for name in SamplerType::NAMES {
+            let sampler_type: SamplerType = name.parse().unwrap();
+            assert_eq!(&sampler_type.to_string(), name);
+
+            let mut a = sampler_type.new_sampler(1, 4);
+            let mut b = sampler_type.new_sampler(1, 4);
+            a.start_sample(10, 3);
+            b.start_sample(10, 3);
+            for _ in 0..40 {
+                let x = a.get_2d();
+                assert!(x.0 >= 0.0 && x.0 < 1.0 && x.1 >= 0.0 && x.1 < 1.0);
+                assert_eq!(x, b.get_2d());
+            }
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use super::{dimension_seed, to_unit_float, Sampler};
+
+/// Sobol (0, 2)-sequence, using Burley's "Practical Hash-based Owen Scrambling": every pair of
+/// dimensions uses the first two Sobol dimensions, with the points Owen scrambled and their order
+/// shuffled independently for each pair and pixel. Any power of two number of samples is well
+/// stratified in each pair of dimensions.
+pub struct Sobol {
+    seed: u64,
+    pixel: u64,
+    index: u32,
+    dimension: u32,
+}
+
+impl Sobol {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, pixel: 0, index: 0, dimension: 0 }
+    }
+
+    // scrambled Sobol point for the current sample in the next dimension
+    fn next_point(&mut self) -> (u32, u32) {
+        let seed = dimension_seed(self.seed, self.pixel, self.dimension);
+        self.dimension += 1;
+        let index = nested_uniform_scramble(self.index, seed as u32);
+        (nested_uniform_scramble(sobol_0(index), (seed >> 32) as u32),
+         nested_uniform_scramble(sobol_1(index), (seed >> 16) as u32 ^ 0x5bd1_e995))
+    }
+}
+
+impl Sampler for Sobol {
+    fn start_sample(&mut self, pixel: u64, index: u32) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        to_unit_float(self.next_point().0)
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let (x, y) = self.next_point();
+        (to_unit_float(x), to_unit_float(y))
+    }
+}
+
+// first Sobol dimension, the base 2 radical inverse (as a 0.32 fixed point number)
+fn sobol_0(i: u32) -> u32 {
+    i.reverse_bits()
+}
+
+// second Sobol dimension, whose generator matrix is Pascal's triangle mod 2
+fn sobol_1(mut i: u32) -> u32 {
+    let mut v = 1 << 31;
+    let mut x = 0;
+    while i != 0 {
+        if i & 1 != 0 {
+            x ^= v;
+        }
+        i >>= 1;
+        v ^= v >> 1;
+    }
+    x
+}
+
+// hash based approximation of a random Owen scramble of a 0.32 fixed point number, from Laine and
+// Karras' "Stratified sampling for stochastic transparency"
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x.reverse_bits()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sobol_points() {
+        // first points of the unscrambled sequence, as fractions of 8
+        let points: Vec<(u32, u32)> = (0..8).map(|i| (sobol_0(i) >> 29, sobol_1(i) >> 29)).collect();
+        assert_eq!(points, vec![(0, 0), (4, 4), (2, 6), (6, 2), (1, 5), (5, 1), (3, 3), (7, 7)]);
+    }
+}
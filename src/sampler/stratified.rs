@@ -0,0 +1,58 @@
+use rand::prelude::*;
+use rand_pcg::Pcg32;
+
+use crate::utils;
+use super::{dimension_seed, permute, Sampler, ONE_MINUS_EPSILON};
+
+/// Jittered samples, where each dimension is split into one stratum per sample (or, for pairs of
+/// dimensions, a square grid of strata) and each sample of a pixel falls in a different stratum.
+/// Strata are assigned to samples in a random order which differs for each dimension, so that
+/// dimensions aren't correlated. Samples beyond the expected number per pixel start a new round
+/// of strata.
+pub struct Stratified {
+    seed: u64,
+    samples_per_pixel: u32,
+    pixel: u64,
+    index: u32,
+    dimension: u32,
+    rng: Pcg32,
+}
+
+impl Stratified {
+    pub fn new(seed: u64, samples_per_pixel: u32) -> Self {
+        let samples_per_pixel = samples_per_pixel.max(1);
+        Self { seed, samples_per_pixel, pixel: 0, index: 0, dimension: 0, rng: utils::seeded_rng(seed) }
+    }
+
+    // stratum of the current sample, out of `strata` in the next dimension
+    fn next_stratum(&mut self, strata: u32) -> u32 {
+        let round = self.index / strata;
+        let dimension_seed = dimension_seed(self.seed, self.pixel, self.dimension);
+        let permutation_seed = utils::mix_seed(dimension_seed, u64::from(round)) as u32;
+        self.dimension += 1;
+        permute(self.index % strata, strata, permutation_seed)
+    }
+}
+
+impl Sampler for Stratified {
+    fn start_sample(&mut self, pixel: u64, index: u32) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+        self.rng = utils::seeded_rng(utils::mix_seed(utils::mix_seed(self.seed, pixel), u64::from(index)));
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let strata = self.samples_per_pixel;
+        let stratum = self.next_stratum(strata);
+        ((stratum as f32 + self.rng.gen::<f32>()) / strata as f32).min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let n = ((self.samples_per_pixel as f32).sqrt() as u32).max(1);
+        let stratum = self.next_stratum(n * n);
+        let x = ((stratum % n) as f32 + self.rng.gen::<f32>()) / n as f32;
+        let y = ((stratum / n) as f32 + self.rng.gen::<f32>()) / n as f32;
+        (x.min(ONE_MINUS_EPSILON), y.min(ONE_MINUS_EPSILON))
+    }
+}
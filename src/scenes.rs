@@ -4,17 +4,119 @@ use rand::prelude::*;
 use crate::vec3::Vec3;
 use crate::material::{Dielectric, DiffuseLight, Metal, Lambertian};
 use crate::texture;
-use crate::hitable::{ConstantMedium, Rotate, Translate, Cuboid, FlipNormals, Rectangle, Hitable, MovingSphere, Sphere};
+use crate::ray::Ray;
+use crate::hitable::{ConstantMedium, Rotate, Translate, Cuboid, FlipNormals, Rectangle, HitRecord, Hitable, MovingSphere, Sphere};
 use crate::camera::Camera;
+use crate::environment::{self, Environment};
 use crate::bvh;
+use crate::sampler::Sampler;
+use crate::spectrum::Wavelengths;
+use crate::utils::{self, Onb};
+use crate::stats;
+
+// scenes are built using a fixed seed, so that they're identical between runs
+const SCENE_SEED: u64 = 0;
 
 pub struct Scene<T: Hitable + Send + Sync> {
     pub camera: Camera,
     pub hitables: T,
+    /// Emitters which integrators can sample directly, should include every emissive hitable.
+    pub lights: Vec<Box<dyn Hitable + Send + Sync>>,
+    /// Source of light for rays which don't hit anything, black if `None`.
+    pub environment: Option<Box<dyn Environment>>,
+}
+
+/// A ray leaving one of a scene's lights, from `Scene::sample_light_ray`.
+pub struct LightSample<'a> {
+    /// Point on the light the ray leaves from.
+    pub hit: HitRecord<'a>,
+    pub ray: Ray,
+    /// Radiance emitted along the ray.
+    pub emitted: Vec3,
+    /// Emitted radiance times the cosine at the light, over the pdfs of sampling the ray.
+    pub beta: Vec3,
+    /// Area pdf of sampling the point on the light.
+    pub pdf_pos: f32,
+    /// Solid angle pdf of sampling the direction from it.
+    pub pdf_dir: f32,
+}
+
+impl<T: Hitable + Send + Sync> Scene<T> {
+    /// Closest surface hit by ray `r` between `t_min` and `t_max`, counting the ray in the render
+    /// statistics.
+    pub fn hit(&self, r: &Ray, t_min: f32, t_max: f32, sampler: &mut dyn Sampler) -> Option<HitRecord<'_>> {
+        stats::count(|stats| stats.rays += 1);
+        self.hitables.hit(r, t_min, t_max, sampler)
+    }
+
+    /// Radiance arriving along rays which don't hit anything.
+    pub fn background(&self, direction: &Vec3) -> Vec3 {
+        match self.environment {
+            Some(ref environment) => environment.value(direction),
+            None => Vec3::zeros(),
+        }
+    }
+
+    /// Whether there are any lights or an environment which can be sampled directly.
+    pub fn has_emitters(&self) -> bool {
+        !self.lights.is_empty() || self.environment.is_some()
+    }
+
+    /// Samples a direction from `origin` towards either one of the lights or the environment,
+    /// returning it along with the combined solid angle pdf of choosing it.
+    pub fn sample_emitters(&self, origin: &Vec3, sampler: &mut dyn Sampler) -> Option<(Vec3, f32)> {
+        let environment = match self.environment {
+            Some(ref environment) => environment,
+            None => return self.lights.sample_direction(origin, sampler),
+        };
+
+        let direction = if !self.lights.is_empty() && sampler.get_1d() < 0.5 {
+            self.lights.sample_direction(origin, sampler)?.0
+        } else {
+            environment.sample_direction(sampler)?.0
+        };
+        let pdf = self.emitters_pdf(origin, &direction, sampler);
+        Some((direction, pdf))
+    }
+
+    /// Samples a ray leaving one of the lights, at `time` and carrying `wavelengths`, for tracing
+    /// light into the scene: a point uniformly by area, and a direction from a cosine distribution
+    /// about either side of the light, as lights emit from both. `None` if nothing could be
+    /// sampled, or no light is emitted along the ray.
+    pub fn sample_light_ray(&self, time: f32, wavelengths: Option<Wavelengths>, sampler: &mut dyn Sampler) -> Option<LightSample<'_>> {
+        let (hit, pdf_pos) = self.lights.sample_surface(sampler)?;
+        if pdf_pos <= 0.0 {
+            return None;
+        }
+
+        let side = if sampler.get_1d() < 0.5 { hit.normal.clone() } else { -hit.normal.clone() };
+        let direction = Onb::from_w(&side).local(&utils::cosine_hemisphere(sampler.get_2d()));
+        let cosine = direction.dot(&side) / direction.length();
+        let pdf_dir = cosine / (2.0 * std::f32::consts::PI);
+        let ray = Ray::new_at_time(hit.point.clone(), direction, time).with_wavelengths(wavelengths);
+
+        let emitted = ray.colour(hit.material.emitted(hit.u, hit.v, &hit.point));
+        if emitted.squared_length() == 0.0 || pdf_dir <= 0.0 {
+            return None;
+        }
+        let beta = (cosine / (pdf_pos * pdf_dir)) * &emitted;
+        Some(LightSample { hit, ray, emitted, beta, pdf_pos, pdf_dir })
+    }
+
+    /// Solid angle pdf of `sample_emitters` generating `direction` from `origin`.
+    pub fn emitters_pdf(&self, origin: &Vec3, direction: &Vec3, sampler: &mut dyn Sampler) -> f32 {
+        match self.environment {
+            Some(ref environment) if self.lights.is_empty() => environment.pdf_value(direction),
+            Some(ref environment) => {
+                0.5 * (self.lights.pdf_value(origin, direction, sampler) + environment.pdf_value(direction))
+            },
+            None => self.lights.pdf_value(origin, direction, sampler),
+        }
+    }
 }
 
 //pub fn random_sphere_scene() -> Vec<Box<dyn Hitable + Send + Sync>> {
-//    let mut rng = rand::thread_rng();
+//    let mut rng = utils::seeded_rng(SCENE_SEED);
 //    let n = 500;
 //    let sphere_radius = 0.2;
 //    let mut list: Vec<Box<dyn Hitable + Send + Sync>> = Vec::with_capacity(n + 1);
@@ -76,7 +178,7 @@ pub struct Scene<T: Hitable + Send + Sync> {
 //                             up_vector, field_of_view, aspect_ratio, aperture, focal_distance,
 //                             time0, time1);
 //
-//    let mut rng = rand::thread_rng();
+//    let mut rng = utils::seeded_rng(SCENE_SEED);
 //    let n = 500;
 //    let sphere_radius = 0.2;
 //    let mut list: Vec<Box<dyn Hitable + Send + Sync>> = Vec::with_capacity(n + 1);
@@ -126,8 +228,8 @@ pub struct Scene<T: Hitable + Send + Sync> {
 //    list.push(Box::new(Sphere::new(Vec3::new(-4.0, 1.0, 0.0), 1.0, Lambertian::new(texture::Constant::from_rgb(0.4, 0.2, 0.1)))));
 //    list.push(Box::new(Sphere::new(Vec3::new(4.0, 1.0, 0.0), 1.0, Metal::new(texture::Constant::from_rgb(0.7, 0.6, 0.5), 0.0))));
 //
-//    let hitables = bvh::BvhNode::from_vec(list, time0, time1);
-//    Scene { camera, hitables }
+//    let hitables = bvh::BvhNode::from_vec(list, time0, time1, &mut rng);
+//    Scene { camera, hitables, lights: Vec::new(), environment: None }
 //}
 //
 //pub fn two_spheres(aspect_ratio: f32) -> Scene<bvh::BvhNode> {
@@ -153,8 +255,9 @@ pub struct Scene<T: Hitable + Send + Sync> {
 //        Box::new(Sphere::new(Vec3::new(0.0, -10.0, 0.0), 10.0, Lambertian::new(checker.clone()))),
 //        Box::new(Sphere::new(Vec3::new(0.0, 10.0, 0.0), 10.0, Lambertian::new(checker.clone()))),
 //    ];
-//    let hitables = bvh::BvhNode::from_vec(hitables, time0, time1);
-//    Scene { camera, hitables }
+//    let mut rng = utils::seeded_rng(SCENE_SEED);
+//    let hitables = bvh::BvhNode::from_vec(hitables, time0, time1, &mut rng);
+//    Scene { camera, hitables, lights: Vec::new(), environment: None }
 //}
 //
 //pub fn two_perlin_spheres(aspect_ratio: f32) -> Scene<bvh::BvhNode> {
@@ -171,13 +274,14 @@ pub struct Scene<T: Hitable + Send + Sync> {
 //                             up_vector, field_of_view, aspect_ratio, aperture, focal_distance,
 //                             time0, time1);
 //
-//    let noise = texture::Noise::new(4.0);
+//    let mut rng = utils::seeded_rng(SCENE_SEED);
+//    let noise = texture::Noise::new(4.0, &mut rng);
 //    let hitables: Vec<Box<dyn Hitable + Send + Sync>> = vec![
 //        Box::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, Lambertian::new(noise.clone()))),
 //        Box::new(Sphere::new(Vec3::new(0.0, 2.0, 0.0), 2.0, Lambertian::new(noise.clone()))),
 //    ];
-//    let hitables = bvh::BvhNode::from_vec(hitables, time0, time1);
-//    Scene { camera, hitables }
+//    let hitables = bvh::BvhNode::from_vec(hitables, time0, time1, &mut rng);
+//    Scene { camera, hitables, lights: Vec::new(), environment: None }
 //}
 //
 //pub fn earth_sphere(aspect_ratio: f32) -> Scene<bvh::BvhNode> {
@@ -195,13 +299,14 @@ pub struct Scene<T: Hitable + Send + Sync> {
 //                             time0, time1);
 //
 //    let earth_img = texture::Image::new("/Users/dsc/src/rust-raytracer/earthmap1k.jpg");
+//    let mut rng = utils::seeded_rng(SCENE_SEED);
 //    let hitables: Vec<Box<dyn Hitable + Send + Sync>> = vec![
-//        Box::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, Lambertian::new(texture::Noise::new(4.0)))),
+//        Box::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, Lambertian::new(texture::Noise::new(4.0, &mut rng)))),
 //        Box::new(Sphere::new(Vec3::new(1.5, 2.0, 0.0), 1.5, Lambertian::new(earth_img.clone()))),
 //        Box::new(Sphere::new(Vec3::new(-1.5, 2.0, 0.0), 1.5, Metal::new(earth_img.clone(), 0.9))),
 //    ];
-//    let hitables = bvh::BvhNode::from_vec(hitables, time0, time1);
-//    Scene { camera, hitables }
+//    let hitables = bvh::BvhNode::from_vec(hitables, time0, time1, &mut rng);
+//    Scene { camera, hitables, lights: Vec::new(), environment: None }
 //}
 //
 //pub fn simple_light(aspect_ratio: f32) -> Scene<bvh::BvhNode> {
@@ -217,7 +322,8 @@ pub struct Scene<T: Hitable + Send + Sync> {
 //                             up_vector, field_of_view, aspect_ratio, aperture, focal_distance,
 //                             time0, time1);
 //
-//    let noise = texture::Noise::new(4.0);
+//    let mut rng = utils::seeded_rng(SCENE_SEED);
+//    let noise = texture::Noise::new(4.0, &mut rng);
 //    let light = texture::Constant::from_rgb(6.0, 0.0, 0.0); // light > 1.0, bright enough to light things
 //    let blue_light = texture::Constant::from_rgb(0.0, 4.0, 0.0); // light > 1.0, bright enough to light things
 //    let hitables: Vec<Box<dyn Hitable + Send + Sync>> = vec![
@@ -226,8 +332,12 @@ pub struct Scene<T: Hitable + Send + Sync> {
 //        Box::new(Sphere::new(Vec3::new(1.0, 6.0, 02.0), 0.5, DiffuseLight::new(light.clone()))),
 //        Box::new(Rectangle::new_xy((3.0, 5.0), (1.0, 3.0), -2.0, DiffuseLight::new(blue_light.clone()))),
 //    ];
-//    let hitables = bvh::BvhNode::from_vec(hitables, time0, time1);
-//    Scene { camera, hitables }
+//    let lights: Vec<Box<dyn Hitable + Send + Sync>> = vec![
+//        Box::new(Sphere::new(Vec3::new(1.0, 6.0, 02.0), 0.5, DiffuseLight::new(light.clone()))),
+//        Box::new(Rectangle::new_xy((3.0, 5.0), (1.0, 3.0), -2.0, DiffuseLight::new(blue_light.clone()))),
+//    ];
+//    let hitables = bvh::BvhNode::from_vec(hitables, time0, time1, &mut rng);
+//    Scene { camera, hitables, lights, environment: None }
 //}
 
 //pub fn cornell_box(aspect_ratio: f32) -> Scene<bvh::BvhNode> {
@@ -247,11 +357,12 @@ pub struct Scene<T: Hitable + Send + Sync> {
 //    let white = Lambertian::new(texture::Constant::from_rgb(0.73, 0.73, 0.73));
 //    let green = Lambertian::new(texture::Constant::from_rgb(0.12, 0.45, 0.15));
 //    let light = DiffuseLight::new(texture::Constant::from_rgb(15.0, 15.0, 15.0));
+//    let light_rect = Rectangle::new_xz((213.0, 343.0), (227.0, 332.0), 554.0, light.clone());
 //
 //    let hitables: Vec<Box<dyn Hitable + Send + Sync>> = vec![
 //        Box::new(FlipNormals::new(Rectangle::new_yz((0.0, 555.0), (0.0, 555.0), 555.0, green.clone()))),
 //        Box::new(Rectangle::new_yz((0.0, 555.0), (0.0, 555.0), 0.0, red.clone())),
-//        Box::new(Rectangle::new_xz((213.0, 343.0), (227.0, 332.0), 554.0, light.clone())),
+//        Box::new(light_rect.clone()),
 //        Box::new(FlipNormals::new(Rectangle::new_xz((0.0, 555.0), (0.0, 555.0), 555.0, white.clone()))),
 //        Box::new(Rectangle::new_xz((0.0, 555.0), (0.0, 555.0), 0.0, white.clone())),
 //        Box::new(FlipNormals::new(Rectangle::new_xy((0.0, 555.0), (0.0, 555.0), 555.0, white.clone()))),
@@ -274,8 +385,10 @@ pub struct Scene<T: Hitable + Send + Sync> {
 //            )
 //        ),
 //    ];
-//    let hitables = bvh::BvhNode::from_vec(hitables, time0, time1);
-//    Scene { camera, hitables }
+//    let lights: Vec<Box<dyn Hitable + Send + Sync>> = vec![Box::new(light_rect)];
+//    let mut rng = utils::seeded_rng(SCENE_SEED);
+//    let hitables = bvh::BvhNode::from_vec(hitables, time0, time1, &mut rng);
+//    Scene { camera, hitables, lights, environment: None }
 //}
 
 pub fn cornell_smoke(aspect_ratio: f32) -> Scene<bvh::BvhNode> {
@@ -295,11 +408,12 @@ pub fn cornell_smoke(aspect_ratio: f32) -> Scene<bvh::BvhNode> {
     let white = Lambertian::new(texture::Constant::from_rgb(0.73, 0.73, 0.73));
     let green = Lambertian::new(texture::Constant::from_rgb(0.12, 0.45, 0.15));
     let light = DiffuseLight::new(texture::Constant::from_rgb(4.0, 4.0, 4.0));
+    let light_rect = Rectangle::new_xz((113.0, 443.0), (127.0, 432.0), 554.0, light.clone());
 
     let hitables: Vec<Box<dyn Hitable + Send + Sync>> = vec![
         Box::new(FlipNormals::new(Rectangle::new_yz((0.0, 555.0), (0.0, 555.0), 555.0, green.clone()))),
         Box::new(Rectangle::new_yz((0.0, 555.0), (0.0, 555.0), 0.0, red.clone())),
-        Box::new(Rectangle::new_xz((113.0, 443.0), (127.0, 432.0), 554.0, light.clone())),
+        Box::new(light_rect.clone()),
         Box::new(FlipNormals::new(Rectangle::new_xz((0.0, 555.0), (0.0, 555.0), 555.0, white.clone()))),
         Box::new(Rectangle::new_xz((0.0, 555.0), (0.0, 555.0), 0.0, white.clone())),
         Box::new(FlipNormals::new(Rectangle::new_xy((0.0, 555.0), (0.0, 555.0), 555.0, white.clone()))),
@@ -330,8 +444,10 @@ pub fn cornell_smoke(aspect_ratio: f32) -> Scene<bvh::BvhNode> {
             )
         ),
     ];
-    let hitables = bvh::BvhNode::from_vec(hitables, time0, time1);
-    Scene { camera, hitables }
+    let lights: Vec<Box<dyn Hitable + Send + Sync>> = vec![Box::new(light_rect)];
+    let mut rng = utils::seeded_rng(SCENE_SEED);
+    let hitables = bvh::BvhNode::from_vec(hitables, time0, time1, &mut rng);
+    Scene { camera, hitables, lights, environment: None }
 }
 
 pub fn tnw_final_scene(aspect_ratio: f32) -> Scene<Vec<Box<dyn Hitable + Send + Sync>>> {
@@ -347,7 +463,7 @@ pub fn tnw_final_scene(aspect_ratio: f32) -> Scene<Vec<Box<dyn Hitable + Send +
                              up_vector, field_of_view, aspect_ratio, aperture, focal_distance,
                              time0, time1);
 
-    let mut rng = rand::thread_rng();
+    let mut rng = utils::seeded_rng(SCENE_SEED);
     let white = Lambertian::new(texture::Constant::from_rgb(0.73, 0.73, 0.73));
     let ground = Lambertian::new(texture::Constant::from_rgb(0.48, 0.83, 0.53));
 
@@ -404,7 +520,7 @@ pub fn tnw_final_scene(aspect_ratio: f32) -> Scene<Vec<Box<dyn Hitable + Send +
     let perlin_sphere = Sphere::new(
         Vec3::new(220.0, 280.0, 300.0),
         80.0,
-        Lambertian::new(texture::Noise::new(0.1)),
+        Lambertian::new(texture::Noise::new(0.1, &mut rng)),
     );
 
     let mut spherelist: Vec<Box<dyn Hitable + Send + Sync>> = Vec::new();
@@ -419,15 +535,16 @@ pub fn tnw_final_scene(aspect_ratio: f32) -> Scene<Vec<Box<dyn Hitable + Send +
 
     let sphere_cube = Translate::new(
         Rotate::new_y(
-            bvh::BvhNode::from_vec(spherelist, 0.0, 1.0),
+            bvh::BvhNode::from_vec(spherelist, 0.0, 1.0, &mut rng),
             15.0
         ),
         Vec3::new(-100.0, 270.0, 395.0)
     );
 
+    let lights: Vec<Box<dyn Hitable + Send + Sync>> = vec![Box::new(light_rect.clone())];
     let hitables: Vec<Box<dyn Hitable + Send + Sync>> = vec![
         Box::new(light_rect),
-        Box::new(bvh::BvhNode::from_vec(boxlist, time0, time1)),
+        Box::new(bvh::BvhNode::from_vec(boxlist, time0, time1, &mut rng)),
         Box::new(moving_sphere),
         Box::new(glass_sphere),
         Box::new(metal_sphere),
@@ -438,5 +555,103 @@ pub fn tnw_final_scene(aspect_ratio: f32) -> Scene<Vec<Box<dyn Hitable + Send +
         Box::new(perlin_sphere),
         Box::new(sphere_cube),
     ];
-    Scene { camera, hitables }
+    Scene { camera, hitables, lights, environment: None }
+}
+
+pub fn sky_spheres(aspect_ratio: f32) -> Scene<bvh::BvhNode> {
+    let look_from = Vec3::new(13.0, 2.0, 3.0);
+    let look_at = Vec3::new(0.0, 0.5, 0.0);
+    let up_vector = Vec3::new(0.0, 1.0, 0.0);
+    let field_of_view = 30.0;
+    let aperture = 0.0;
+    let focal_distance = 10.0;
+    let time0 = 0.0;
+    let time1 = 1.0;
+    let camera = Camera::new(look_from, look_at,
+                             up_vector, field_of_view, aspect_ratio, aperture, focal_distance,
+                             time0, time1);
+
+    let checker = texture::Checker::new(
+        texture::Constant::from_rgb(0.2, 0.3, 0.1),
+        texture::Constant::from_rgb(0.9, 0.9, 0.9),
+    );
+
+    let hitables: Vec<Box<dyn Hitable + Send + Sync>> = vec![
+        Box::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, Lambertian::new(checker))),
+        Box::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, Dielectric::new_glass())),
+        Box::new(Sphere::new(Vec3::new(-4.0, 1.0, 0.0), 1.0, Lambertian::new(texture::Constant::from_rgb(0.4, 0.2, 0.1)))),
+        Box::new(Sphere::new(Vec3::new(4.0, 1.0, 0.0), 1.0, Metal::new(texture::Constant::from_rgb(0.7, 0.6, 0.5), 0.0))),
+    ];
+    let mut rng = utils::seeded_rng(SCENE_SEED);
+    let hitables = bvh::BvhNode::from_vec(hitables, time0, time1, &mut rng);
+    let environment: Option<Box<dyn Environment>> = Some(Box::new(environment::Gradient::sky()));
+    Scene { camera, hitables, lights: Vec::new(), environment }
+}
+
+/// Boxes and spheres lit by the sun and sky, with the sun at the given elevation (degrees).
+pub fn daylight(aspect_ratio: f32, sun_elevation: f32) -> Scene<bvh::BvhNode> {
+    let look_from = Vec3::new(13.0, 3.0, 6.0);
+    let look_at = Vec3::new(0.0, 1.5, 0.0);
+    let up_vector = Vec3::new(0.0, 1.0, 0.0);
+    let field_of_view = 35.0;
+    let aperture = 0.0;
+    let focal_distance = 10.0;
+    let time0 = 0.0;
+    let time1 = 1.0;
+    let camera = Camera::new(look_from, look_at,
+                             up_vector, field_of_view, aspect_ratio, aperture, focal_distance,
+                             time0, time1);
+
+    let ground = Lambertian::new(texture::Constant::from_rgb(0.5, 0.5, 0.45));
+    let concrete = Lambertian::new(texture::Constant::from_rgb(0.7, 0.7, 0.7));
+
+    let hitables: Vec<Box<dyn Hitable + Send + Sync>> = vec![
+        Box::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground)),
+        Box::new(Cuboid::new(Vec3::new(-3.0, 0.0, -4.0), Vec3::new(-1.0, 4.0, -1.0), concrete.clone())),
+        Box::new(
+            Translate::new(
+                Rotate::new_y(Cuboid::new(Vec3::zeros(), Vec3::new(1.5, 2.5, 1.5), concrete.clone()), 30.0),
+                Vec3::new(-2.0, 0.0, 1.5)
+            )
+        ),
+        Box::new(Sphere::new(Vec3::new(2.0, 1.0, 0.0), 1.0, Dielectric::new_glass())),
+        Box::new(Sphere::new(Vec3::new(1.5, 0.7, 3.0), 0.7, Metal::new(texture::Constant::from_rgb(0.8, 0.8, 0.8), 0.1))),
+    ];
+    let mut rng = utils::seeded_rng(SCENE_SEED);
+    let hitables = bvh::BvhNode::from_vec(hitables, time0, time1, &mut rng);
+    let environment: Option<Box<dyn Environment>> = Some(Box::new(environment::SunSky::new(sun_elevation, 200.0, 3.0)));
+    Scene { camera, hitables, lights: Vec::new(), environment }
+}
+
+
+/// Spheres of dispersive glass and diamond under a small, bright light, whose caustics are split
+/// into colours when rendered spectrally.
+pub fn dispersion(aspect_ratio: f32) -> Scene<bvh::BvhNode> {
+    let look_from = Vec3::new(0.0, 4.0, 9.0);
+    let look_at = Vec3::new(0.0, 0.8, 0.0);
+    let up_vector = Vec3::new(0.0, 1.0, 0.0);
+    let field_of_view = 35.0;
+    let aperture = 0.0;
+    let focal_distance = 10.0;
+    let time0 = 0.0;
+    let time1 = 1.0;
+    let camera = Camera::new(look_from, look_at,
+                             up_vector, field_of_view, aspect_ratio, aperture, focal_distance,
+                             time0, time1);
+
+    let white = Lambertian::new(texture::Constant::from_rgb(0.8, 0.8, 0.8));
+    let light = DiffuseLight::new(texture::Constant::from_rgb(60.0, 60.0, 60.0));
+    let light_sphere = Sphere::new(Vec3::new(-3.0, 6.0, -2.0), 0.5, light);
+
+    let hitables: Vec<Box<dyn Hitable + Send + Sync>> = vec![
+        Box::new(Rectangle::new_xz((-20.0, 20.0), (-20.0, 20.0), 0.0, white.clone())),
+        Box::new(Rectangle::new_xy((-20.0, 20.0), (0.0, 20.0), -4.0, white.clone())),
+        Box::new(Sphere::new(Vec3::new(-1.3, 1.0, 0.0), 1.0, Dielectric::new_flint_glass())),
+        Box::new(Sphere::new(Vec3::new(1.3, 1.0, 0.5), 1.0, Dielectric::new_diamond())),
+        Box::new(light_sphere.clone()),
+    ];
+    let lights: Vec<Box<dyn Hitable + Send + Sync>> = vec![Box::new(light_sphere)];
+    let mut rng = utils::seeded_rng(SCENE_SEED);
+    let hitables = bvh::BvhNode::from_vec(hitables, time0, time1, &mut rng);
+    Scene { camera, hitables, lights, environment: None }
 }
@@ -0,0 +1,221 @@
+//! Spectral rendering, where paths carry radiance at a few sampled wavelengths rather than RGB.
+//! This lets materials vary with wavelength (e.g. dispersion in glass), at the cost of some extra
+//! colour noise. RGB colours of textures and emitters are upsampled to smooth spectra (using
+//! Smits' method), and radiance is converted back to RGB with the CIE colour matching functions.
+
+use std::sync::OnceLock;
+
+use crate::vec3::Vec3;
+
+/// Range of wavelengths sampled, in nanometres.
+pub const MIN_WAVELENGTH: f32 = 380.0;
+pub const MAX_WAVELENGTH: f32 = 720.0;
+
+/// Wavelengths carried by a path, with radiance at each stored in the components of a `Vec3`.
+///
+/// The first (hero) wavelength is sampled uniformly, and the others are spaced evenly from it
+/// across the range, so that each path covers the whole spectrum. Where a path's direction depends
+/// on wavelength, it can only be followed for the hero wavelength, and the others are terminated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Wavelengths {
+    lambda: [f32; 3],
+    secondary_terminated: bool,
+}
+
+impl Wavelengths {
+    /// Wavelengths for a path, from a uniform sample `u` in [0, 1).
+    pub fn sample(u: f32) -> Self {
+        let range = MAX_WAVELENGTH - MIN_WAVELENGTH;
+        let mut lambda = [0.0; 3];
+        for (k, l) in lambda.iter_mut().enumerate() {
+            let offset = (u + k as f32 / 3.0).fract();
+            *l = MIN_WAVELENGTH + offset * range;
+        }
+        Self { lambda, secondary_terminated: false }
+    }
+
+    /// Wavelength the path's direction is chosen for.
+    pub fn hero(&self) -> f32 {
+        self.lambda[0]
+    }
+
+    pub fn get(&self, k: usize) -> f32 {
+        self.lambda[k]
+    }
+
+    /// Spectrum of an RGB colour (reflectance or emission), at each wavelength.
+    pub fn upsample(&self, rgb: &Vec3) -> Vec3 {
+        Vec3::new(rgb_to_spectrum(rgb, self.lambda[0]), rgb_to_spectrum(rgb, self.lambda[1]),
+                  rgb_to_spectrum(rgb, self.lambda[2]))
+    }
+
+    /// Drops all but the hero wavelength from a path's throughput, once its direction has been
+    /// chosen for the hero alone. The hero then stands in for the others, so keeps the estimate
+    /// unbiased.
+    pub fn terminate_secondary(&mut self, throughput: &mut Vec3) {
+        if !self.secondary_terminated {
+            *throughput = Vec3::new(3.0 * throughput[0], 0.0, 0.0);
+            self.secondary_terminated = true;
+        }
+    }
+
+    /// Converts radiance at each wavelength to an estimate of linear RGB radiance.
+    pub fn to_rgb(&self, radiance: &Vec3) -> Vec3 {
+        let norm = rgb_normalisation();
+        let range = MAX_WAVELENGTH - MIN_WAVELENGTH;
+        let mut rgb = Vec3::zeros();
+        for (k, &lambda) in self.lambda.iter().enumerate() {
+            rgb += &(radiance[k] * &xyz_to_rgb(&cie_xyz(lambda)));
+        }
+        // each wavelength has pdf 1 / range
+        for k in 0..3 {
+            rgb[k] *= range / (3.0 * norm[k]);
+        }
+        rgb
+    }
+}
+
+/// CIE 1931 2° colour matching functions at a wavelength (in nm), using the multi-lobe Gaussian
+/// fit of Wyman, Sloan and Shirley (2013).
+pub fn cie_xyz(lambda: f32) -> Vec3 {
+    // Gaussian with different widths either side of its peak
+    let g = |mu: f32, sigma1: f32, sigma2: f32| {
+        let t = (lambda - mu) / if lambda < mu { sigma1 } else { sigma2 };
+        (-0.5 * t * t).exp()
+    };
+    let x = 1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2);
+    let y = 0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1);
+    let z = 1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8);
+    Vec3::new(x, y, z)
+}
+
+// CIE XYZ to linear sRGB
+fn xyz_to_rgb(xyz: &Vec3) -> Vec3 {
+    Vec3::new(
+        3.240_454_2 * xyz[0] - 1.537_138_5 * xyz[1] - 0.498_531_4 * xyz[2],
+        -0.969_266 * xyz[0] + 1.876_010_8 * xyz[1] + 0.041_556 * xyz[2],
+        0.055_643_4 * xyz[0] - 0.204_025_9 * xyz[1] + 1.057_225_2 * xyz[2],
+    )
+}
+
+// integral of the RGB response to each wavelength over the sampled range, by which RGB is divided
+// so that a constant spectrum of 1 (which Smits' method gives for white) converts back to white
+fn rgb_normalisation() -> &'static [f32; 3] {
+    static NORMALISATION: OnceLock<[f32; 3]> = OnceLock::new();
+    NORMALISATION.get_or_init(|| {
+        let steps = 4 * (MAX_WAVELENGTH - MIN_WAVELENGTH) as usize;
+        let step = (MAX_WAVELENGTH - MIN_WAVELENGTH) / steps as f32;
+        let mut total = [0.0; 3];
+        for i in 0..steps {
+            let rgb = xyz_to_rgb(&cie_xyz(MIN_WAVELENGTH + (i as f32 + 0.5) * step));
+            for (k, t) in total.iter_mut().enumerate() {
+                *t += rgb[k] * step;
+            }
+        }
+        total
+    })
+}
+
+// spectra of Smits' method, in 10 equal bins across the wavelength range
+const SMITS_WHITE: [f32; 10] = [1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000];
+const SMITS_CYAN: [f32; 10] = [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000];
+const SMITS_MAGENTA: [f32; 10] = [1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959];
+const SMITS_YELLOW: [f32; 10] = [0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840];
+const SMITS_RED: [f32; 10] = [0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149];
+const SMITS_GREEN: [f32; 10] = [0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025];
+const SMITS_BLUE: [f32; 10] = [1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496];
+
+/// Value at a wavelength (in nm) of a smooth spectrum with the given RGB colour, using Smits'
+/// method: a sum of white and the spectra of two primaries or secondaries, weighted by the
+/// colour's components in order of size.
+pub fn rgb_to_spectrum(rgb: &Vec3, lambda: f32) -> f32 {
+    let s = |spectrum: &[f32; 10]| interpolate(spectrum, lambda);
+    let (r, g, b) = (rgb[0], rgb[1], rgb[2]);
+
+    if r <= g && r <= b {
+        r * s(&SMITS_WHITE) + if g <= b {
+            (g - r) * s(&SMITS_CYAN) + (b - g) * s(&SMITS_BLUE)
+        } else {
+            (b - r) * s(&SMITS_CYAN) + (g - b) * s(&SMITS_GREEN)
+        }
+    } else if g <= r && g <= b {
+        g * s(&SMITS_WHITE) + if r <= b {
+            (r - g) * s(&SMITS_MAGENTA) + (b - r) * s(&SMITS_BLUE)
+        } else {
+            (b - g) * s(&SMITS_MAGENTA) + (r - b) * s(&SMITS_RED)
+        }
+    } else {
+        b * s(&SMITS_WHITE) + if r <= g {
+            (r - b) * s(&SMITS_YELLOW) + (g - r) * s(&SMITS_GREEN)
+        } else {
+            (g - b) * s(&SMITS_YELLOW) + (r - g) * s(&SMITS_RED)
+        }
+    }
+}
+
+// linearly interpolates between the centres of equal bins spanning the wavelength range
+fn interpolate(bins: &[f32; 10], lambda: f32) -> f32 {
+    let x = (lambda - MIN_WAVELENGTH) / (MAX_WAVELENGTH - MIN_WAVELENGTH) * bins.len() as f32 - 0.5;
+    if x <= 0.0 {
+        return bins[0];
+    }
+    let i = x as usize;
+    if i + 1 >= bins.len() {
+        return bins[bins.len() - 1];
+    }
+    let t = x - i as f32;
+    (1.0 - t) * bins[i] + t * bins[i + 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RGB of a colour's spectrum, averaged over evenly spaced wavelength samples
+    fn round_trip(rgb: &Vec3) -> Vec3 {
+        let n = 200;
+        let mut total = Vec3::zeros();
+        for i in 0..n {
+            let wavelengths = Wavelengths::sample((i as f32 + 0.5) / n as f32);
+            total += &wavelengths.to_rgb(&wavelengths.upsample(rgb));
+        }
+        total / n as f32
+    }
+
+    #[test]
+    fn test_wavelengths() {
+        let wavelengths = Wavelengths::sample(0.9);
+        assert_eq!(wavelengths.hero(), MIN_WAVELENGTH + 0.9 * (MAX_WAVELENGTH - MIN_WAVELENGTH));
+        for k in 0..3 {
+            assert!(wavelengths.get(k) >= MIN_WAVELENGTH && wavelengths.get(k) < MAX_WAVELENGTH);
+        }
+
+        let mut wavelengths = wavelengths;
+        let mut throughput = Vec3::new(0.5, 0.25, 0.125);
+        wavelengths.terminate_secondary(&mut throughput);
+        wavelengths.terminate_secondary(&mut throughput);
+        assert_eq!(throughput, Vec3::new(1.5, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_cie_xyz() {
+        // peaks of the colour matching functions
+        assert!((cie_xyz(555.0)[1] - 1.0).abs() < 0.02);
+        assert!((cie_xyz(600.0)[0] - 1.06).abs() < 0.02);
+        assert!((cie_xyz(445.0)[2] - 1.78).abs() < 0.03);
+    }
+
+    #[test]
+    fn test_rgb_round_trip() {
+        let white = round_trip(&Vec3::ones());
+        for k in 0..3 {
+            assert!((white[k] - 1.0).abs() < 1e-3, "white became {:?}", white);
+        }
+
+        for rgb in &[Vec3::new(0.8, 0.2, 0.1), Vec3::new(0.12, 0.45, 0.15), Vec3::new(0.1, 0.2, 0.6)] {
+            let result = round_trip(rgb);
+            let error = (&result - rgb).length() / rgb.length();
+            assert!(error < 0.05, "{:?} became {:?}", rgb, result);
+        }
+    }
+}
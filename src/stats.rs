@@ -0,0 +1,217 @@
+//! Statistics on the work done while rendering, for profiling. Each thread counts into its own
+//! counters, which is cheap enough to leave on all the time, and `Stats::collect` adds up the
+//! counts from every thread.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::bvh::Traversal;
+
+thread_local! {
+    static LOCAL: RefCell<Stats> = const { RefCell::new(Stats::new()) };
+}
+
+// counts flushed from threads' own counters
+static TOTAL: Mutex<Stats> = Mutex::new(Stats::new());
+
+/// Counts of the work done while rendering.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stats {
+    /// Rays traced from the camera.
+    pub camera_rays: u64,
+    /// Rays intersected with the scene, including camera rays, rays continuing paths, shadow rays
+    /// and photons.
+    pub rays: u64,
+    /// Vertices of paths traced from the camera, i.e. surfaces (or points in media) reached.
+    pub path_vertices: u64,
+    /// Bounding volume hierarchy nodes visited and primitives tested by all rays.
+    pub traversal: Traversal,
+    /// Calls to `Material::scatter`, by the name of the type of material. A type may appear more
+    /// than once, see `scatters_by_material` for the totals.
+    pub scatters: Vec<(&'static str, u64)>,
+    /// Time spent building bounding volume hierarchies.
+    pub bvh_build_time: Duration,
+    /// Time spent rendering images, including anything computed for the scene beforehand (e.g.
+    /// photon maps).
+    pub render_time: Duration,
+}
+
+impl Stats {
+    pub const fn new() -> Self {
+        Self {
+            camera_rays: 0,
+            rays: 0,
+            path_vertices: 0,
+            traversal: Traversal { nodes: 0, primitives: 0 },
+            scatters: Vec::new(),
+            bvh_build_time: Duration::ZERO,
+            render_time: Duration::ZERO,
+        }
+    }
+
+    /// Everything counted since the last collection, on this thread and rayon's global thread
+    /// pool.
+    pub fn collect() -> Self {
+        rayon::broadcast(|_| flush());
+        flush();
+        std::mem::take(&mut *TOTAL.lock().unwrap())
+    }
+
+    /// Adds the counts from `other`.
+    pub fn merge(&mut self, other: &Stats) {
+        self.camera_rays += other.camera_rays;
+        self.rays += other.rays;
+        self.path_vertices += other.path_vertices;
+        self.traversal.nodes += other.traversal.nodes;
+        self.traversal.primitives += other.traversal.primitives;
+        for &(name, count) in &other.scatters {
+            add_scatters(&mut self.scatters, name, count);
+        }
+        self.bvh_build_time += other.bvh_build_time;
+        self.render_time += other.render_time;
+    }
+
+    /// Rays traced per second of rendering.
+    pub fn rays_per_second(&self) -> f64 {
+        let secs = self.render_time.as_secs_f64();
+        if secs > 0.0 {
+            self.rays as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// Average number of vertices of paths traced from the camera.
+    pub fn average_path_length(&self) -> f64 {
+        if self.camera_rays > 0 {
+            self.path_vertices as f64 / self.camera_rays as f64
+        } else {
+            0.0
+        }
+    }
+
+    /// Scatter counts by material, with material types given by their short names (e.g.
+    /// `Lambertian`), and sorted by name.
+    pub fn scatters_by_material(&self) -> Vec<(String, u64)> {
+        let mut scatters: Vec<(String, u64)> = Vec::new();
+        for &(name, count) in &self.scatters {
+            let name = short_type_name(name);
+            match scatters.iter_mut().find(|(n, _)| n == name) {
+                Some((_, total)) => *total += count,
+                None => scatters.push((name.to_owned(), count)),
+            }
+        }
+        scatters.sort();
+        scatters
+    }
+
+    /// Writes the statistics as a JSON object, with times in seconds.
+    pub fn write_json<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "{{")?;
+        writeln!(writer, "  \"camera_rays\": {},", self.camera_rays)?;
+        writeln!(writer, "  \"rays\": {},", self.rays)?;
+        writeln!(writer, "  \"rays_per_second\": {},", self.rays_per_second())?;
+        writeln!(writer, "  \"average_path_length\": {},", self.average_path_length())?;
+        writeln!(writer, "  \"bvh_nodes_visited\": {},", self.traversal.nodes)?;
+        writeln!(writer, "  \"primitive_tests\": {},", self.traversal.primitives)?;
+        let scatters: Vec<String> = self.scatters_by_material().iter()
+            .map(|(name, count)| format!("\"{}\": {}", name, count))
+            .collect();
+        writeln!(writer, "  \"scatters\": {{{}}},", scatters.join(", "))?;
+        writeln!(writer, "  \"bvh_build_seconds\": {},", self.bvh_build_time.as_secs_f64())?;
+        writeln!(writer, "  \"render_seconds\": {}", self.render_time.as_secs_f64())?;
+        writeln!(writer, "}}")
+    }
+
+    pub fn save_json<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_json(&mut writer)?;
+        writer.flush()
+    }
+}
+
+/// Table of the statistics, one per line.
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:<24}{:>16}", "camera rays", self.camera_rays)?;
+        writeln!(f, "{:<24}{:>16}", "rays", self.rays)?;
+        writeln!(f, "{:<24}{:>16.0}", "rays/sec", self.rays_per_second())?;
+        writeln!(f, "{:<24}{:>16.2}", "average path length", self.average_path_length())?;
+        writeln!(f, "{:<24}{:>16}", "BVH nodes visited", self.traversal.nodes)?;
+        writeln!(f, "{:<24}{:>16}", "primitive tests", self.traversal.primitives)?;
+        for (name, count) in self.scatters_by_material() {
+            writeln!(f, "{:<24}{:>16}", format!("scatters ({})", name), count)?;
+        }
+        writeln!(f, "{:<24}{:>15.3}s", "BVH build time", self.bvh_build_time.as_secs_f64())?;
+        write!(f, "{:<24}{:>15.3}s", "render time", self.render_time.as_secs_f64())
+    }
+}
+
+/// Updates this thread's counters.
+pub(crate) fn count<F: FnOnce(&mut Stats)>(f: F) {
+    LOCAL.with(|stats| f(&mut stats.borrow_mut()));
+}
+
+/// Counts a call to `Material::scatter`, for a material of the type named `name`.
+pub(crate) fn count_scatter(name: &'static str) {
+    count(|stats| add_scatters(&mut stats.scatters, name, 1));
+}
+
+// BVH nodes visited and primitives tested on this thread since its counters were last flushed
+pub(crate) fn traversal() -> Traversal {
+    LOCAL.with(|stats| stats.borrow().traversal)
+}
+
+// adds this thread's counters to the totals, and resets them
+fn flush() {
+    let local = LOCAL.with(|stats| std::mem::take(&mut *stats.borrow_mut()));
+    TOTAL.lock().unwrap().merge(&local);
+}
+
+// names from `type_name` are compared by address, as comparing their contents on every scatter
+// would be slow. A name may have more than one address, which only means it's counted under each,
+// until `scatters_by_material` merges them.
+fn add_scatters(scatters: &mut Vec<(&'static str, u64)>, name: &'static str, count: u64) {
+    match scatters.iter_mut().find(|(n, _)| std::ptr::eq(n.as_ptr(), name.as_ptr()) && n.len() == name.len()) {
+        Some((_, total)) => *total += count,
+        None => scatters.push((name, count)),
+    }
+}
+
+// name of a type without its module path or type parameters, e.g. `Lambertian` for
+// `rtracer::material::lambertian::Lambertian<rtracer::texture::Constant>`
+fn short_type_name(name: &str) -> &str {
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge() {
+        let mut stats = Stats::new();
+        stats.rays = 3;
+        add_scatters(&mut stats.scatters, "rtracer::material::lambertian::Lambertian<rtracer::texture::Constant>", 1);
+        let mut other = Stats::new();
+        other.rays = 2;
+        let metal: &'static str = "rtracer::material::metal::Metal";
+        add_scatters(&mut other.scatters, metal, 1);
+        add_scatters(&mut other.scatters, metal, 1);
+        add_scatters(&mut other.scatters, "rtracer::material::lambertian::Lambertian<rtracer::texture::Checker>", 1);
+        assert_eq!(other.scatters.len(), 2);
+        stats.merge(&other);
+
+        assert_eq!(stats.rays, 5);
+        assert_eq!(stats.scatters_by_material(), vec![("Lambertian".to_owned(), 2), ("Metal".to_owned(), 2)]);
+        let mut json = Vec::new();
+        stats.write_json(&mut json).unwrap();
+        assert!(String::from_utf8(json).unwrap().contains("\"scatters\": {\"Lambertian\": 2, \"Metal\": 2},"));
+    }
+}
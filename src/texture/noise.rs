@@ -1,3 +1,4 @@
+use rand::RngCore;
 use crate::vec3::Vec3;
 use crate::texture::Texture;
 use crate::texture::perlin::Perlin;
@@ -10,8 +11,8 @@ pub struct Noise {
 }
 
 impl Noise {
-    pub fn new(scale: f32) -> Self {
-        Self { scale, perlin: Perlin::new() }
+    pub fn new(scale: f32, rng: &mut dyn RngCore) -> Self {
+        Self { scale, perlin: Perlin::new(rng) }
     }
 
     fn turbulence(&self, point: &Vec3, depth: u32) -> f32 {
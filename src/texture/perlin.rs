@@ -12,12 +12,12 @@ pub struct Perlin {
 }
 
 impl Perlin {
-    pub fn new() -> Self {
+    pub fn new(rng: &mut dyn RngCore) -> Self {
         Perlin {
-            ranvec: Perlin::generate(),
-            perm_x: Perlin::generate_perm(),
-            perm_y: Perlin::generate_perm(),
-            perm_z: Perlin::generate_perm(),
+            ranvec: Perlin::generate(rng),
+            perm_x: Perlin::generate_perm(rng),
+            perm_y: Perlin::generate_perm(rng),
+            perm_z: Perlin::generate_perm(rng),
         }
     }
 
@@ -47,8 +47,7 @@ impl Perlin {
         Perlin::trilinear_interpolation(&c, u, v, w)
     }
 
-    fn generate() -> Vec<Vec3> {
-        let mut rng = rand::thread_rng();
+    fn generate(rng: &mut dyn RngCore) -> Vec<Vec3> {
         let mut perlin = Vec::with_capacity(SIZE);
         for _ in 0..SIZE {
             // use random unit vectors (instead of just floats) on lattice points, use dot product
@@ -61,17 +60,16 @@ impl Perlin {
         perlin
     }
 
-    fn generate_perm() -> [usize; SIZE] {
+    fn generate_perm(rng: &mut dyn RngCore) -> [usize; SIZE] {
         let mut perm = [0; SIZE];
-        for i in 0..SIZE {
-            perm[i] = i as usize;
+        for (i, p) in perm.iter_mut().enumerate() {
+            *p = i;
         }
-        Perlin::permute(&mut perm);
+        Perlin::permute(&mut perm, rng);
         perm
     }
 
-    fn permute(perm: &mut [usize; SIZE]) {
-        let mut rng = rand::thread_rng();
+    fn permute(perm: &mut [usize; SIZE], rng: &mut dyn RngCore) {
         for i in (0..perm.len()).rev() {
             let target = (rng.gen::<f32>() * (i as f32 + 1.0)) as usize;
             perm.swap(i, target);
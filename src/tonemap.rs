@@ -0,0 +1,234 @@
+//! Converting linear radiance to 8-bit display colours. Radiance is first scaled by the exposure,
+//! then compressed into [0, 1] by a tone mapping operator, and finally encoded with the sRGB
+//! transfer function.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::vec3::Vec3;
+
+/// Tone mapping operators, which map (exposure adjusted) radiance to display values in [0, 1].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ToneMapper {
+    /// Clamps each channel to 1, so anything brighter is blown out.
+    #[default]
+    Clamp,
+    /// Reinhard's L / (1 + L) applied to luminance, which compresses highlights but never reaches
+    /// white.
+    Reinhard,
+    /// Reinhard, with luminance at the white point mapped to white.
+    ExtendedReinhard,
+    /// Narkowicz's fit of the ACES filmic curve.
+    Aces,
+    /// John Hable's filmic curve from Uncharted 2.
+    Hable,
+    /// Troy Sobotka's AgX (using Benjamin Wrensch's polynomial fit), which desaturates bright
+    /// colours towards white rather than skewing their hue.
+    Agx,
+}
+
+impl ToneMapper {
+    pub const NAMES: &'static [&'static str] = &["clamp", "reinhard", "extended-reinhard", "aces", "hable", "agx"];
+
+    /// Luminance mapped to white by operators which have a white point, when none is given.
+    pub fn default_white_point(self) -> f32 {
+        match self {
+            ToneMapper::Hable => 5.6,
+            _ => 4.0,
+        }
+    }
+}
+
+impl FromStr for ToneMapper {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clamp" => Ok(ToneMapper::Clamp),
+            "reinhard" => Ok(ToneMapper::Reinhard),
+            "extended-reinhard" => Ok(ToneMapper::ExtendedReinhard),
+            "aces" => Ok(ToneMapper::Aces),
+            "hable" => Ok(ToneMapper::Hable),
+            "agx" => Ok(ToneMapper::Agx),
+            _ => Err(format!("unknown tone mapper: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for ToneMapper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ToneMapper::Clamp => "clamp",
+            ToneMapper::Reinhard => "reinhard",
+            ToneMapper::ExtendedReinhard => "extended-reinhard",
+            ToneMapper::Aces => "aces",
+            ToneMapper::Hable => "hable",
+            ToneMapper::Agx => "agx",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Settings for converting radiance to display colours.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ToneMapping {
+    mapper: ToneMapper,
+    exposure: f32,
+    white_point: f32,
+}
+
+impl ToneMapping {
+    pub fn new(mapper: ToneMapper) -> Self {
+        Self { mapper, exposure: 0.0, white_point: mapper.default_white_point() }
+    }
+
+    /// Set exposure adjustment in stops, i.e. radiance is scaled by 2^exposure.
+    pub fn with_exposure(mut self, exposure: f32) -> Self {
+        self.exposure = exposure;
+        self
+    }
+
+    /// Set (exposure adjusted) luminance mapped to white, used by extended Reinhard and Hable.
+    pub fn with_white_point(mut self, white_point: f32) -> Self {
+        self.white_point = white_point;
+        self
+    }
+
+    /// Maps linear radiance to linear display values in [0, 1].
+    pub fn map(&self, col: &Vec3) -> Vec3 {
+        let col = self.exposure.exp2() * col;
+        let mapped = match self.mapper {
+            ToneMapper::Clamp => col,
+            ToneMapper::Reinhard => scale_luminance(&col, |l| l / (1.0 + l)),
+            ToneMapper::ExtendedReinhard => {
+                let white2 = self.white_point * self.white_point;
+                scale_luminance(&col, |l| l * (1.0 + l / white2) / (1.0 + l))
+            },
+            ToneMapper::Aces => map_channels(&col, aces),
+            ToneMapper::Hable => {
+                // Hable's curve is designed for an exposure bias of 2
+                let white_scale = 1.0 / hable(2.0 * self.white_point);
+                map_channels(&col, |x| hable(2.0 * x) * white_scale)
+            },
+            ToneMapper::Agx => agx(&col),
+        };
+        map_channels(&mapped, |x| if x > 0.0 { x.min(1.0) } else { 0.0 })
+    }
+
+    /// Maps linear radiance to 8-bit sRGB.
+    pub fn to_colour(&self, col: &Vec3) -> [u8; 3] {
+        let mapped = self.map(col);
+        let mut rgb = [0; 3];
+        for (k, c) in rgb.iter_mut().enumerate() {
+            *c = (255.0 * srgb_encode(mapped[k]) + 0.5) as u8;
+        }
+        rgb
+    }
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        Self::new(ToneMapper::default())
+    }
+}
+
+/// sRGB transfer function, from linear values in [0, 1] to display encoded values.
+pub fn srgb_encode(x: f32) -> f32 {
+    if x <= 0.003_130_8 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn map_channels<F: Fn(f32) -> f32>(col: &Vec3, f: F) -> Vec3 {
+    Vec3::new(f(col[0]), f(col[1]), f(col[2]))
+}
+
+// scales a colour so that its luminance becomes f(luminance)
+fn scale_luminance<F: Fn(f32) -> f32>(col: &Vec3, f: F) -> Vec3 {
+    let lum = col.luminance();
+    if lum <= 0.0 {
+        return Vec3::zeros();
+    }
+    f(lum) / lum * col
+}
+
+fn aces(x: f32) -> f32 {
+    // scaled to match the exposure of the reference ACES transform
+    let x = 0.6 * x;
+    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+}
+
+fn hable(x: f32) -> f32 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+}
+
+fn agx(col: &Vec3) -> Vec3 {
+    const MIN_EV: f32 = -12.473_931;
+    const MAX_EV: f32 = 4.026_069;
+
+    // into AgX's working space, where the curve is applied in log2 space to each channel
+    let inset = Vec3::new(
+        0.842_479_06 * col[0] + 0.078_433_6 * col[1] + 0.079_223_745 * col[2],
+        0.042_328_242 * col[0] + 0.878_468_6 * col[1] + 0.079_166_13 * col[2],
+        0.042_375_655 * col[0] + 0.078_433_6 * col[1] + 0.879_143 * col[2],
+    );
+    let curved = map_channels(&inset, |x| {
+        let ev = x.max(1e-10).log2().clamp(MIN_EV, MAX_EV);
+        let x = (ev - MIN_EV) / (MAX_EV - MIN_EV);
+        let (x2, x4) = (x * x, x * x * x * x);
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.002_32
+    });
+    let outset = Vec3::new(
+        1.196_879 * curved[0] - 0.098_020_88 * curved[1] - 0.099_029_74 * curved[2],
+        -0.052_896_852 * curved[0] + 1.151_903_1 * curved[1] - 0.098_961_18 * curved[2],
+        -0.052_971_635 * curved[0] - 0.098_043_45 * curved[1] + 1.151_073_7 * curved[2],
+    );
+    // the curve gives display encoded values, so undo its (2.2) gamma to get back to linear
+    map_channels(&outset, |x| x.max(0.0).powf(2.2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_srgb_encode() {
+        assert_eq!(srgb_encode(0.0), 0.0);
+        assert!((srgb_encode(1.0) - 1.0).abs() < 1e-6);
+        assert!((srgb_encode(0.214_041) - 0.5).abs() < 1e-4);
+        // the linear and power segments meet
+        assert!((srgb_encode(0.003_130_8) - srgb_encode(0.003_130_9)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_tone_mappers_are_monotonic() {
+        for name in ToneMapper::NAMES {
+            let mapper: ToneMapper = name.parse().unwrap();
+            assert_eq!(&mapper.to_string(), name);
+
+            let tone_mapping = ToneMapping::new(mapper);
+            assert!(tone_mapping.map(&Vec3::zeros())[1] < 0.01, "{} doesn't map black to black", name);
+            let mut prev = 0.0;
+            for i in 1..100 {
+                let v = tone_mapping.map(&(0.1 * i as f32 * Vec3::ones()))[1];
+                assert!(v >= prev && v <= 1.0, "{} isn't monotonic at {}", name, 0.1 * i as f32);
+                prev = v;
+            }
+        }
+    }
+
+    #[test]
+    fn test_exposure_and_white_point() {
+        let clamp = ToneMapping::new(ToneMapper::Clamp).with_exposure(1.0);
+        assert_eq!(clamp.map(&Vec3::new(0.25, 0.5, 2.0)), Vec3::new(0.5, 1.0, 1.0));
+        assert_eq!(clamp.to_colour(&Vec3::new(0.0, 0.5, 1.0)), [0, 255, 255]);
+
+        let reinhard = ToneMapping::new(ToneMapper::ExtendedReinhard).with_white_point(8.0);
+        assert!((reinhard.map(&Vec3::new(8.0, 8.0, 8.0))[0] - 1.0).abs() < 1e-5);
+        let hable = ToneMapping::new(ToneMapper::Hable);
+        assert!((hable.map(&Vec3::new(5.6, 5.6, 5.6))[0] - 1.0).abs() < 1e-5);
+    }
+}
@@ -1,25 +1,65 @@
-use rand::prelude::*;
+use rand::SeedableRng;
+use rand_pcg::Pcg32;
 use crate::vec3::Vec3;
 
-pub fn random_in_unit_sphere() -> Vec3 {
-    // get random point from unit cube -1 to +1, reject if outside sphere
-    let mut rng = thread_rng();
-    loop {
-        let p = 2.0 * Vec3::new(rng.gen_range(0.0, 1.0), rng.gen_range(0.0, 1.0), rng.gen_range(0.0, 1.0)) - 1.0;
-        if p.squared_length() < 1.0 {
-            return p;
-        }
-    }
+/// Small, fast random number generator, seeded deterministically.
+pub fn seeded_rng(seed: u64) -> Pcg32 {
+    Pcg32::seed_from_u64(seed)
+}
+
+// combines two values into a well mixed 64 bit seed (using the splitmix64 finaliser), so that
+// nearby inputs give unrelated random sequences
+pub fn mix_seed(a: u64, b: u64) -> u64 {
+    let mut z = a ^ b.wrapping_add(0x9e37_79b9_7f4a_7c15).wrapping_add(a << 6).wrapping_add(a >> 2);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// uniformly distributed direction, i.e. point on the surface of the unit sphere
+pub fn uniform_sphere(u: (f32, f32)) -> Vec3 {
+    let z = 1.0 - 2.0 * u.0;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * std::f32::consts::PI * u.1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+// uniformly distributed point within the unit sphere
+pub fn uniform_in_sphere(u: (f32, f32), radius_sample: f32) -> Vec3 {
+    radius_sample.cbrt() * uniform_sphere(u)
+}
+
+// direction within a cone around the z axis, uniformly distributed over its solid angle
+pub fn uniform_cone(u: (f32, f32), cos_theta_max: f32) -> Vec3 {
+    let z = 1.0 + u.1 * (cos_theta_max - 1.0);
+    let phi = 2.0 * std::f32::consts::PI * u.0;
+    let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+    Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z)
+}
+
+// direction in the hemisphere around the z axis, with pdf proportional to its cosine
+pub fn cosine_hemisphere(u: (f32, f32)) -> Vec3 {
+    let r = u.0.sqrt();
+    let phi = 2.0 * std::f32::consts::PI * u.1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), (1.0 - u.0).max(0.0).sqrt())
 }
 
-pub fn random_in_unit_disc() -> Vec3 {
-    let mut rng = thread_rng();
-    loop {
-        let p = 2.0 * Vec3::new(rng.gen_range(0.0, 1.0), rng.gen_range(0.0, 1.0), 0.0) - Vec3::new(1.0, 1.0, 0.0);
-        if p.dot(&p) < 1.0 {
-            return p;
-        }
+// uniformly distributed point within the unit disc (in the xy plane), using Shirley and Chiu's
+// concentric mapping, which keeps stratified samples well distributed
+pub fn concentric_disc(u: (f32, f32)) -> Vec3 {
+    let a = 2.0 * u.0 - 1.0;
+    let b = 2.0 * u.1 - 1.0;
+    if a == 0.0 && b == 0.0 {
+        return Vec3::zeros();
     }
+
+    let quarter_pi = std::f32::consts::PI / 4.0;
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, quarter_pi * (b / a))
+    } else {
+        (b, 2.0 * quarter_pi - quarter_pi * (a / b))
+    };
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
 }
 
 
@@ -45,3 +85,28 @@ pub fn schlick(cosine: f32, reflective_index: f32) -> f32 {
     let r0 = ((1.0 - reflective_index) / (1.0 + reflective_index)).powi(2);
     r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
 }
+
+/// Orthonormal basis, used to convert directions sampled around the z axis to world space.
+pub struct Onb {
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Onb {
+    pub fn from_w(n: &Vec3) -> Self {
+        let w = n.to_unit_vector();
+        let a = if w[0].abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(&a).to_unit_vector();
+        let u = w.cross(&v);
+        Self { u, v, w }
+    }
+
+    pub fn local(&self, a: &Vec3) -> Vec3 {
+        a[0] * &self.u + a[1] * &self.v + a[2] * &self.w
+    }
+}
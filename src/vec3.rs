@@ -40,6 +40,11 @@ impl Vec3 {
                   -(self.e[0] * other.e[2] - self.e[2] * other.e[0]),
                   self.e[0] * other.e[1] - self.e[1] * other.e[0])
     }
+
+    /// Relative luminance, treating components as linear sRGB.
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.e[0] + 0.7152 * self.e[1] + 0.0722 * self.e[2]
+    }
 }
 
 
@@ -208,6 +213,30 @@ impl SubAssign for Vec3 {
     }
 }
 
+impl Mul<Vec3> for &Vec3 {
+    type Output = Vec3;
+
+    fn mul(self, other: Vec3) -> Vec3 {
+        Vec3 {
+            e: [self.e[0] * other.e[0],
+                self.e[1] * other.e[1],
+                self.e[2] * other.e[2]]
+        }
+    }
+}
+
+impl Mul<&Vec3> for Vec3 {
+    type Output = Vec3;
+
+    fn mul(self, other: &Vec3) -> Vec3 {
+        Vec3 {
+            e: [self.e[0] * other.e[0],
+                self.e[1] * other.e[1],
+                self.e[2] * other.e[2]]
+        }
+    }
+}
+
 impl Mul for Vec3 {
     type Output = Self;
 
@@ -386,9 +415,9 @@ mod tests {
     fn test_vec3_basic_ops() {
         let v1 = Vec3::new(1.0, 2.0, 4.0);
         let v2 = Vec3::new(4.0, 2.0, 1.0);
-        assert_eq!(v1 + v2, Vec3::new(5.0, 4.0, 5.0));
-        assert_eq!(v1 - v2, Vec3::new(-3.0, 0.0, 3.0));
-        assert_eq!(v1 * v2, Vec3::new(4.0, 4.0, 4.0));
+        assert_eq!(v1.clone() + v2.clone(), Vec3::new(5.0, 4.0, 5.0));
+        assert_eq!(v1.clone() - v2.clone(), Vec3::new(-3.0, 0.0, 3.0));
+        assert_eq!(v1.clone() * v2.clone(), Vec3::new(4.0, 4.0, 4.0));
         assert_eq!(v1 / v2, Vec3::new(0.25, 1.0, 4.0));
     }
 
@@ -397,13 +426,13 @@ mod tests {
         let mut v1 = Vec3::new(0.5, -0.5, 1.0);
         let v2 = Vec3::new(1.0, -2.0, 3.0);
 
-        v1 += v2;
+        v1 += v2.clone();
         assert_eq!(v1, Vec3::new(1.5, -2.5, 4.0));
 
-        v1 -= v2;
+        v1 -= v2.clone();
         assert_eq!(v1, Vec3::new(0.5, -0.5, 1.0));
 
-        v1 *= v2;
+        v1 *= v2.clone();
         assert_eq!(v1, Vec3::new(0.5, 1.0, 3.0));
 
         v1 /= v2;
@@ -413,9 +442,9 @@ mod tests {
     #[test]
     fn test_vec3_scalar_ops() {
         let v1 = Vec3::new(1.0, -2.0, 4.0);
-        assert_eq!(v1 + 1.5, Vec3::new(2.5, -0.5, 5.5));
-        assert_eq!(v1 - 0.5, Vec3::new(0.5, -2.5, 3.5));
-        assert_eq!(v1 * -1.5, Vec3::new(-1.5, 3.0, -6.0));
+        assert_eq!(v1.clone() + 1.5, Vec3::new(2.5, -0.5, 5.5));
+        assert_eq!(v1.clone() - 0.5, Vec3::new(0.5, -2.5, 3.5));
+        assert_eq!(v1.clone() * -1.5, Vec3::new(-1.5, 3.0, -6.0));
         assert_eq!(v1 / 2.0, Vec3::new(0.5, -1.0, 2.0));
     }
 